@@ -0,0 +1,206 @@
+//! Throughput of the hot todo paths against a real database.
+//!
+//! Runs against `DATABASE_URL` and leaves the todos it creates behind, so
+//! point it at a scratch database: `cargo bench --bench todos`.
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    routing::{get, patch, post},
+    Router,
+};
+use axum_todo::config::Config;
+use axum_todo::db::create_pool;
+use axum_todo::events::{BroadcastEventBus, EventBus};
+use axum_todo::handlers;
+use axum_todo::metrics::Metrics;
+use axum_todo::models::{CountMode, CreateTodo, TodoQuery, UpdateTodo};
+use axum_todo::repository::{PostgresTodoRepository, TodoRepository};
+use axum_todo::retention::Retention;
+use axum_todo::service::TodoService;
+use axum_todo::state::AppState;
+use axum_todo::usage::UsageTracker;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+/// Open titles are unique, so every created todo gets a fresh one
+fn unique_title() -> String {
+    format!("bench {}", Uuid::new_v4())
+}
+
+fn setup(rt: &Runtime) -> (Arc<dyn TodoRepository>, Arc<TodoService>, Config) {
+    dotenvy::dotenv().ok();
+    let config = Config::from_env().expect("Invalid configuration");
+    let pool = rt
+        .block_on(create_pool(&config))
+        .expect("Failed to create database pool");
+    let repo: Arc<dyn TodoRepository> = Arc::new(PostgresTodoRepository::new(pool));
+    let events: Arc<dyn EventBus> = Arc::new(BroadcastEventBus::default());
+    let service = Arc::new(TodoService::new(repo.clone(), events));
+    (repo, service, config)
+}
+
+fn repository(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let (repo, _, _) = setup(&rt);
+    let target = rt
+        .block_on(repo.create(
+            Uuid::new_v4(),
+            CreateTodo {
+                title: unique_title(),
+                ..Default::default()
+            },
+            false,
+        ))
+        .unwrap();
+
+    let mut group = c.benchmark_group("repository");
+    group.bench_function("create", |b| {
+        b.to_async(&rt).iter(|| {
+            let payload = CreateTodo {
+                title: unique_title(),
+                tags: vec!["bench".into()],
+                ..Default::default()
+            };
+            let repo = repo.clone();
+            async move { repo.create(Uuid::new_v4(), payload, false).await.unwrap() }
+        })
+    });
+    group.bench_function("list first page", |b| {
+        b.to_async(&rt)
+            .iter(|| async { repo.list(&TodoQuery::default(), 21, 0).await.unwrap() })
+    });
+    group.bench_function("list deep page", |b| {
+        b.to_async(&rt)
+            .iter(|| async { repo.list(&TodoQuery::default(), 21, 10_000).await.unwrap() })
+    });
+    group.bench_function("list by tag", |b| {
+        let query = TodoQuery {
+            tag: Some("bench".into()),
+            ..Default::default()
+        };
+        b.to_async(&rt)
+            .iter(|| async { repo.list(&query, 21, 0).await.unwrap() })
+    });
+    group.bench_function("count", |b| {
+        b.to_async(&rt)
+            .iter(|| async { repo.count(&TodoQuery::default()).await.unwrap() })
+    });
+    group.bench_function("update", |b| {
+        b.to_async(&rt).iter(|| {
+            let payload = UpdateTodo {
+                description: Some(Uuid::new_v4().to_string()),
+                ..Default::default()
+            };
+            let repo = repo.clone();
+            async move { repo.update(target.id, payload, None, false).await.unwrap() }
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("service");
+    let (_, service, _) = setup(&rt);
+    group.bench_function("list with exact count", |b| {
+        b.to_async(&rt).iter(|| async {
+            service
+                .list(TodoQuery::default(), None, None, CountMode::Exact)
+                .await
+                .unwrap()
+        })
+    });
+    group.bench_function("list with estimated count", |b| {
+        b.to_async(&rt).iter(|| async {
+            service
+                .list(TodoQuery::default(), None, None, CountMode::Estimated)
+                .await
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// The same operations through routing, extractors and JSON encoding
+fn handlers(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let (_, service, config) = setup(&rt);
+    let target = rt
+        .block_on(service.create(
+            CreateTodo {
+                title: unique_title(),
+                ..Default::default()
+            },
+            false,
+        ))
+        .unwrap();
+    let app = Router::new()
+        .route("/todos", post(handlers::create_todo))
+        .route("/todos", get(handlers::list_todos))
+        .route("/todos/{id}", patch(handlers::update_todo))
+        .with_state(AppState {
+            service,
+            config: Arc::new(config),
+            metrics: Arc::new(Metrics::new()),
+            usage: Arc::new(UsageTracker::new()),
+            retention: Arc::new(Retention::new(0, 0, 1)),
+            rate_limiter: None,
+            shadow: None,
+        });
+
+    let send = |method: Method, uri: String, body: Option<String>, expected: StatusCode| {
+        let app = app.clone();
+        async move {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body.map(Body::from).unwrap_or_else(Body::empty))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected);
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+        }
+    };
+
+    let mut group = c.benchmark_group("handlers");
+    group.bench_function("POST /todos", |b| {
+        b.to_async(&rt).iter(|| {
+            let body = serde_json::json!({ "title": unique_title() }).to_string();
+            send(
+                Method::POST,
+                "/todos".into(),
+                Some(body),
+                StatusCode::CREATED,
+            )
+        })
+    });
+    group.bench_function("GET /todos?per_page=20", |b| {
+        b.to_async(&rt).iter(|| {
+            send(
+                Method::GET,
+                "/todos?per_page=20".into(),
+                None,
+                StatusCode::OK,
+            )
+        })
+    });
+    group.bench_function("PATCH /todos/{id}", |b| {
+        b.to_async(&rt).iter(|| {
+            let body = serde_json::json!({ "description": Uuid::new_v4() }).to_string();
+            send(
+                Method::PATCH,
+                format!("/todos/{}", target.id),
+                Some(body),
+                StatusCode::OK,
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, repository, handlers);
+criterion_main!(benches);
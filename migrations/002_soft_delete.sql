@@ -0,0 +1,6 @@
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+-- The unique index on open titles is opt-in (UNIQUE_OPEN_TITLES) and is built
+-- on startup rather than here, so existing duplicates are never touched.
+
+CREATE INDEX IF NOT EXISTS idx_todos_deleted_at ON todos(deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,2 @@
+-- Todos merged into another todo are soft-deleted and point at the survivor
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS merged_into UUID REFERENCES todos(id);
@@ -0,0 +1,5 @@
+-- Covering index over live, tagged todos so the tag usage aggregate
+-- (GET /tags) can be answered with an index-only scan
+CREATE INDEX IF NOT EXISTS idx_todos_live_tags
+    ON todos (id) INCLUDE (tags)
+    WHERE deleted_at IS NULL AND tags <> '{}';
@@ -0,0 +1,9 @@
+-- Record when a todo was completed (cleared again if it is reopened)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS completed_at TIMESTAMPTZ;
+
+-- Best available approximation for todos completed before this migration
+UPDATE todos SET completed_at = updated_at WHERE completed;
+
+-- Recently completed todos, newest first (feeds, reports)
+CREATE INDEX IF NOT EXISTS idx_todos_completed_at ON todos (completed_at DESC)
+    WHERE completed AND deleted_at IS NULL;
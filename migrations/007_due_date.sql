@@ -0,0 +1,6 @@
+-- Optional deadline for a todo
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS due_date TIMESTAMPTZ;
+
+-- Upcoming/overdue lookups only care about live, open todos with a deadline
+CREATE INDEX IF NOT EXISTS idx_todos_due_date ON todos (due_date)
+    WHERE due_date IS NOT NULL AND deleted_at IS NULL AND completed IS NOT TRUE;
@@ -0,0 +1,9 @@
+-- REST hook subscriptions registered by integration platforms (Zapier, IFTTT)
+CREATE TABLE IF NOT EXISTS hook_subscriptions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    event TEXT NOT NULL,
+    target_url TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_hook_subscriptions_event ON hook_subscriptions (event);
@@ -0,0 +1,26 @@
+-- Automation rules: when `trigger` fires for a todo matching every condition,
+-- run the actions in order
+CREATE TABLE IF NOT EXISTS rules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL,
+    trigger TEXT NOT NULL,
+    conditions JSONB NOT NULL DEFAULT '[]',
+    actions JSONB NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_rules_trigger ON rules (trigger) WHERE enabled;
+
+-- Execution log: one row per rule whose conditions matched
+CREATE TABLE IF NOT EXISTS rule_runs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    rule_id UUID NOT NULL REFERENCES rules (id) ON DELETE CASCADE,
+    todo_id UUID NOT NULL,
+    succeeded BOOLEAN NOT NULL,
+    error TEXT,
+    ran_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_rule_runs_rule ON rule_runs (rule_id, ran_at DESC);
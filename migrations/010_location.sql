@@ -0,0 +1,20 @@
+-- Optional place a todo is about ("buy milk" at the store). radius_m is the
+-- geofence around it, in meters
+CREATE EXTENSION IF NOT EXISTS cube;
+CREATE EXTENSION IF NOT EXISTS earthdistance;
+
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS latitude DOUBLE PRECISION;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS radius_m INTEGER;
+
+ALTER TABLE todos ADD CONSTRAINT todos_location_check CHECK (
+    (latitude IS NULL) = (longitude IS NULL)
+    AND (radius_m IS NULL OR latitude IS NOT NULL)
+    AND latitude BETWEEN -90 AND 90
+    AND longitude BETWEEN -180 AND 180
+    AND radius_m > 0
+);
+
+-- Nearby lookups search a bounding box around the caller (earth_box)
+CREATE INDEX IF NOT EXISTS idx_todos_location ON todos USING gist (ll_to_earth(latitude, longitude))
+    WHERE latitude IS NOT NULL AND deleted_at IS NULL;
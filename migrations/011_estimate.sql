@@ -0,0 +1,3 @@
+-- Expected effort for a todo, summed per due day by the workload report
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS estimate_minutes INTEGER
+    CONSTRAINT todos_estimate_check CHECK (estimate_minutes > 0);
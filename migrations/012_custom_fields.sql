@@ -0,0 +1,15 @@
+-- Typed fields clients can add to todos; values live in todos.custom_fields
+CREATE TABLE IF NOT EXISTS custom_fields (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL UNIQUE,
+    field_type TEXT NOT NULL,
+    options TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Values keyed by field name, checked against the definitions on write
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS custom_fields JSONB NOT NULL DEFAULT '{}';
+
+-- Filters use containment (custom_fields @> '{"sprint": 12}')
+CREATE INDEX IF NOT EXISTS idx_todos_custom_fields ON todos USING gin (custom_fields jsonb_path_ops)
+    WHERE deleted_at IS NULL;
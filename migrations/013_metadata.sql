@@ -0,0 +1,28 @@
+-- Free-form client state (UI flags and the like), never interpreted by the API
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS metadata JSONB NOT NULL DEFAULT '{}'
+    CONSTRAINT todos_metadata_check CHECK (
+        jsonb_typeof(metadata) = 'object' AND octet_length(metadata::text) <= 16384
+    );
+
+-- JSON Merge Patch (RFC 7386): objects merge recursively, null removes a
+-- key, anything else replaces the target
+CREATE OR REPLACE FUNCTION jsonb_merge_patch(target JSONB, patch JSONB) RETURNS JSONB
+LANGUAGE plpgsql IMMUTABLE AS $$
+DECLARE
+    result JSONB;
+    item RECORD;
+BEGIN
+    IF jsonb_typeof(patch) IS DISTINCT FROM 'object' THEN
+        RETURN patch;
+    END IF;
+    result := CASE WHEN jsonb_typeof(target) = 'object' THEN target ELSE '{}' END;
+    FOR item IN SELECT key, value FROM jsonb_each(patch) LOOP
+        IF jsonb_typeof(item.value) = 'null' THEN
+            result := result - item.key;
+        ELSE
+            result := jsonb_set(result, ARRAY[item.key], jsonb_merge_patch(result -> item.key, item.value));
+        END IF;
+    END LOOP;
+    RETURN result;
+END;
+$$;
@@ -0,0 +1,18 @@
+-- Links between todos. A link reads "todo_id <kind> related_id", e.g. this
+-- todo duplicates / was caused by the related one
+CREATE TABLE IF NOT EXISTS todo_relations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    todo_id UUID NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    related_id UUID NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT todo_relations_distinct CHECK (todo_id <> related_id),
+    CONSTRAINT todo_relations_unique UNIQUE (todo_id, related_id, kind)
+);
+
+-- relates_to has no direction: A relates_to B is the same link as B relates_to A
+CREATE UNIQUE INDEX IF NOT EXISTS idx_todo_relations_symmetric
+    ON todo_relations (LEAST(todo_id, related_id), GREATEST(todo_id, related_id))
+    WHERE kind = 'relates_to';
+
+CREATE INDEX IF NOT EXISTS idx_todo_relations_related ON todo_relations (related_id);
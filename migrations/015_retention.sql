@@ -0,0 +1,2 @@
+-- Retention purges delete old rule runs by age
+CREATE INDEX IF NOT EXISTS idx_rule_runs_ran_at ON rule_runs (ran_at);
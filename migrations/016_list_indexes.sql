@@ -0,0 +1,10 @@
+-- GET /todos pages through live todos newest first, optionally only open or
+-- only completed ones
+CREATE INDEX IF NOT EXISTS idx_todos_live_created ON todos (created_at DESC)
+    WHERE deleted_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_todos_live_completed_created ON todos (completed, created_at DESC)
+    WHERE deleted_at IS NULL;
+
+-- The retention purge checks whether deleted todos were merged into a todo
+CREATE INDEX IF NOT EXISTS idx_todos_merged_into ON todos (merged_into)
+    WHERE merged_into IS NOT NULL;
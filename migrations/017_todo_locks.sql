@@ -0,0 +1,7 @@
+-- Edit locks: `holder` is editing the todo until `expires_at`; an expired
+-- row is the same as no lock and is overwritten by the next lock
+CREATE TABLE IF NOT EXISTS todo_locks (
+    todo_id UUID PRIMARY KEY REFERENCES todos (id) ON DELETE CASCADE,
+    holder TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
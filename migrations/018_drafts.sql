@@ -0,0 +1,4 @@
+-- Drafts are todos still being filled in: hidden from lists, reports and
+-- events until published, and exempt from the open-title rule until then
+-- (the startup-built idx_todos_open_title leaves them out)
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS draft BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,11 @@
+-- Todos can be scheduled to start later; until then they are hidden from
+-- `?active_only=true` lists, and reaching the date publishes an event
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS start_date TIMESTAMPTZ;
+
+ALTER TABLE todos DROP CONSTRAINT IF EXISTS todos_schedule_check;
+ALTER TABLE todos ADD CONSTRAINT todos_schedule_check
+    CHECK (start_date IS NULL OR due_date IS NULL OR start_date <= due_date);
+
+-- The activation job looks up todos whose start date just passed
+CREATE INDEX IF NOT EXISTS idx_todos_start_date ON todos (start_date)
+    WHERE start_date IS NOT NULL AND deleted_at IS NULL;
@@ -0,0 +1,8 @@
+-- Display settings of a tag, shared by every client; tags themselves live
+-- on the todos, so a style may outlive the last todo using its tag
+CREATE TABLE IF NOT EXISTS tag_styles (
+    tag TEXT PRIMARY KEY,
+    icon TEXT,
+    color TEXT CONSTRAINT tag_styles_color_check CHECK (color ~ '^#[0-9a-f]{6}$'),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
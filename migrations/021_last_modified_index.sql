@@ -0,0 +1,3 @@
+-- Last-Modified of GET responses is the latest write or soft delete of any
+-- todo; the expression index lets MAX() read it without a scan
+CREATE INDEX IF NOT EXISTS idx_todos_last_modified ON todos (GREATEST(updated_at, deleted_at));
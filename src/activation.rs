@@ -0,0 +1,27 @@
+use crate::service::TodoService;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Announce todos as their start date passes: every `interval` the open todos
+/// that started since the previous run are published as `activated` events.
+/// Start dates passing while the server is down are not announced after a
+/// restart.
+pub fn spawn_activation(service: Arc<TodoService>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut after = service.clock().now();
+        loop {
+            ticker.tick().await;
+            let until = service.clock().now();
+            match service.activate_started(after, until).await {
+                Ok(0) => after = until,
+                Ok(activated) => {
+                    tracing::info!(activated, "Announced todos whose start date passed");
+                    after = until;
+                }
+                // The window is kept, so the next run catches up
+                Err(e) => tracing::error!("Failed to look up started todos: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,80 @@
+use crate::config::Config;
+use crate::error::AppError;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{header, request::Parts},
+};
+use std::sync::Arc;
+
+/// Compare a presented token with the configured one without leaking how
+/// many leading bytes matched through timing
+pub fn token_matches(presented: Option<&str>, expected: &str) -> bool {
+    let Some(presented) = presented else {
+        return false;
+    };
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Guard for admin endpoints: requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// When no admin token is configured the endpoints don't exist (404).
+#[derive(Debug)]
+pub struct AdminAuth;
+
+impl<S> FromRequestParts<S> for AdminAuth
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let config = Arc::<Config>::from_ref(state);
+        check_bearer(parts, config.admin_token.as_deref(), "Admin")?;
+        Ok(AdminAuth)
+    }
+}
+
+/// Guard for the integration endpoints (polling triggers and REST hooks):
+/// requires `Authorization: Bearer <INTEGRATION_TOKEN>`, 404 when unset.
+#[derive(Debug)]
+pub struct IntegrationAuth;
+
+impl<S> FromRequestParts<S> for IntegrationAuth
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let config = Arc::<Config>::from_ref(state);
+        check_bearer(parts, config.integration_token.as_deref(), "Integration")?;
+        Ok(IntegrationAuth)
+    }
+}
+
+/// Check the request's bearer token against `expected`; the endpoints of a
+/// `scope` without a configured token are reported as missing
+fn check_bearer(parts: &Parts, expected: Option<&str>, scope: &str) -> Result<(), AppError> {
+    let expected = expected
+        .ok_or_else(|| AppError::NotFound(format!("{} endpoints are not enabled", scope)))?;
+
+    let presented = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !token_matches(presented, expected) {
+        return Err(AppError::Unauthorized(format!(
+            "Invalid {} token",
+            scope.to_lowercase()
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// Identifies a file as a backup of this API
+pub const BACKUP_FORMAT: &str = "axum-todo-backup";
+
+/// Version of the archive layout written by this build; bump it whenever a
+/// change to `BackupTodo` can't be read by older builds
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Largest archive accepted by the restore endpoint
+pub const MAX_BACKUP_BYTES: usize = 100 * 1024 * 1024;
+
+/// A complete, versioned dump of the database
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct Backup {
+    pub format: String,
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub todos: Vec<BackupTodo>,
+}
+
+/// Every column of a todo row, including soft-deleted and merged todos
+#[derive(Debug, Clone, Serialize, Deserialize, TS, sqlx::FromRow)]
+pub struct BackupTodo {
+    pub id: Uuid,
+    pub title: String,
+    pub description: Option<String>,
+    pub completed: bool,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub latitude: Option<f64>,
+    #[serde(default)]
+    pub longitude: Option<f64>,
+    #[serde(default)]
+    pub radius_m: Option<i32>,
+    #[serde(default)]
+    pub estimate_minutes: Option<i32>,
+    /// Values only; field definitions are not part of backups
+    #[serde(default)]
+    #[ts(type = "Record<string, unknown>")]
+    pub custom_fields: serde_json::Value,
+    #[serde(default)]
+    #[ts(type = "Record<string, unknown>")]
+    pub metadata: serde_json::Value,
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub merged_into: Option<Uuid>,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub start_date: Option<DateTime<Utc>>,
+}
+
+/// Summary returned after a restore
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct RestoreReport {
+    pub version: u32,
+    #[ts(type = "number")]
+    pub todos: u64,
+}
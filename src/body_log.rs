@@ -0,0 +1,170 @@
+use axum::{
+    body::{to_bytes, Body, Bytes, HttpBody},
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Largest body that is buffered for logging; bigger (or unsized) bodies are
+/// passed through untouched and logged as omitted
+const MAX_BUFFERED_BYTES: u64 = 64 * 1024;
+
+/// Window over which `max_per_minute` is counted
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Placeholder written in place of redacted values
+const REDACTED: &str = "[REDACTED]";
+
+/// Settings of the body logging middleware
+#[derive(Debug, Clone)]
+pub struct BodyLogOptions {
+    /// Fraction of requests (0.0 to 1.0) whose bodies are logged
+    pub sample_rate: f64,
+    /// Matched route patterns to log (e.g. `/todos/{id}`); empty logs all
+    pub routes: Vec<String>,
+    /// JSON field names whose values are replaced, at any depth
+    pub redact: Vec<String>,
+    /// Most requests logged per minute, so sampling can't flood the logs
+    pub max_per_minute: u32,
+    /// Logged bodies are cut to this many bytes
+    pub max_bytes: usize,
+}
+
+/// Debug logger for sampled request and response bodies
+pub struct BodyLogger {
+    options: BodyLogOptions,
+    window: Mutex<(Instant, u32)>,
+}
+
+impl BodyLogger {
+    pub fn new(options: BodyLogOptions) -> Self {
+        Self {
+            options,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Whether this request should be logged: the route is selected, it falls
+    /// in the sample and the per-minute budget isn't used up
+    fn select(&self, route: &str) -> bool {
+        if !self.options.routes.is_empty() && !self.options.routes.iter().any(|r| r == route) {
+            return false;
+        }
+        if random_fraction() >= self.options.sample_rate {
+            return false;
+        }
+
+        let now = Instant::now();
+        let mut window = self.window.lock().unwrap();
+        if now.duration_since(window.0) >= WINDOW {
+            *window = (now, 0);
+        }
+        if window.1 >= self.options.max_per_minute {
+            return false;
+        }
+        window.1 += 1;
+        true
+    }
+
+    /// Printable form of a body: JSON with the sensitive fields redacted,
+    /// truncated to `max_bytes`. Non-JSON bodies can't be redacted, so only
+    /// their size is logged.
+    fn render(&self, body: &Bytes) -> String {
+        if body.is_empty() {
+            return String::new();
+        }
+        let Ok(mut json) = serde_json::from_slice::<Value>(body) else {
+            return format!("<{} bytes, not JSON>", body.len());
+        };
+        redact(&mut json, &self.options.redact);
+
+        let mut text = json.to_string();
+        if text.len() > self.options.max_bytes {
+            let mut end = self.options.max_bytes;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+            text.push_str("...");
+        }
+        text
+    }
+}
+
+fn redact(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if fields.iter().any(|field| field.eq_ignore_ascii_case(key)) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value, fields);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact(item, fields)),
+        _ => {}
+    }
+}
+
+/// Uniform in [0, 1) from the random bits of a v4 UUID, for sampling
+pub fn random_fraction() -> f64 {
+    (Uuid::new_v4().as_u128() >> 75) as f64 / (1u64 << 53) as f64
+}
+
+/// Buffer `body` when it is small enough, returning the bytes (or None when
+/// it was left alone) and a body to pass on in its place
+pub async fn capture(body: Body) -> (Option<Bytes>, Body) {
+    if body
+        .size_hint()
+        .upper()
+        .is_none_or(|upper| upper > MAX_BUFFERED_BYTES)
+    {
+        return (None, body);
+    }
+    match to_bytes(body, MAX_BUFFERED_BYTES as usize).await {
+        Ok(bytes) => (Some(bytes.clone()), Body::from(bytes)),
+        Err(_) => (None, Body::empty()),
+    }
+}
+
+/// Middleware logging the bodies of a sample of requests and their responses
+pub async fn log_bodies(
+    State(logger): State<Arc<BodyLogger>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    if !logger.select(&route) {
+        return next.run(request).await;
+    }
+
+    let method = request.method().clone();
+    let (parts, body) = request.into_parts();
+    let (request_body, body) = capture(body).await;
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let status = response.status();
+    let (parts, body) = response.into_parts();
+    let (response_body, body) = capture(body).await;
+
+    let omitted = || "<omitted>".to_string();
+    tracing::info!(
+        %method,
+        route,
+        status = status.as_u16(),
+        request_body = request_body.map(|b| logger.render(&b)).unwrap_or_else(omitted),
+        response_body = response_body.map(|b| logger.render(&b)).unwrap_or_else(omitted),
+        "sampled request"
+    );
+
+    Response::from_parts(parts, body)
+}
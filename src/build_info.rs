@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Crate version of this build
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Commit the build was made from, `unknown` outside a git checkout
+pub const GIT_SHA: &str = env!("BUILD_GIT_SHA");
+
+/// Output of `rustc --version` for the compiler that made the build
+pub const RUSTC_VERSION: &str = env!("BUILD_RUSTC_VERSION");
+
+/// Versions of the HTTP API this build serves; the unversioned routes are
+/// version 1
+pub const API_VERSIONS: &[&str] = &["1"];
+
+/// Seconds since the Unix epoch at build time, set by build.rs
+const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+
+/// Which build of the API is running
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_sha: String,
+    pub rustc: String,
+    pub built_at: Option<DateTime<Utc>>,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            version: VERSION.to_string(),
+            git_sha: GIT_SHA.to_string(),
+            rustc: RUSTC_VERSION.to_string(),
+            built_at: BUILD_TIMESTAMP
+                .parse()
+                .ok()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+        }
+    }
+}
+
+/// Public summary of the running build, for clients and monitors
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct VersionInfo {
+    /// Semantic version of the server
+    pub version: String,
+    pub git_sha: String,
+    pub built_at: Option<DateTime<Utc>>,
+    pub api_versions: Vec<String>,
+}
+
+impl From<BuildInfo> for VersionInfo {
+    fn from(build: BuildInfo) -> Self {
+        Self {
+            version: build.version,
+            git_sha: build.git_sha,
+            built_at: build.built_at,
+            api_versions: API_VERSIONS
+                .iter()
+                .map(|version| version.to_string())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,643 @@
+use crate::error::AppError;
+use crate::extractors::Path;
+use crate::ical::{self, ICALENDAR_CONTENT_TYPE};
+use crate::models::{CreateTodo, TodoResponse, UpdateTodo};
+use crate::service::TodoService;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use quick_xml::events::Event;
+use quick_xml::name::ResolveResult;
+use quick_xml::NsReader;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Principal and calendar home (there are no users, so they are the same)
+const HOME: &str = "/dav/";
+/// The single calendar collection, holding every todo as a VTODO
+const CALENDAR: &str = "/dav/todos/";
+
+const DAV: &str = "DAV:";
+const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+const CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+const ALLOWED_METHODS: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT";
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// Properties returned for `allprop` (calendar-data must be asked for)
+const ALL_PROPS: [(&str, &str); 12] = [
+    (DAV, "resourcetype"),
+    (DAV, "displayname"),
+    (DAV, "current-user-principal"),
+    (DAV, "principal-URL"),
+    (DAV, "current-user-privilege-set"),
+    (DAV, "getetag"),
+    (DAV, "getcontenttype"),
+    (DAV, "getlastmodified"),
+    (CALDAV, "calendar-home-set"),
+    (CALDAV, "supported-calendar-component-set"),
+    (CALENDARSERVER, "getctag"),
+    (DAV, "supported-report-set"),
+];
+
+/// A property name qualified by its XML namespace
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PropName {
+    ns: String,
+    name: String,
+}
+
+/// A parsed PROPFIND or REPORT body
+#[derive(Debug, Default)]
+struct DavRequest {
+    /// Local name of the root element (`propfind`, `calendar-multiget`, ...)
+    kind: String,
+    /// Requested properties; `None` means all of them
+    props: Option<Vec<PropName>>,
+    /// Resources listed by a `calendar-multiget`
+    hrefs: Vec<String>,
+}
+
+/// A resource exposed over CalDAV
+enum Resource<'a> {
+    Home,
+    Calendar { ctag: &'a str },
+    Todo(&'a TodoResponse),
+}
+
+impl Resource<'_> {
+    fn href(&self) -> String {
+        match self {
+            Resource::Home => HOME.to_string(),
+            Resource::Calendar { .. } => CALENDAR.to_string(),
+            Resource::Todo(todo) => todo_href(todo.id),
+        }
+    }
+
+    /// Value of a property as an XML fragment, or None if the resource
+    /// doesn't have it
+    fn prop(&self, ns: &str, name: &str) -> Option<String> {
+        let href = |path: &str| format!("<D:href>{}</D:href>", path);
+        let value = match (ns, name, self) {
+            (DAV, "resourcetype", Resource::Home) => "<D:collection/><D:principal/>".to_string(),
+            (DAV, "resourcetype", Resource::Calendar { .. }) => {
+                "<D:collection/><C:calendar/>".to_string()
+            }
+            (DAV, "resourcetype", Resource::Todo(_)) => String::new(),
+            (DAV, "displayname", Resource::Home) => "Todo API".to_string(),
+            (DAV, "displayname", Resource::Calendar { .. }) => "Todos".to_string(),
+            (DAV, "displayname", Resource::Todo(todo)) => escape(&todo.title),
+            (DAV, "current-user-principal", _) => href(HOME),
+            (DAV, "principal-URL", Resource::Home) => href(HOME),
+            (DAV, "current-user-privilege-set", _) => {
+                "<D:privilege><D:read/></D:privilege><D:privilege><D:write/></D:privilege>"
+                    .to_string()
+            }
+            (DAV, "supported-report-set", Resource::Calendar { .. }) => {
+                "<D:supported-report><D:report><C:calendar-query/></D:report></D:supported-report>\
+                 <D:supported-report><D:report><C:calendar-multiget/></D:report></D:supported-report>"
+                    .to_string()
+            }
+            (DAV, "getetag", Resource::Todo(todo)) => escape(&etag(todo)),
+            (DAV, "getcontenttype", Resource::Todo(_)) => ICALENDAR_CONTENT_TYPE.to_string(),
+            (DAV, "getlastmodified", Resource::Todo(todo)) => todo
+                .updated_at
+                .format("%a, %d %b %Y %H:%M:%S GMT")
+                .to_string(),
+            (CALDAV, "calendar-home-set", Resource::Home) => href(HOME),
+            (CALDAV, "supported-calendar-component-set", Resource::Calendar { .. }) => {
+                r#"<C:comp name="VTODO"/>"#.to_string()
+            }
+            (CALDAV, "calendar-data", Resource::Todo(todo)) => escape(&ical::render_vtodo(todo)),
+            (CALENDARSERVER, "getctag", Resource::Calendar { ctag }) => escape(ctag),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Answer the OPTIONS requests clients send to discover CalDAV support; the
+/// CORS layer would otherwise treat them as preflights and drop the `DAV`
+/// header. Must wrap the CORS layer.
+pub async fn discovery_options(request: Request, next: Next) -> Response {
+    let is_probe = request.method() == Method::OPTIONS
+        && request.uri().path().starts_with("/dav")
+        && !request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if is_probe {
+        return options();
+    }
+    next.run(request).await
+}
+
+/// Redirect service discovery (RFC 6764) to the principal
+pub async fn well_known() -> Response {
+    (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, HOME)]).into_response()
+}
+
+/// `/dav/`: the principal and calendar home
+pub async fn home(
+    State(service): State<Arc<TodoService>>,
+    method: Method,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, AppError> {
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => {
+            let request = parse_request(&body)?;
+            let mut responses = vec![prop_response(&Resource::Home, &request.props)];
+            if depth(&headers) > 0 {
+                let ctag = ctag(&service.list_all().await?);
+                let calendar = Resource::Calendar { ctag: &ctag };
+                responses.push(prop_response(&calendar, &request.props));
+            }
+            Ok(multistatus(responses))
+        }
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+/// `/dav/todos/`: the calendar collection
+pub async fn calendar(
+    State(service): State<Arc<TodoService>>,
+    method: Method,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, AppError> {
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => {
+            let request = parse_request(&body)?;
+            let todos = service.list_all().await?;
+            let ctag = ctag(&todos);
+            let mut responses = vec![prop_response(
+                &Resource::Calendar { ctag: &ctag },
+                &request.props,
+            )];
+            if depth(&headers) > 0 {
+                responses.extend(
+                    todos
+                        .iter()
+                        .map(|todo| prop_response(&Resource::Todo(todo), &request.props)),
+                );
+            }
+            Ok(multistatus(responses))
+        }
+        "REPORT" => {
+            let request = parse_request(&body)?;
+            match request.kind.as_str() {
+                // Every object is a VTODO, so component filters always match
+                "calendar-query" => {
+                    let todos = service.list_all().await?;
+                    Ok(multistatus(
+                        todos
+                            .iter()
+                            .map(|todo| prop_response(&Resource::Todo(todo), &request.props))
+                            .collect(),
+                    ))
+                }
+                "calendar-multiget" => {
+                    let mut responses = Vec::with_capacity(request.hrefs.len());
+                    for href in &request.hrefs {
+                        let todo = match href_id(href) {
+                            Some(id) => find(&service, id).await?,
+                            None => None,
+                        };
+                        responses.push(match &todo {
+                            Some(todo) => prop_response(&Resource::Todo(todo), &request.props),
+                            None => missing_response(href),
+                        });
+                    }
+                    Ok(multistatus(responses))
+                }
+                other => Err(AppError::BadRequest(format!(
+                    "Unsupported REPORT '{}', supported: calendar-query, calendar-multiget",
+                    other
+                ))),
+            }
+        }
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+/// `/dav/todos/{id}.ics`: one todo as an iCalendar object. Clients name new
+/// objects themselves, so the name must be a UUID, which becomes the todo id.
+pub async fn object(
+    State(service): State<Arc<TodoService>>,
+    Path(name): Path<String>,
+    method: Method,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, AppError> {
+    let id = href_id(&name);
+    match (method.as_str(), id) {
+        ("OPTIONS", _) => Ok(options()),
+        ("GET" | "HEAD", Some(id)) => {
+            let todo = service.get(id).await?;
+            Ok((
+                [
+                    (header::CONTENT_TYPE, ICALENDAR_CONTENT_TYPE.to_string()),
+                    (header::ETAG, etag(&todo)),
+                ],
+                ical::render_vtodo(&todo),
+            )
+                .into_response())
+        }
+        ("PROPFIND", Some(id)) => {
+            let request = parse_request(&body)?;
+            let todo = service.get(id).await?;
+            Ok(multistatus(vec![prop_response(
+                &Resource::Todo(&todo),
+                &request.props,
+            )]))
+        }
+        ("PUT", Some(id)) => put_object(&service, id, &headers, &body).await,
+        ("PUT", None) => Err(AppError::BadRequest(format!(
+            "Object name '{}' must be a UUID followed by .ics",
+            name
+        ))),
+        ("DELETE", Some(id)) => {
+            let current = find(&service, id).await?;
+            if !preconditions_met(&headers, current.as_ref()) {
+                return Ok(StatusCode::PRECONDITION_FAILED.into_response());
+            }
+            service.delete(id, None).await?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        ("GET" | "HEAD" | "PROPFIND" | "DELETE", None) => Err(AppError::NotFound(format!(
+            "Calendar object {} not found",
+            name
+        ))),
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+/// Create or replace a todo from a VTODO. The stored object never matches
+/// the uploaded one byte for byte (the UID becomes the todo id), so no ETag
+/// is returned and clients fetch it again.
+async fn put_object(
+    service: &TodoService,
+    id: Uuid,
+    headers: &HeaderMap,
+    body: &str,
+) -> Result<Response, AppError> {
+    let vtodo = ical::parse_vtodo(body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid iCalendar object: {}", e)))?;
+    let title = vtodo
+        .summary
+        .filter(|summary| !summary.trim().is_empty())
+        .ok_or_else(|| AppError::BadRequest("VTODO has no SUMMARY".to_string()))?;
+
+    let current = find(service, id).await?;
+    if !preconditions_met(headers, current.as_ref()) {
+        return Ok(StatusCode::PRECONDITION_FAILED.into_response());
+    }
+
+    if current.is_some() {
+        let payload = UpdateTodo {
+            title: Some(title),
+            // A VTODO is replaced as a whole; descriptions can't be unset
+            // through updates, so a removed one becomes empty
+            description: Some(vtodo.description.unwrap_or_default()),
+            completed: Some(vtodo.completed),
+            tags: Some(vtodo.categories),
+            due_date: vtodo.due,
+            ..UpdateTodo::default()
+        };
+        service.update(id, payload, None, false).await?;
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+
+    let payload = CreateTodo {
+        title,
+        description: vtodo.description,
+        tags: vtodo.categories,
+        due_date: vtodo.due,
+        ..CreateTodo::default()
+    };
+    service.create_with_id(id, payload).await?;
+    if vtodo.completed {
+        service.mark_completed(id, None).await?;
+    }
+    Ok(StatusCode::CREATED.into_response())
+}
+
+/// Fetch a todo, treating a missing one as None
+async fn find(service: &TodoService, id: Uuid) -> Result<Option<TodoResponse>, AppError> {
+    match service.get(id).await {
+        Ok(todo) => Ok(Some(todo)),
+        Err(AppError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Evaluate `If-Match` / `If-None-Match` against the current version
+fn preconditions_met(headers: &HeaderMap, current: Option<&TodoResponse>) -> bool {
+    let header = |name: header::HeaderName| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+    };
+    let current_etag = current.map(etag);
+    let matches = |condition: &str| {
+        current_etag.as_deref().is_some_and(|etag| {
+            condition == "*" || condition.split(',').any(|tag| tag.trim() == etag)
+        })
+    };
+
+    if let Some(condition) = header(header::IF_MATCH) {
+        if !matches(condition) {
+            return false;
+        }
+    }
+    if let Some(condition) = header(header::IF_NONE_MATCH) {
+        if matches(condition) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Parse a PROPFIND or REPORT body; an empty body asks for all properties
+fn parse_request(body: &str) -> Result<DavRequest, AppError> {
+    if body.trim().is_empty() {
+        return Ok(DavRequest {
+            kind: "propfind".to_string(),
+            ..DavRequest::default()
+        });
+    }
+
+    let invalid = |e: quick_xml::Error| AppError::BadRequest(format!("Invalid XML body: {}", e));
+    let mut reader = NsReader::from_str(body);
+    let mut request = DavRequest::default();
+    let mut requested = Vec::new();
+    let mut all_props = false;
+    // Qualified names of the currently open elements
+    let mut open: Vec<PropName> = Vec::new();
+    loop {
+        let (ns, event) = reader.read_resolved_event().map_err(invalid)?;
+        match event {
+            Event::Start(ref element) | Event::Empty(ref element) => {
+                let name = PropName {
+                    ns: namespace(&ns),
+                    name: String::from_utf8_lossy(element.local_name().as_ref()).into_owned(),
+                };
+                match open.last() {
+                    None => request.kind = name.name.clone(),
+                    Some(parent) if parent.ns == DAV && parent.name == "prop" => {
+                        requested.push(name.clone())
+                    }
+                    _ => {}
+                }
+                if name.ns == DAV && (name.name == "allprop" || name.name == "propname") {
+                    all_props = true;
+                }
+                if matches!(event, Event::Start(_)) {
+                    open.push(name);
+                }
+            }
+            Event::Text(text)
+                if open
+                    .last()
+                    .is_some_and(|element| element.ns == DAV && element.name == "href") =>
+            {
+                let href = text.unescape().map_err(invalid)?;
+                request.hrefs.push(href.trim().to_string());
+            }
+            Event::End(_) => {
+                open.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    request.props = (!all_props && !requested.is_empty()).then_some(requested);
+    Ok(request)
+}
+
+fn namespace(ns: &ResolveResult) -> String {
+    match ns {
+        ResolveResult::Bound(ns) => String::from_utf8_lossy(ns.as_ref()).into_owned(),
+        _ => String::new(),
+    }
+}
+
+/// One `<D:response>` with a 200 propstat for the properties the resource
+/// has and a 404 propstat for the others
+fn prop_response(resource: &Resource, props: &Option<Vec<PropName>>) -> String {
+    let mut found = String::new();
+    let mut missing = String::new();
+    match props {
+        Some(props) => {
+            for prop in props {
+                match resource.prop(&prop.ns, &prop.name) {
+                    Some(value) => found.push_str(&prop_element(&prop.ns, &prop.name, &value)),
+                    None => missing.push_str(&format!(
+                        r#"<{} xmlns="{}"/>"#,
+                        prop.name,
+                        escape(&prop.ns)
+                    )),
+                }
+            }
+        }
+        None => {
+            for (ns, name) in ALL_PROPS {
+                if let Some(value) = resource.prop(ns, name) {
+                    found.push_str(&prop_element(ns, name, &value));
+                }
+            }
+        }
+    }
+
+    let mut response = format!("<D:response><D:href>{}</D:href>", resource.href());
+    if !found.is_empty() {
+        response.push_str(&propstat(&found, "200 OK"));
+    }
+    if !missing.is_empty() {
+        response.push_str(&propstat(&missing, "404 Not Found"));
+    }
+    response.push_str("</D:response>");
+    response
+}
+
+fn missing_response(href: &str) -> String {
+    format!(
+        "<D:response><D:href>{}</D:href><D:status>HTTP/1.1 404 Not Found</D:status></D:response>",
+        escape(href)
+    )
+}
+
+fn propstat(props: &str, status: &str) -> String {
+    format!(
+        "<D:propstat><D:prop>{}</D:prop><D:status>HTTP/1.1 {}</D:status></D:propstat>",
+        props, status
+    )
+}
+
+fn prop_element(ns: &str, name: &str, value: &str) -> String {
+    let prefix = match ns {
+        DAV => "D",
+        CALDAV => "C",
+        _ => "CS",
+    };
+    format!("<{0}:{1}>{2}</{0}:{1}>", prefix, name, value)
+}
+
+fn multistatus(responses: Vec<String>) -> Response {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<D:multistatus xmlns:D="{}" xmlns:C="{}" xmlns:CS="{}">{}</D:multistatus>"#,
+        DAV,
+        CALDAV,
+        CALENDARSERVER,
+        responses.concat()
+    );
+    (
+        StatusCode::MULTI_STATUS,
+        [(header::CONTENT_TYPE, XML_CONTENT_TYPE)],
+        body,
+    )
+        .into_response()
+}
+
+fn options() -> Response {
+    (
+        [
+            (header::ALLOW, ALLOWED_METHODS),
+            (HeaderName::from_static("dav"), "1, 3, calendar-access"),
+        ],
+        StatusCode::OK,
+    )
+        .into_response()
+}
+
+fn method_not_allowed() -> Response {
+    (
+        StatusCode::METHOD_NOT_ALLOWED,
+        [(header::ALLOW, ALLOWED_METHODS)],
+    )
+        .into_response()
+}
+
+/// `Depth` of a PROPFIND; missing means infinity, served as 1
+fn depth(headers: &HeaderMap) -> u8 {
+    match headers.get("depth").and_then(|value| value.to_str().ok()) {
+        Some("0") => 0,
+        _ => 1,
+    }
+}
+
+fn todo_href(id: Uuid) -> String {
+    format!("{}{}.ics", CALENDAR, id)
+}
+
+/// Todo id named by an object href or file name (`.../{id}.ics`)
+fn href_id(href: &str) -> Option<Uuid> {
+    let name = href.trim_end_matches('/').rsplit('/').next()?;
+    Uuid::parse_str(name.strip_suffix(".ics")?).ok()
+}
+
+/// Changes whenever a todo is saved
+fn etag(todo: &TodoResponse) -> String {
+    format!("\"{}\"", todo.updated_at.timestamp_micros())
+}
+
+/// Collection tag polled by clients to skip syncing an unchanged calendar;
+/// the count covers deletions, which don't touch the remaining todos
+fn ctag(todos: &[TodoResponse]) -> String {
+    let last_update = todos
+        .iter()
+        .map(|todo| todo.updated_at.timestamp_micros())
+        .max()
+        .unwrap_or_default();
+    format!("{}-{}", last_update, todos.len())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prop(ns: &str, name: &str) -> PropName {
+        PropName {
+            ns: ns.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn an_empty_body_asks_for_all_properties() {
+        let request = parse_request("  \n").unwrap();
+        assert_eq!(request.kind, "propfind");
+        assert_eq!(request.props, None);
+    }
+
+    #[test]
+    fn reads_requested_properties_with_their_namespaces() {
+        let request = parse_request(
+            r#"<?xml version="1.0"?>
+            <d:propfind xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+              <d:prop><d:getetag/><cs:getctag/><d:displayname></d:displayname></d:prop>
+            </d:propfind>"#,
+        )
+        .unwrap();
+        assert_eq!(request.kind, "propfind");
+        assert_eq!(
+            request.props,
+            Some(vec![
+                prop(DAV, "getetag"),
+                prop(CALENDARSERVER, "getctag"),
+                prop(DAV, "displayname"),
+            ])
+        );
+    }
+
+    #[test]
+    fn allprop_overrides_listed_properties() {
+        let request =
+            parse_request(r#"<propfind xmlns="DAV:"><allprop/><prop><getetag/></prop></propfind>"#)
+                .unwrap();
+        assert_eq!(request.props, None);
+    }
+
+    #[test]
+    fn reads_the_hrefs_of_a_multiget() {
+        let request = parse_request(
+            r#"<c:calendar-multiget xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+              <d:prop><d:getetag/><c:calendar-data/></d:prop>
+              <d:href> /dav/todos/a.ics </d:href>
+              <d:href>/dav/todos/b%20c.ics</d:href>
+            </c:calendar-multiget>"#,
+        )
+        .unwrap();
+        assert_eq!(request.kind, "calendar-multiget");
+        assert_eq!(request.hrefs, ["/dav/todos/a.ics", "/dav/todos/b%20c.ics"]);
+        assert_eq!(
+            request.props,
+            Some(vec![prop(DAV, "getetag"), prop(CALDAV, "calendar-data")])
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_xml() {
+        let error = parse_request(r#"<propfind xmlns="DAV:"><prop></propfind>"#).unwrap_err();
+        assert!(
+            matches!(&error, AppError::BadRequest(message) if message.starts_with("Invalid XML body")),
+            "{:?}",
+            error
+        );
+    }
+}
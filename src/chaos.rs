@@ -0,0 +1,159 @@
+use crate::body_log::random_fraction;
+use crate::error::HttpError;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Route pattern of a rule that applies to every route without its own
+pub const ANY_ROUTE: &str = "*";
+
+/// Faults injected into the requests of one route
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosRule {
+    /// Matched route pattern (e.g. `/todos/{id}`) or `*`
+    pub route: String,
+    /// Delay added before the request is handled
+    pub latency: Duration,
+    /// Fraction of requests (0.0 to 1.0) answered with an injected 503
+    pub error_rate: f64,
+}
+
+/// Parses `<route>=<latency_ms>:<error_rate>`, e.g. `/todos/{id}=250:0.05`
+impl FromStr for ChaosRule {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid chaos rule '{}' (expected <route>=<latency_ms>:<error_rate>)",
+                spec
+            )
+        };
+        let (route, faults) = spec.rsplit_once('=').ok_or_else(invalid)?;
+        let (latency_ms, error_rate) = faults.split_once(':').ok_or_else(invalid)?;
+        let latency_ms: u64 = latency_ms.trim().parse().map_err(|_| invalid())?;
+        let error_rate: f64 = error_rate.trim().parse().map_err(|_| invalid())?;
+        if !(0.0..=1.0).contains(&error_rate) {
+            return Err(format!(
+                "Chaos error rate must be between 0 and 1 in '{}'",
+                spec
+            ));
+        }
+
+        Ok(ChaosRule {
+            route: route.trim().to_string(),
+            latency: Duration::from_millis(latency_ms),
+            error_rate,
+        })
+    }
+}
+
+/// Fault injection for resilience testing; only built with the `chaos`
+/// feature and only active when `CHAOS_ENABLED` is set
+pub struct Chaos {
+    rules: Vec<ChaosRule>,
+}
+
+impl Chaos {
+    pub fn new(rules: Vec<ChaosRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Parse one rule per spec (see `ChaosRule`'s `FromStr`)
+    pub fn from_specs(specs: &[String]) -> Result<Self, String> {
+        let rules = specs
+            .iter()
+            .map(|spec| spec.parse())
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(rules))
+    }
+
+    pub fn rules(&self) -> &[ChaosRule] {
+        &self.rules
+    }
+
+    /// The route's own rule, or else the `*` rule
+    fn rule_for(&self, route: &str) -> Option<&ChaosRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.route == route)
+            .or_else(|| self.rules.iter().find(|rule| rule.route == ANY_ROUTE))
+    }
+}
+
+/// Middleware delaying requests and failing a share of them with `503`
+/// (code `CHAOS_INJECTED`) as configured per route
+pub async fn inject_faults(
+    State(chaos): State<Arc<Chaos>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let Some(rule) = chaos.rule_for(&route) else {
+        return next.run(request).await;
+    };
+
+    if !rule.latency.is_zero() {
+        tokio::time::sleep(rule.latency).await;
+    }
+    if random_fraction() < rule.error_rate {
+        tracing::debug!(route, "Injecting a chaos failure");
+        return HttpError::new(
+            "Injected failure (chaos mode)",
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .with_code("CHAOS_INJECTED")
+        .with_retry_after(1)
+        .into_http_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rules() {
+        let rule: ChaosRule = " /todos/{id} = 250 : 0.05 ".parse().unwrap();
+        assert_eq!(
+            rule,
+            ChaosRule {
+                route: "/todos/{id}".to_string(),
+                latency: Duration::from_millis(250),
+                error_rate: 0.05,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        for spec in [
+            "/todos",
+            "/todos=250",
+            "/todos=fast:0.1",
+            "*=10:1.5",
+            "*=-5:0",
+        ] {
+            assert!(spec.parse::<ChaosRule>().is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn route_rules_take_precedence_over_the_wildcard() {
+        let chaos = Chaos::from_specs(&["*=10:0".to_string(), "/todos=20:0".to_string()]).unwrap();
+        assert_eq!(chaos.rule_for("/todos").unwrap().latency.as_millis(), 20);
+        assert_eq!(chaos.rule_for("/tags").unwrap().latency.as_millis(), 10);
+    }
+}
@@ -0,0 +1,392 @@
+use crate::backup::{Backup, RestoreReport};
+use crate::build_info::VersionInfo;
+use crate::error::ErrorResponse;
+use crate::extractors::CONFIRM_COUNT_HEADER;
+use crate::import::Provider;
+use crate::integrations::{HookSubscription, SubscribeHook, Trigger, TriggerItem};
+use crate::models::{
+    BulkResult, BulkTagRequest, CountMode, CreateTodo, ImportReport, LockTodo, NearbyTodo,
+    OnConflict, Page, RenderedTodo, TagUsage, TodoLock, TodoQuery, TodoResponse, UnlockTodo,
+    UpdateTodo,
+};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use uuid::Uuid;
+
+/// Errors returned by `TodoClient`
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request could not be sent or the response could not be decoded
+    Transport(reqwest::Error),
+    /// The API answered with a non-success status
+    Api {
+        status: StatusCode,
+        error: ErrorResponse,
+    },
+}
+
+impl ClientError {
+    /// HTTP status returned by the API, if the request got that far
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Transport(e) => e.status(),
+            ClientError::Api { status, .. } => Some(*status),
+        }
+    }
+
+    /// Machine-readable error code (e.g. `DUPLICATE_TODO`), if the API sent one
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ClientError::Transport(_) => None,
+            ClientError::Api { error, .. } => error.code.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Transport(e) => write!(f, "Request failed: {}", e),
+            ClientError::Api { status, error } => write!(f, "{}: {}", status, error.message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Transport(e) => Some(e),
+            ClientError::Api { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Transport(e)
+    }
+}
+
+/// Pagination parameters for `TodoClient::list_todos`
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct PageParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<CountMode>,
+}
+
+#[derive(Serialize)]
+struct TagParams<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    starts_with: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<u32>,
+}
+
+/// Typed HTTP client for the Todo API
+#[derive(Debug, Clone)]
+pub struct TodoClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl TodoClient {
+    /// Create a client for the API served at `base_url` (e.g. `http://localhost:3000`)
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    /// Create a client reusing a preconfigured `reqwest::Client`
+    /// (timeouts, default headers, connection pool, ...)
+    pub fn with_http_client(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self { http, base_url }
+    }
+
+    /// POST /todos
+    pub async fn create_todo(&self, payload: &CreateTodo) -> Result<TodoResponse, ClientError> {
+        self.send(self.http.post(self.url("/todos")).json(payload))
+            .await
+    }
+
+    /// POST /todos?on_conflict=return_existing: an open todo with the same
+    /// title is returned (unchanged) instead of a DUPLICATE_TODO error
+    pub async fn create_todo_or_existing(
+        &self,
+        payload: &CreateTodo,
+    ) -> Result<TodoResponse, ClientError> {
+        self.send(
+            self.http
+                .post(self.url("/todos"))
+                .query(&[("on_conflict", OnConflict::ReturnExisting)])
+                .json(payload),
+        )
+        .await
+    }
+
+    /// GET /todos
+    pub async fn list_todos(
+        &self,
+        query: &TodoQuery,
+        page: PageParams,
+    ) -> Result<Page<TodoResponse>, ClientError> {
+        // Without any paging parameter the server answers with a bare array
+        let page = PageParams {
+            page: Some(page.page.unwrap_or(1)),
+            ..page
+        };
+        self.send(self.http.get(self.url("/todos")).query(query).query(&page))
+            .await
+    }
+
+    /// GET /todos/{id}
+    pub async fn get_todo(&self, id: Uuid) -> Result<TodoResponse, ClientError> {
+        self.send(self.http.get(self.url(&format!("/todos/{}", id))))
+            .await
+    }
+
+    /// GET /todos/{id}?render=html
+    pub async fn get_todo_rendered(&self, id: Uuid) -> Result<RenderedTodo, ClientError> {
+        self.send(
+            self.http
+                .get(self.url(&format!("/todos/{}", id)))
+                .query(&[("render", "html")]),
+        )
+        .await
+    }
+
+    /// GET /todos/nearby (`radius_m` in meters)
+    pub async fn nearby_todos(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        radius_m: i32,
+    ) -> Result<Vec<NearbyTodo>, ClientError> {
+        self.send(self.http.get(self.url("/todos/nearby")).query(&[
+            ("lat", latitude.to_string()),
+            ("lng", longitude.to_string()),
+            ("radius", radius_m.to_string()),
+        ]))
+        .await
+    }
+
+    /// PATCH /todos/{id}
+    pub async fn update_todo(
+        &self,
+        id: Uuid,
+        payload: &UpdateTodo,
+    ) -> Result<TodoResponse, ClientError> {
+        self.send(
+            self.http
+                .patch(self.url(&format!("/todos/{}", id)))
+                .json(payload),
+        )
+        .await
+    }
+
+    /// DELETE /todos/{id}
+    pub async fn delete_todo(&self, id: Uuid) -> Result<(), ClientError> {
+        self.execute(self.http.delete(self.url(&format!("/todos/{}", id))))
+            .await?;
+        Ok(())
+    }
+
+    /// PATCH /todos/{id}/complete
+    pub async fn mark_completed(&self, id: Uuid) -> Result<TodoResponse, ClientError> {
+        self.send(
+            self.http
+                .patch(self.url(&format!("/todos/{}/complete", id))),
+        )
+        .await
+    }
+
+    /// POST /todos/{id}/restore
+    pub async fn restore_todo(&self, id: Uuid) -> Result<TodoResponse, ClientError> {
+        self.send(self.http.post(self.url(&format!("/todos/{}/restore", id))))
+            .await
+    }
+
+    /// POST /todos/{id}/publish
+    pub async fn publish_todo(&self, id: Uuid) -> Result<TodoResponse, ClientError> {
+        self.send(self.http.post(self.url(&format!("/todos/{}/publish", id))))
+            .await
+    }
+
+    /// POST /todos/{id}/lock
+    pub async fn lock_todo(&self, id: Uuid, payload: &LockTodo) -> Result<TodoLock, ClientError> {
+        self.send(
+            self.http
+                .post(self.url(&format!("/todos/{}/lock", id)))
+                .json(payload),
+        )
+        .await
+    }
+
+    /// POST /todos/{id}/unlock
+    pub async fn unlock_todo(&self, id: Uuid, payload: &UnlockTodo) -> Result<(), ClientError> {
+        self.execute(
+            self.http
+                .post(self.url(&format!("/todos/{}/unlock", id)))
+                .json(payload),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// POST /todos/{id}/merge/{other_id}
+    pub async fn merge_todo(&self, id: Uuid, other_id: Uuid) -> Result<TodoResponse, ClientError> {
+        self.send(
+            self.http
+                .post(self.url(&format!("/todos/{}/merge/{}", id, other_id))),
+        )
+        .await
+    }
+
+    /// PATCH /todos: update every todo matching `query`. When more todos
+    /// match than the server's threshold, `confirm` must be their number.
+    pub async fn bulk_update(
+        &self,
+        query: &TodoQuery,
+        payload: &UpdateTodo,
+        confirm: Option<u64>,
+    ) -> Result<BulkResult, ClientError> {
+        let mut request = self
+            .http
+            .patch(self.url("/todos"))
+            .query(query)
+            .json(payload);
+        if let Some(count) = confirm {
+            request = request.header(CONFIRM_COUNT_HEADER, count);
+        }
+        self.send(request).await
+    }
+
+    /// POST /todos/tags/bulk
+    pub async fn bulk_tag(&self, payload: &BulkTagRequest) -> Result<BulkResult, ClientError> {
+        self.send(self.http.post(self.url("/todos/tags/bulk")).json(payload))
+            .await
+    }
+
+    /// GET /version
+    pub async fn version(&self) -> Result<VersionInfo, ClientError> {
+        self.send(self.http.get(self.url("/version"))).await
+    }
+
+    /// GET /tags
+    pub async fn list_tags(
+        &self,
+        starts_with: Option<&str>,
+        limit: Option<u32>,
+    ) -> Result<Vec<TagUsage>, ClientError> {
+        let params = TagParams { starts_with, limit };
+        self.send(self.http.get(self.url("/tags")).query(&params))
+            .await
+    }
+
+    /// POST /import/{provider}
+    pub async fn import_todos(
+        &self,
+        provider: Provider,
+        export: Vec<u8>,
+        dry_run: bool,
+    ) -> Result<ImportReport, ClientError> {
+        self.send(
+            self.http
+                .post(self.url(&format!("/import/{}", provider.name())))
+                .query(&[("dry_run", dry_run)])
+                .body(export),
+        )
+        .await
+    }
+
+    /// GET /admin/backup (needs an `Authorization: Bearer` default header,
+    /// see `with_http_client`)
+    pub async fn create_backup(&self) -> Result<Backup, ClientError> {
+        self.send(self.http.get(self.url("/admin/backup"))).await
+    }
+
+    /// POST /admin/restore
+    pub async fn restore_backup(&self, backup: &Backup) -> Result<RestoreReport, ClientError> {
+        self.send(self.http.post(self.url("/admin/restore")).json(backup))
+            .await
+    }
+
+    /// GET /integrations/triggers/{trigger} (needs an `Authorization: Bearer`
+    /// default header, see `with_http_client`)
+    pub async fn poll_trigger(
+        &self,
+        trigger: Trigger,
+        tag: Option<&str>,
+    ) -> Result<Vec<TriggerItem>, ClientError> {
+        let mut request = self
+            .http
+            .get(self.url(&format!("/integrations/triggers/{}", trigger.name())));
+        if let Some(tag) = tag {
+            request = request.query(&[("tag", tag)]);
+        }
+        self.send(request).await
+    }
+
+    /// POST /integrations/hooks
+    pub async fn subscribe_hook(
+        &self,
+        payload: &SubscribeHook,
+    ) -> Result<HookSubscription, ClientError> {
+        self.send(
+            self.http
+                .post(self.url("/integrations/hooks"))
+                .json(payload),
+        )
+        .await
+    }
+
+    /// DELETE /integrations/hooks/{id}
+    pub async fn unsubscribe_hook(&self, id: Uuid) -> Result<(), ClientError> {
+        self.execute(
+            self.http
+                .delete(self.url(&format!("/integrations/hooks/{}", id))),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// GET /metrics (OpenMetrics text)
+    pub async fn metrics(&self) -> Result<String, ClientError> {
+        let response = self.execute(self.http.get(self.url("/metrics"))).await?;
+        Ok(response.text().await?)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, ClientError> {
+        let response = self.execute(request).await?;
+        Ok(response.json().await?)
+    }
+
+    /// Send the request, turning non-success responses into `ClientError::Api`
+    async fn execute(&self, request: RequestBuilder) -> Result<reqwest::Response, ClientError> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        // Errors raised outside the handlers (e.g. by a proxy) may not carry
+        // the usual JSON envelope
+        let body = response.text().await?;
+        let error = serde_json::from_str(&body).unwrap_or_else(|_| ErrorResponse {
+            status: "fail".to_string(),
+            message: body,
+            code: None,
+            details: None,
+        });
+        Err(ClientError::Api { status, error })
+    }
+}
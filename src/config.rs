@@ -0,0 +1,269 @@
+use crate::ids::IdFormat;
+use serde::{Serialize, Serializer};
+use std::env;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Placeholder for secrets when the configuration is serialized
+const REDACTED: &str = "[REDACTED]";
+
+/// Application configuration, loaded from environment variables. It
+/// serializes with secrets redacted, for diagnostics.
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    #[serde(serialize_with = "redact_url")]
+    pub database_url: String,
+    /// Longest a single SQL statement may run, in milliseconds; 0 disables
+    /// the limit
+    pub statement_timeout_ms: u64,
+    /// Largest number of connections the database pool opens
+    pub db_max_connections: u32,
+    /// How long a request waits for a free pooled connection before failing
+    /// with 503, in milliseconds
+    pub db_acquire_timeout_ms: u64,
+    /// Connect through a transaction-pooling proxy such as PgBouncer: no
+    /// prepared statement caching and no session startup parameters
+    pub db_pgbouncer: bool,
+    pub port: u16,
+    /// Interface the public API listens on
+    pub host: IpAddr,
+    /// Port for the internal endpoints (`/metrics`, `/admin/*`); when unset
+    /// they are served on the public listener
+    pub internal_port: Option<u16>,
+    /// Interface the internal listener binds to
+    pub internal_host: IpAddr,
+    /// Accept HTTP/2 connections next to HTTP/1.1
+    pub http2: bool,
+    /// Concurrent streams allowed on one HTTP/2 connection
+    pub http2_max_concurrent_streams: u32,
+    /// Seconds between HTTP/2 keep-alive pings; 0 disables them
+    pub http2_keep_alive_interval_secs: u64,
+    /// Seconds to wait for a keep-alive ping to be acknowledged
+    pub http2_keep_alive_timeout_secs: u64,
+    /// Reuse HTTP/1.1 connections across requests
+    pub keep_alive: bool,
+    /// Seconds a client may take to send request headers (also closes idle
+    /// HTTP/1.1 connections)
+    pub header_read_timeout_secs: u64,
+    /// Disable Nagle's algorithm on accepted connections
+    pub tcp_nodelay: bool,
+    /// Bind with SO_REUSEPORT so a new instance can take over the port while
+    /// the old one drains
+    pub reuse_port: bool,
+    /// Seconds open connections may keep running after a shutdown signal
+    pub shutdown_timeout_secs: u64,
+    /// Reject request bodies containing fields the endpoint doesn't know
+    pub strict_json: bool,
+    /// Collapse internal whitespace runs in titles into a single space
+    pub collapse_whitespace: bool,
+    /// Allow only one open todo per title (case-insensitive), backed by a
+    /// unique index built on startup
+    pub unique_open_titles: bool,
+    /// Page size used by list endpoints when the client doesn't specify one
+    pub default_page_size: u32,
+    /// Largest page size a client may request
+    pub max_page_size: u32,
+    /// Requests allowed per client IP per window; 0 disables rate limiting
+    pub rate_limit_requests: u32,
+    /// Length of the rate limiting window in seconds
+    pub rate_limit_window_secs: u64,
+    /// Check the database schema against this build on startup
+    pub verify_schema: bool,
+    /// How often the cached completion streaks are refreshed after changes
+    pub stats_refresh_secs: u64,
+    /// Estimated minutes of work a day holds before the workload report warns
+    pub workload_capacity_minutes: u32,
+    /// UUID version of new ids (`v4` or time-ordered `v7`)
+    pub id_format: IdFormat,
+    /// Todos a filtered bulk update changes before it needs `X-Confirm-Count`
+    pub bulk_confirm_threshold: u64,
+    /// How often todos whose start date passed are announced
+    pub activation_interval_secs: u64,
+    /// Log sampled request/response bodies (debugging aid)
+    pub log_bodies: bool,
+    /// Fraction of requests whose bodies are logged, from 0.0 to 1.0
+    pub log_body_sample_rate: f64,
+    /// Route patterns whose bodies are logged; empty means every route
+    pub log_body_routes: Vec<String>,
+    /// JSON fields whose values are redacted in logged bodies
+    pub log_body_redact: Vec<String>,
+    /// Most requests whose bodies are logged per minute
+    pub log_body_max_per_minute: u32,
+    /// Logged bodies are truncated to this many bytes
+    pub log_body_max_bytes: usize,
+    /// Inject latency and errors (builds with the `chaos` feature only)
+    pub chaos_enabled: bool,
+    /// Chaos rules, `<route>=<latency_ms>:<error_rate>`; `*` matches any route
+    pub chaos_rules: Vec<String>,
+    /// Base URL GET requests are mirrored to for comparison; shadowing is
+    /// off when unset
+    pub shadow_base_url: Option<String>,
+    /// Fraction of GET requests mirrored, from 0.0 to 1.0
+    pub shadow_sample_rate: f64,
+    /// Seconds browsers may reuse a todo GET response before revalidating it
+    /// with If-Modified-Since
+    pub cache_max_age_secs: u64,
+    /// Days soft-deleted todos are kept before being purged; 0 keeps them
+    pub retention_deleted_todos_days: u32,
+    /// Days rule runs are kept; 0 keeps them
+    pub retention_rule_runs_days: u32,
+    /// How often the retention policies run
+    pub retention_interval_secs: u64,
+    /// Rows deleted per statement by the retention purges
+    pub retention_batch_size: u32,
+    /// Token required to read the Atom feeds; feeds are disabled when unset
+    #[serde(serialize_with = "redact")]
+    pub feed_token: Option<String>,
+    /// Bearer token for the admin endpoints; they are disabled when unset
+    #[serde(serialize_with = "redact")]
+    pub admin_token: Option<String>,
+    /// Bearer token for the integration triggers and REST hooks; they are
+    /// disabled when unset
+    #[serde(serialize_with = "redact")]
+    pub integration_token: Option<String>,
+}
+
+impl Config {
+    /// Load the configuration from the environment (after `.env` has been read)
+    pub fn from_env() -> Result<Self, String> {
+        let config = Self {
+            database_url: env::var("DATABASE_URL").map_err(|_| "DATABASE_URL must be set")?,
+            statement_timeout_ms: env_or("DB_STATEMENT_TIMEOUT_MS", 30_000)?,
+            db_max_connections: env_or("DB_MAX_CONNECTIONS", 5)?,
+            db_acquire_timeout_ms: env_or("DB_ACQUIRE_TIMEOUT_MS", 5_000)?,
+            db_pgbouncer: env_or("DB_PGBOUNCER", false)?,
+            port: env_or("PORT", 3000)?,
+            host: env_or("HOST", IpAddr::from([0, 0, 0, 0]))?,
+            internal_port: env_opt("INTERNAL_PORT")
+                .map(|port| {
+                    port.parse()
+                        .map_err(|_| format!("INTERNAL_PORT has an invalid value: {}", port))
+                })
+                .transpose()?,
+            internal_host: env_or("INTERNAL_HOST", IpAddr::from([127, 0, 0, 1]))?,
+            http2: env_or("HTTP2", true)?,
+            http2_max_concurrent_streams: env_or("HTTP2_MAX_CONCURRENT_STREAMS", 200)?,
+            http2_keep_alive_interval_secs: env_or("HTTP2_KEEP_ALIVE_INTERVAL_SECS", 0)?,
+            http2_keep_alive_timeout_secs: env_or("HTTP2_KEEP_ALIVE_TIMEOUT_SECS", 20)?,
+            keep_alive: env_or("HTTP_KEEP_ALIVE", true)?,
+            header_read_timeout_secs: env_or("HTTP_HEADER_READ_TIMEOUT_SECS", 30)?,
+            tcp_nodelay: env_or("TCP_NODELAY", true)?,
+            reuse_port: env_or("REUSE_PORT", false)?,
+            shutdown_timeout_secs: env_or("SHUTDOWN_TIMEOUT_SECS", 30)?,
+            strict_json: env_or("STRICT_JSON", false)?,
+            collapse_whitespace: env_or("COLLAPSE_WHITESPACE", true)?,
+            unique_open_titles: env_or("UNIQUE_OPEN_TITLES", false)?,
+            default_page_size: env_or("DEFAULT_PAGE_SIZE", 20)?,
+            max_page_size: env_or("MAX_PAGE_SIZE", 100)?,
+            rate_limit_requests: env_or("RATE_LIMIT_REQUESTS", 100)?,
+            rate_limit_window_secs: env_or("RATE_LIMIT_WINDOW_SECS", 60)?,
+            verify_schema: env_or("VERIFY_SCHEMA", true)?,
+            stats_refresh_secs: env_or("STATS_REFRESH_SECS", 60)?,
+            workload_capacity_minutes: env_or("WORKLOAD_CAPACITY_MINUTES", 480)?,
+            id_format: env_or("ID_FORMAT", IdFormat::V4)?,
+            bulk_confirm_threshold: env_or("BULK_CONFIRM_THRESHOLD", 100)?,
+            activation_interval_secs: env_or("ACTIVATION_INTERVAL_SECS", 60)?,
+            log_bodies: env_or("LOG_BODIES", false)?,
+            log_body_sample_rate: env_or("LOG_BODY_SAMPLE_RATE", 0.1)?,
+            log_body_routes: env_list("LOG_BODY_ROUTES", &[]),
+            log_body_redact: env_list(
+                "LOG_BODY_REDACT",
+                &["title", "description", "metadata", "custom_fields"],
+            ),
+            log_body_max_per_minute: env_or("LOG_BODY_MAX_PER_MINUTE", 60)?,
+            log_body_max_bytes: env_or("LOG_BODY_MAX_BYTES", 2048)?,
+            chaos_enabled: env_or("CHAOS_ENABLED", false)?,
+            chaos_rules: env_list("CHAOS_RULES", &[]),
+            shadow_base_url: env_opt("SHADOW_BASE_URL"),
+            shadow_sample_rate: env_or("SHADOW_SAMPLE_RATE", 0.01)?,
+            cache_max_age_secs: env_or("CACHE_MAX_AGE_SECS", 0)?,
+            retention_deleted_todos_days: env_or("RETENTION_DELETED_TODOS_DAYS", 30)?,
+            retention_rule_runs_days: env_or("RETENTION_RULE_RUNS_DAYS", 180)?,
+            retention_interval_secs: env_or("RETENTION_INTERVAL_SECS", 3600)?,
+            retention_batch_size: env_or("RETENTION_BATCH_SIZE", 1000)?,
+            feed_token: env_opt("FEED_TOKEN"),
+            admin_token: env_opt("ADMIN_TOKEN"),
+            integration_token: env_opt("INTEGRATION_TOKEN"),
+        };
+
+        if config.internal_port == Some(config.port) {
+            return Err("INTERNAL_PORT must differ from PORT".to_string());
+        }
+
+        if !(0.0..=1.0).contains(&config.log_body_sample_rate) {
+            return Err("LOG_BODY_SAMPLE_RATE must be between 0 and 1".to_string());
+        }
+
+        if !(0.0..=1.0).contains(&config.shadow_sample_rate) {
+            return Err("SHADOW_SAMPLE_RATE must be between 0 and 1".to_string());
+        }
+
+        if config.db_max_connections == 0 {
+            return Err("DB_MAX_CONNECTIONS must be at least 1".to_string());
+        }
+
+        if config.stats_refresh_secs == 0 {
+            return Err("STATS_REFRESH_SECS must be at least 1".to_string());
+        }
+
+        if config.retention_interval_secs == 0 {
+            return Err("RETENTION_INTERVAL_SECS must be at least 1".to_string());
+        }
+
+        if config.default_page_size == 0 || config.default_page_size > config.max_page_size {
+            return Err(format!(
+                "DEFAULT_PAGE_SIZE must be between 1 and MAX_PAGE_SIZE ({})",
+                config.max_page_size
+            ));
+        }
+
+        Ok(config)
+    }
+}
+
+/// Serialize a secret as whether it is set, never as its value
+fn redact<S: Serializer>(secret: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    secret.as_ref().map(|_| REDACTED).serialize(serializer)
+}
+
+/// Serialize a connection URL with its password replaced
+fn redact_url<S: Serializer>(url: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    match reqwest::Url::parse(url) {
+        Ok(mut url) => {
+            if url.password().is_some() {
+                // Brackets would be percent-encoded in the URL
+                let _ = url.set_password(Some("REDACTED"));
+            }
+            serializer.serialize_str(url.as_str())
+        }
+        Err(_) => serializer.serialize_str(REDACTED),
+    }
+}
+
+/// Parse an optional environment variable, falling back to `default` when unset
+fn env_or<T: FromStr>(key: &str, default: T) -> Result<T, String> {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| format!("{} has an invalid value: {}", key, value)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Parse a comma-separated list, falling back to `default` when unset
+fn env_list(key: &str, default: &[&str]) -> Vec<String> {
+    match env::var(key) {
+        Ok(value) => value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect(),
+        Err(_) => default.iter().map(|item| item.to_string()).collect(),
+    }
+}
+
+/// Read an optional environment variable, treating an empty value as unset
+fn env_opt(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.is_empty())
+}
@@ -0,0 +1,180 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// Longest value accepted by a text field
+pub const MAX_TEXT_VALUE_LENGTH: usize = 1_000;
+
+/// Kind of value a custom field holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    Text,
+    Number,
+    /// `YYYY-MM-DD`
+    Date,
+    /// One of the field's `options`
+    Select,
+}
+
+impl FieldType {
+    /// Name stored in the database (matches the serialized form)
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldType::Text => "text",
+            FieldType::Number => "number",
+            FieldType::Date => "date",
+            FieldType::Select => "select",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            FieldType::Text,
+            FieldType::Number,
+            FieldType::Date,
+            FieldType::Select,
+        ]
+        .into_iter()
+        .find(|field_type| field_type.name() == name)
+    }
+}
+
+/// Definition of a field todos can carry in `custom_fields`
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct CustomField {
+    pub id: Uuid,
+    pub name: String,
+    pub field_type: FieldType,
+    /// Allowed values of a select field; empty for other types
+    pub options: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request DTO for defining a custom field
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct CreateCustomField {
+    pub name: String,
+    pub field_type: FieldType,
+    #[serde(default)]
+    #[ts(as = "Option<Vec<String>>", optional)]
+    pub options: Vec<String>,
+}
+
+impl CustomField {
+    /// Check a value given for this field, returning why it doesn't fit
+    pub fn check(&self, value: &Value) -> Result<(), String> {
+        let fits = match (self.field_type, value) {
+            (FieldType::Text, Value::String(text)) => {
+                if text.chars().count() > MAX_TEXT_VALUE_LENGTH {
+                    return Err(format!(
+                        "{} must not be more than {} characters",
+                        self.name, MAX_TEXT_VALUE_LENGTH
+                    ));
+                }
+                true
+            }
+            (FieldType::Number, Value::Number(_)) => true,
+            (FieldType::Date, Value::String(date)) => {
+                NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()
+            }
+            (FieldType::Select, Value::String(option)) => {
+                if !self.options.contains(option) {
+                    return Err(format!(
+                        "{} must be one of: {}",
+                        self.name,
+                        self.options.join(", ")
+                    ));
+                }
+                true
+            }
+            _ => false,
+        };
+        if !fits {
+            return Err(format!("{} must be {}", self.name, self.expected()));
+        }
+        Ok(())
+    }
+
+    /// Turn a value written in a query string into the JSON value stored for
+    /// this field
+    pub fn parse(&self, raw: &str) -> Result<Value, String> {
+        let value = match self.field_type {
+            FieldType::Number => raw
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number)
+                .ok_or_else(|| format!("{} must be {}", self.name, self.expected()))?,
+            _ => Value::String(raw.to_string()),
+        };
+        self.check(&value)?;
+        Ok(value)
+    }
+
+    fn expected(&self) -> &'static str {
+        match self.field_type {
+            FieldType::Text => "a string",
+            FieldType::Number => "a number",
+            FieldType::Date => "a date (YYYY-MM-DD)",
+            FieldType::Select => "a string",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(field_type: FieldType, options: &[&str]) -> CustomField {
+        CustomField {
+            id: Uuid::nil(),
+            name: "size".to_string(),
+            field_type,
+            options: options.iter().map(|option| option.to_string()).collect(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn parses_numbers_from_query_values() {
+        let number = field(FieldType::Number, &[]);
+        assert_eq!(number.parse("2.5"), Ok(serde_json::json!(2.5)));
+        assert_eq!(number.parse("-3"), Ok(serde_json::json!(-3.0)));
+        assert_eq!(
+            number.parse("ten"),
+            Err("size must be a number".to_string())
+        );
+        assert_eq!(
+            number.parse("NaN"),
+            Err("size must be a number".to_string())
+        );
+    }
+
+    #[test]
+    fn checks_dates_and_options() {
+        let date = field(FieldType::Date, &[]);
+        assert_eq!(
+            date.parse("2024-02-29"),
+            Ok(serde_json::json!("2024-02-29"))
+        );
+        assert!(date.parse("2023-02-29").is_err());
+        assert!(date.parse("29/02/2024").is_err());
+
+        let select = field(FieldType::Select, &["s", "m"]);
+        assert_eq!(select.parse("m"), Ok(serde_json::json!("m")));
+        assert_eq!(
+            select.parse("xl"),
+            Err("size must be one of: s, m".to_string())
+        );
+    }
+
+    #[test]
+    fn text_keeps_the_raw_value() {
+        let text = field(FieldType::Text, &[]);
+        assert_eq!(text.parse("a:b c"), Ok(serde_json::json!("a:b c")));
+        assert!(text.parse(&"x".repeat(MAX_TEXT_VALUE_LENGTH + 1)).is_err());
+    }
+}
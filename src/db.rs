@@ -1,13 +1,142 @@
-use sqlx::postgres::PgPoolOptions;
-use sqlx::{Pool, Postgres, Error as SqlxError};
+use crate::config::Config;
+use crate::error::AppError;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Error as SqlxError, Pool, Postgres};
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
 
 pub type DbPool = Pool<Postgres>;
 
+/// Migrations embedded at compile time; the schema this build expects
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Columns (name, Postgres data type) each table must have for the queries
+/// compiled into this build to work
+const EXPECTED_COLUMNS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "todos",
+        &[
+            ("id", "uuid"),
+            ("title", "text"),
+            ("description", "text"),
+            ("completed", "boolean"),
+            ("created_at", "timestamp with time zone"),
+            ("updated_at", "timestamp with time zone"),
+            ("deleted_at", "timestamp with time zone"),
+            ("merged_into", "uuid"),
+            ("tags", "ARRAY"),
+            ("completed_at", "timestamp with time zone"),
+            ("due_date", "timestamp with time zone"),
+            ("latitude", "double precision"),
+            ("longitude", "double precision"),
+            ("radius_m", "integer"),
+            ("estimate_minutes", "integer"),
+            ("custom_fields", "jsonb"),
+            ("metadata", "jsonb"),
+            ("draft", "boolean"),
+            ("start_date", "timestamp with time zone"),
+        ],
+    ),
+    (
+        "custom_fields",
+        &[
+            ("id", "uuid"),
+            ("name", "text"),
+            ("field_type", "text"),
+            ("options", "ARRAY"),
+            ("created_at", "timestamp with time zone"),
+        ],
+    ),
+    (
+        "todo_relations",
+        &[
+            ("id", "uuid"),
+            ("todo_id", "uuid"),
+            ("related_id", "uuid"),
+            ("kind", "text"),
+            ("created_at", "timestamp with time zone"),
+        ],
+    ),
+    (
+        "hook_subscriptions",
+        &[
+            ("id", "uuid"),
+            ("event", "text"),
+            ("target_url", "text"),
+            ("created_at", "timestamp with time zone"),
+        ],
+    ),
+    (
+        "rules",
+        &[
+            ("id", "uuid"),
+            ("name", "text"),
+            ("trigger", "text"),
+            ("conditions", "jsonb"),
+            ("actions", "jsonb"),
+            ("enabled", "boolean"),
+            ("created_at", "timestamp with time zone"),
+            ("updated_at", "timestamp with time zone"),
+        ],
+    ),
+    (
+        "rule_runs",
+        &[
+            ("id", "uuid"),
+            ("rule_id", "uuid"),
+            ("todo_id", "uuid"),
+            ("succeeded", "boolean"),
+            ("error", "text"),
+            ("ran_at", "timestamp with time zone"),
+        ],
+    ),
+    (
+        "todo_locks",
+        &[
+            ("todo_id", "uuid"),
+            ("holder", "text"),
+            ("expires_at", "timestamp with time zone"),
+        ],
+    ),
+    (
+        "tag_styles",
+        &[
+            ("tag", "text"),
+            ("icon", "text"),
+            ("color", "text"),
+            ("updated_at", "timestamp with time zone"),
+        ],
+    ),
+];
+
 /// Creates a new database connection pool
-pub async fn create_pool(database_url: &str) -> Result<DbPool, SqlxError> {
+pub async fn create_pool(config: &Config) -> Result<DbPool, SqlxError> {
+    let mut options: PgConnectOptions = config.database_url.parse()?;
+    if config.db_pgbouncer {
+        // In transaction pooling the server connection behind a client changes
+        // between transactions, so a statement prepared on one may not exist
+        // on the next
+        options = options.statement_cache_capacity(0);
+        if config.statement_timeout_ms > 0 {
+            tracing::warn!(
+                "DB_STATEMENT_TIMEOUT_MS is ignored with DB_PGBOUNCER; set statement_timeout on the database role instead"
+            );
+        }
+    } else if config.statement_timeout_ms > 0 {
+        // Sent as a startup parameter, so every connection the pool opens (and
+        // therefore every acquired connection) carries the limit
+        options = options.options([(
+            "statement_timeout",
+            format!("{}ms", config.statement_timeout_ms),
+        )]);
+    }
+
     PgPoolOptions::new()
-        .max_connections(5)
-        .connect(database_url)
+        .max_connections(config.db_max_connections)
+        .acquire_timeout(Duration::from_millis(config.db_acquire_timeout_ms))
+        .connect_with(options)
         .await
 }
 
@@ -17,4 +146,168 @@ pub async fn init_db(_pool: &DbPool) -> Result<(), SqlxError> {
     // Migrations should be run via sqlx-cli:
     // sqlx migrate run
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Builds or drops the unique index on open titles to match
+/// `UNIQUE_OPEN_TITLES`. Only one open (not completed, not deleted, not
+/// draft) todo may use a title while it exists. Open todos that already share
+/// a title are reported, never removed: the index isn't built until they have
+/// been merged, renamed or completed.
+pub async fn sync_open_title_index(pool: &DbPool, unique: bool) -> Result<(), AppError> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT to_regclass('idx_todos_open_title') IS NOT NULL as "exists!""#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if !unique {
+        if exists {
+            sqlx::query("DROP INDEX IF EXISTS idx_todos_open_title")
+                .execute(pool)
+                .await?;
+            tracing::info!("Dropped the unique index on open titles");
+        }
+        return Ok(());
+    }
+    if exists {
+        return Ok(());
+    }
+
+    let duplicates = sqlx::query!(
+        r#"
+        SELECT min(title) as "title!", array_agg(id ORDER BY created_at) as "ids!"
+        FROM todos
+        WHERE deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft
+        GROUP BY lower(title)
+        HAVING count(*) > 1
+        ORDER BY lower(title)
+        LIMIT 20
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if !duplicates.is_empty() {
+        let listed: Vec<String> = duplicates
+            .iter()
+            .map(|row| {
+                let ids: Vec<String> = row.ids.iter().map(Uuid::to_string).collect();
+                format!("\"{}\" ({})", row.title, ids.join(", "))
+            })
+            .collect();
+        return Err(AppError::Internal(format!(
+            "UNIQUE_OPEN_TITLES is set but open todos share titles; merge, rename or complete them first: {}",
+            listed.join("; ")
+        )));
+    }
+
+    sqlx::query(
+        r#"
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_todos_open_title
+            ON todos (lower(title)) WHERE deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    tracing::info!("Built the unique index on open titles");
+    Ok(())
+}
+
+/// Detects drift between the database schema and the schema this build was
+/// compiled against, so a mismatch fails at startup instead of surfacing
+/// later as runtime sqlx errors.
+///
+/// Checks that every embedded migration is applied with the same checksum,
+/// that the database has no migrations newer than this build, and that the
+/// expected tables and columns exist with the expected types.
+pub async fn verify_schema(pool: &DbPool) -> Result<(), AppError> {
+    let mut problems = Vec::new();
+
+    let history_exists = sqlx::query_scalar!(
+        r#"SELECT to_regclass('public._sqlx_migrations') IS NOT NULL as "exists!""#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if history_exists {
+        let applied: HashMap<i64, Vec<u8>> = sqlx::query!(
+            r#"SELECT version, checksum FROM _sqlx_migrations WHERE success ORDER BY version"#
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.version, row.checksum))
+        .collect();
+
+        for migration in MIGRATOR.iter() {
+            match applied.get(&migration.version) {
+                None => problems.push(format!(
+                    "migration {} ({}) has not been applied",
+                    migration.version, migration.description
+                )),
+                Some(checksum) if checksum.as_slice() != &*migration.checksum => {
+                    problems.push(format!(
+                        "migration {} ({}) was modified after being applied",
+                        migration.version, migration.description
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+
+        for version in applied.keys() {
+            if !MIGRATOR
+                .iter()
+                .any(|migration| migration.version == *version)
+            {
+                problems.push(format!(
+                    "database has migration {} which this build doesn't know about",
+                    version
+                ));
+            }
+        }
+    } else {
+        tracing::warn!("No _sqlx_migrations table found, skipping migration version check");
+    }
+
+    for (table, columns) in EXPECTED_COLUMNS {
+        let actual: HashMap<String, String> = sqlx::query!(
+            r#"
+            SELECT column_name as "column_name!", data_type as "data_type!"
+            FROM information_schema.columns
+            WHERE table_schema = current_schema() AND table_name = $1
+            "#,
+            table
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.column_name, row.data_type))
+        .collect();
+
+        if actual.is_empty() {
+            problems.push(format!("table {} does not exist", table));
+            continue;
+        }
+
+        for (column, data_type) in *columns {
+            match actual.get(*column) {
+                None => problems.push(format!("column {}.{} is missing", table, column)),
+                Some(actual_type) if actual_type != data_type => problems.push(format!(
+                    "column {}.{} has type {}, expected {}",
+                    table, column, actual_type, data_type
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Internal(format!(
+            "Database schema does not match this build (run `sqlx migrate run`?): {}",
+            problems.join("; ")
+        )))
+    }
+}
@@ -0,0 +1,67 @@
+use crate::build_info::BuildInfo;
+use crate::config::Config;
+use crate::events::EventBus;
+use crate::metrics::{Metrics, PoolStats};
+use crate::retention::{Retention, RetentionStatus};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// State of a running instance in one document, to attach to support tickets
+#[derive(Debug, Serialize)]
+pub struct Diagnostics {
+    pub build: BuildInfo,
+    /// Effective configuration, with tokens and the database password redacted
+    pub config: Value,
+    /// None when the pool isn't reported (e.g. in tests)
+    pub pool: Option<PoolStats>,
+    pub events: EventStats,
+    /// Background purges and the outcome of their latest run
+    pub retention: Vec<RetentionStatus>,
+    /// Compile-time features and the optional subsystems enabled by config
+    pub features: BTreeMap<&'static str, bool>,
+}
+
+/// Delivery of domain events to in-process consumers (hooks, rules, stats)
+#[derive(Debug, Serialize)]
+pub struct EventStats {
+    pub subscribers: usize,
+    /// Events the slowest subscriber hasn't received yet
+    pub backlog: usize,
+}
+
+pub fn collect(
+    config: &Config,
+    metrics: &Metrics,
+    retention: &Retention,
+    events: &dyn EventBus,
+) -> Diagnostics {
+    let retention = retention.statuses();
+    let features = BTreeMap::from([
+        ("chaos", cfg!(feature = "chaos") && config.chaos_enabled),
+        ("rate_limiting", config.rate_limit_requests > 0),
+        ("http2", config.http2),
+        ("internal_listener", config.internal_port.is_some()),
+        ("strict_json", config.strict_json),
+        ("log_bodies", config.log_bodies),
+        ("verify_schema", config.verify_schema),
+        ("unique_open_titles", config.unique_open_titles),
+        ("pgbouncer", config.db_pgbouncer),
+        ("feeds", config.feed_token.is_some()),
+        ("integrations", config.integration_token.is_some()),
+        ("retention", !retention.is_empty()),
+        ("shadowing", config.shadow_base_url.is_some()),
+    ]);
+
+    Diagnostics {
+        build: BuildInfo::current(),
+        config: serde_json::to_value(config).unwrap_or_default(),
+        pool: metrics.pool_stats(),
+        events: EventStats {
+            subscribers: events.subscribers(),
+            backlog: events.backlog(),
+        },
+        retention,
+        features,
+    }
+}
@@ -0,0 +1,173 @@
+use crate::config::Config;
+use crate::db::{create_pool, verify_schema, DbPool};
+use std::fmt;
+
+/// Outcome of a single doctor check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    /// Works, but something is probably not set up as intended
+    Warn,
+    Fail,
+    /// Not applicable to this deployment or build
+    Skip,
+}
+
+impl Status {
+    fn label(&self) -> &'static str {
+        match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+            Status::Skip => "SKIP",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+/// Result of `todo-api doctor`
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn push(&mut self, name: &'static str, status: Status, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name,
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    pub fn failures(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.status == Status::Fail)
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .checks
+            .iter()
+            .map(|check| check.name.len())
+            .max()
+            .unwrap_or(0);
+        for check in &self.checks {
+            writeln!(
+                f,
+                "[{}] {:width$}  {}",
+                check.status.label(),
+                check.name,
+                check.detail,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the environment the server would run in: configuration, database
+/// connectivity and schema, and the optional endpoints that are enabled
+pub async fn run() -> Report {
+    let mut report = Report::default();
+
+    let config = match Config::from_env() {
+        Ok(config) => {
+            report.push(
+                "configuration",
+                Status::Pass,
+                "environment variables are valid",
+            );
+            config
+        }
+        Err(e) => {
+            report.push("configuration", Status::Fail, e);
+            return report;
+        }
+    };
+
+    for (name, token, endpoints) in [
+        ("ADMIN_TOKEN", &config.admin_token, "/admin/*"),
+        (
+            "INTEGRATION_TOKEN",
+            &config.integration_token,
+            "/integrations/* and /rules",
+        ),
+        ("FEED_TOKEN", &config.feed_token, "the Atom feeds"),
+    ] {
+        if token.is_none() {
+            report.push(
+                "configuration",
+                Status::Warn,
+                format!("{} is unset, {} disabled", name, endpoints),
+            );
+        }
+    }
+
+    let pool = match create_pool(&config).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            report.push("database", Status::Fail, format!("cannot connect: {}", e));
+            return report;
+        }
+    };
+    match server_version(&pool).await {
+        Ok(version) => report.push(
+            "database",
+            Status::Pass,
+            format!("connected to PostgreSQL {}", version),
+        ),
+        Err(e) => {
+            report.push("database", Status::Fail, format!("query failed: {}", e));
+            return report;
+        }
+    }
+
+    match verify_schema(&pool).await {
+        Ok(()) => report.push(
+            "migrations",
+            Status::Pass,
+            "schema matches the migrations this build was compiled with",
+        ),
+        Err(e) => report.push("migrations", Status::Fail, e.to_string()),
+    }
+
+    match database_tls(&pool).await {
+        Ok(true) => report.push("database tls", Status::Pass, "connection is encrypted"),
+        Ok(false) => report.push(
+            "database tls",
+            Status::Warn,
+            "connection is not encrypted (add sslmode=require to DATABASE_URL)",
+        ),
+        Err(e) => report.push("database tls", Status::Warn, format!("cannot tell: {}", e)),
+    }
+
+    report.push("object storage", Status::Skip, "not used by this build");
+    report.push("redis", Status::Skip, "not used by this build");
+
+    report
+}
+
+async fn server_version(pool: &DbPool) -> Result<String, sqlx::Error> {
+    sqlx::query_scalar("SHOW server_version")
+        .fetch_one(pool)
+        .await
+}
+
+async fn database_tls(pool: &DbPool) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COALESCE(bool_or(ssl), false) FROM pg_stat_ssl WHERE pid = pg_backend_pid()",
+    )
+    .fetch_one(pool)
+    .await
+}
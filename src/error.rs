@@ -1,16 +1,25 @@
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::Error as SqlxError;
 use std::fmt;
+use ts_rs::TS;
+use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, TS)]
 pub struct ErrorResponse {
     pub status: String,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub details: Option<serde_json::Value>,
 }
 
 impl fmt::Display for ErrorResponse {
@@ -26,11 +35,13 @@ pub enum ErrorMessage {
     BadRequest,
     Unauthorized,
     PermissionDenied,
+    InvalidPathParam,
 
     // Todo specific errors
     TodoNotFound,
     TodoValidationError,
     TodoAlreadyCompleted,
+    TodoDuplicate,
 
     // Auth related (keep for future use)
     EmptyPassword,
@@ -45,9 +56,9 @@ pub enum ErrorMessage {
     UserNotAuthenticated,
 }
 
-impl ToString for ErrorMessage {
-    fn to_string(&self) -> String {
-        self.to_str().to_owned()
+impl fmt::Display for ErrorMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
     }
 }
 
@@ -60,9 +71,13 @@ impl ErrorMessage {
             ErrorMessage::PermissionDenied => {
                 "You are not allowed to perform this action".to_string()
             }
+            ErrorMessage::InvalidPathParam => "Invalid path parameter".to_string(),
             ErrorMessage::TodoNotFound => "Todo not found".to_string(),
             ErrorMessage::TodoValidationError => "Validation error".to_string(),
             ErrorMessage::TodoAlreadyCompleted => "Todo is already completed".to_string(),
+            ErrorMessage::TodoDuplicate => {
+                "An open todo with the same title already exists".to_string()
+            }
             ErrorMessage::WrongCredentials => "Email or password is wrong".to_string(),
             ErrorMessage::EmailExist => "A user with this email already exists".to_string(),
             ErrorMessage::UserNoLongerExist => {
@@ -90,6 +105,23 @@ pub enum AppError {
     NotFound(String),
     BadRequest(String),
     Unauthorized(String),
+    Conflict(String),
+    /// The todo collides with another open todo with the same title; `id` is
+    /// the todo being written (None when creating) and `existing_id` the other one
+    DuplicateTodo {
+        id: Option<Uuid>,
+        existing_id: Uuid,
+    },
+    /// A bulk change would touch `matched` todos, more than are changed
+    /// without the client confirming the count
+    ConfirmationRequired {
+        matched: u64,
+    },
+    /// The todo is being edited by `holder` until `expires_at`
+    Locked {
+        holder: String,
+        expires_at: DateTime<Utc>,
+    },
     DatabaseError(SqlxError),
     Internal(String),
 }
@@ -100,6 +132,16 @@ impl fmt::Display for AppError {
             AppError::NotFound(msg) => write!(f, "Not found: {}", msg),
             AppError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
             AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            AppError::DuplicateTodo { existing_id, .. } => {
+                write!(f, "Conflict: duplicate of todo {}", existing_id)
+            }
+            AppError::ConfirmationRequired { matched } => {
+                write!(f, "Confirmation required: {} todos match", matched)
+            }
+            AppError::Locked { holder, expires_at } => {
+                write!(f, "Locked by {} until {}", holder, expires_at)
+            }
             AppError::DatabaseError(e) => write!(f, "Database error: {}", e),
             AppError::Internal(msg) => write!(f, "Internal error: {}", msg),
         }
@@ -118,6 +160,10 @@ impl From<SqlxError> for AppError {
 pub struct HttpError {
     pub message: String,
     pub status: StatusCode,
+    pub code: Option<String>,
+    pub details: Option<serde_json::Value>,
+    /// Seconds sent in a `Retry-After` header
+    pub retry_after: Option<u64>,
 }
 
 impl HttpError {
@@ -125,13 +171,37 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status,
+            code: None,
+            details: None,
+            retry_after: None,
         }
     }
 
+    /// Attach a machine-readable error code to the response body
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Attach structured details (e.g. conflicting ids) to the response body
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Ask the client to retry after the given number of seconds
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
     pub fn server_error(message: impl Into<String>) -> Self {
         HttpError {
             message: message.into(),
             status: StatusCode::INTERNAL_SERVER_ERROR,
+            code: None,
+            details: None,
+            retry_after: None,
         }
     }
 
@@ -139,6 +209,9 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::BAD_REQUEST,
+            code: None,
+            details: None,
+            retry_after: None,
         }
     }
 
@@ -146,6 +219,9 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::NOT_FOUND,
+            code: None,
+            details: None,
+            retry_after: None,
         }
     }
 
@@ -153,6 +229,9 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::CONFLICT,
+            code: None,
+            details: None,
+            retry_after: None,
         }
     }
 
@@ -160,6 +239,9 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::UNAUTHORIZED,
+            code: None,
+            details: None,
+            retry_after: None,
         }
     }
 
@@ -167,9 +249,17 @@ impl HttpError {
         let json_response = Json(ErrorResponse {
             status: "fail".to_string(),
             message: self.message.clone(),
+            code: self.code,
+            details: self.details,
         });
 
-        (self.status, json_response).into_response()
+        let mut response = (self.status, json_response).into_response();
+        if let Some(seconds) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
     }
 }
 
@@ -191,6 +281,13 @@ impl IntoResponse for HttpError {
     }
 }
 
+/// SQLSTATE Postgres reports when a statement is cancelled, which is how
+/// `statement_timeout` ends a slow query
+const QUERY_CANCELED: &str = "57014";
+
+/// Seconds a client is asked to wait when no database connection was free
+const POOL_RETRY_AFTER_SECS: u64 = 1;
+
 // Convert AppError to HttpError for IntoResponse
 impl From<AppError> for HttpError {
     fn from(err: AppError) -> Self {
@@ -198,6 +295,46 @@ impl From<AppError> for HttpError {
             AppError::NotFound(msg) => HttpError::not_found(msg),
             AppError::BadRequest(msg) => HttpError::bad_request(msg),
             AppError::Unauthorized(msg) => HttpError::unauthorized(msg),
+            AppError::Conflict(msg) => HttpError::unique_constraint_violation(msg),
+            AppError::DuplicateTodo { id, existing_id } => {
+                HttpError::unique_constraint_violation(ErrorMessage::TodoDuplicate.to_string())
+                    .with_code("DUPLICATE_TODO")
+                    .with_details(serde_json::json!({ "id": id, "existing_id": existing_id }))
+            }
+            AppError::ConfirmationRequired { matched } => HttpError::new(
+                format!(
+                    "{} todos match; repeat the request with `X-Confirm-Count: {}` to change them",
+                    matched, matched
+                ),
+                StatusCode::PRECONDITION_REQUIRED,
+            )
+            .with_code("CONFIRMATION_REQUIRED")
+            .with_details(serde_json::json!({ "matched": matched })),
+            AppError::Locked { holder, expires_at } => HttpError::new(
+                format!(
+                    "Locked by {} until {} UTC",
+                    holder,
+                    expires_at.format("%H:%M")
+                ),
+                StatusCode::LOCKED,
+            )
+            .with_code("TODO_LOCKED")
+            .with_details(serde_json::json!({ "holder": holder, "expires_at": expires_at })),
+            AppError::DatabaseError(SqlxError::Database(e))
+                if e.code().as_deref() == Some(QUERY_CANCELED) =>
+            {
+                HttpError::new(
+                    "The database took too long to answer; try again or narrow the request",
+                    StatusCode::GATEWAY_TIMEOUT,
+                )
+                .with_code("STATEMENT_TIMEOUT")
+            }
+            AppError::DatabaseError(SqlxError::PoolTimedOut) => HttpError::new(
+                "The server is busy; no database connection became free in time",
+                StatusCode::SERVICE_UNAVAILABLE,
+            )
+            .with_code("DATABASE_BUSY")
+            .with_retry_after(POOL_RETRY_AFTER_SECS),
             AppError::DatabaseError(e) => HttpError::server_error(e.to_string()),
             AppError::Internal(msg) => HttpError::server_error(msg),
         }
@@ -211,3 +348,21 @@ impl IntoResponse for AppError {
         http_error.into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn locked_names_the_holder_and_expiry() {
+        let error: HttpError = AppError::Locked {
+            holder: "Alice".to_string(),
+            expires_at: Utc.with_ymd_and_hms(2024, 5, 1, 14, 32, 10).unwrap(),
+        }
+        .into();
+        assert_eq!(error.status, StatusCode::LOCKED);
+        assert_eq!(error.code.as_deref(), Some("TODO_LOCKED"));
+        assert_eq!(error.message, "Locked by Alice until 14:32 UTC");
+    }
+}
@@ -0,0 +1,109 @@
+use crate::models::Todo;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Default number of events buffered per subscriber before it starts lagging
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+/// Domain events emitted by the service layer after a successful mutation
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum TodoEvent {
+    Created(Todo),
+    Updated(Todo),
+    Completed(Todo),
+    Deleted {
+        id: Uuid,
+    },
+    Restored(Todo),
+    /// The todo's start date was reached
+    Activated(Todo),
+    /// `merged_id` was folded into `todo` and soft-deleted
+    Merged {
+        todo: Todo,
+        merged_id: Uuid,
+    },
+}
+
+impl TodoEvent {
+    /// Id of the todo the event is about
+    pub fn todo_id(&self) -> Uuid {
+        match self {
+            TodoEvent::Created(todo)
+            | TodoEvent::Updated(todo)
+            | TodoEvent::Completed(todo)
+            | TodoEvent::Restored(todo)
+            | TodoEvent::Activated(todo)
+            | TodoEvent::Merged { todo, .. } => todo.id,
+            TodoEvent::Deleted { id } => *id,
+        }
+    }
+
+    /// Stable, snake_case name of the event (matches the serialized `type`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            TodoEvent::Created(_) => "created",
+            TodoEvent::Updated(_) => "updated",
+            TodoEvent::Completed(_) => "completed",
+            TodoEvent::Deleted { .. } => "deleted",
+            TodoEvent::Restored(_) => "restored",
+            TodoEvent::Activated(_) => "activated",
+            TodoEvent::Merged { .. } => "merged",
+        }
+    }
+}
+
+/// Trait defining how domain events are distributed to subscribers
+pub trait EventBus: Send + Sync {
+    /// Publish an event; publishing never fails, even without subscribers
+    fn publish(&self, event: TodoEvent);
+
+    /// Subscribe to all events published after this call
+    fn subscribe(&self) -> broadcast::Receiver<TodoEvent>;
+
+    /// Number of current subscribers
+    fn subscribers(&self) -> usize;
+
+    /// Events not yet received by every subscriber, i.e. how far the
+    /// slowest one lags behind
+    fn backlog(&self) -> usize;
+}
+
+/// In-process EventBus backed by a tokio broadcast channel
+pub struct BroadcastEventBus {
+    sender: broadcast::Sender<TodoEvent>,
+}
+
+impl BroadcastEventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+}
+
+impl Default for BroadcastEventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+impl EventBus for BroadcastEventBus {
+    fn publish(&self, event: TodoEvent) {
+        tracing::debug!(event = event.name(), todo_id = %event.todo_id(), "Publishing todo event");
+        // An error only means nobody is listening right now
+        let _ = self.sender.send(event);
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<TodoEvent> {
+        self.sender.subscribe()
+    }
+
+    fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    fn backlog(&self) -> usize {
+        self.sender.len()
+    }
+}
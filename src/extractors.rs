@@ -0,0 +1,352 @@
+use crate::config::Config;
+use crate::error::{ErrorMessage, HttpError};
+use axum::{
+    body::Bytes,
+    extract::{
+        path::ErrorKind, rejection::PathRejection, FromRef, FromRequest, FromRequestParts, Query,
+        Request,
+    },
+    http::{header, request::Parts, StatusCode},
+};
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Drop-in replacement for `axum::extract::Path` that reports malformed
+/// path parameters (e.g. an id that is not a UUID) with the standard JSON
+/// error envelope instead of axum's plain-text rejection. The message names
+/// the parameter, since the same extractor reads todo, rule and field ids.
+#[derive(Debug)]
+pub struct Path<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Path(value)),
+            Err(PathRejection::FailedToDeserializePathParams(rejection)) => {
+                let detail = match rejection.kind() {
+                    ErrorKind::ParseErrorAtKey {
+                        key,
+                        value,
+                        expected_type,
+                    } => format!(
+                        "{} must be {}, got \"{}\"",
+                        key,
+                        type_label(expected_type),
+                        value
+                    ),
+                    ErrorKind::ParseErrorAtIndex {
+                        index,
+                        value,
+                        expected_type,
+                    } => format!(
+                        "parameter {} must be {}, got \"{}\"",
+                        index + 1,
+                        type_label(expected_type),
+                        value
+                    ),
+                    ErrorKind::DeserializeError {
+                        key,
+                        value,
+                        message,
+                    } => format!("{} is \"{}\", {}", key, value, message),
+                    ErrorKind::ParseError {
+                        value,
+                        expected_type,
+                    } => format!("must be {}, got \"{}\"", type_label(expected_type), value),
+                    _ => rejection.body_text(),
+                };
+                Err(HttpError::bad_request(format!(
+                    "{}: {}",
+                    ErrorMessage::InvalidPathParam,
+                    detail
+                ))
+                .with_code("INVALID_PATH_PARAM"))
+            }
+            Err(rejection) => Err(HttpError::server_error(rejection.body_text())),
+        }
+    }
+}
+
+/// How a path parameter's expected Rust type is described to clients
+fn type_label(type_name: &str) -> String {
+    match type_name.rsplit("::").next().unwrap_or(type_name) {
+        "Uuid" => "a UUID".to_string(),
+        name => format!("a {}", name),
+    }
+}
+
+/// Whether a mutating request is only a preview: `?dry_run=true` or
+/// `X-Dry-Run: true`. The request is validated and its writes run, then rolled
+/// back, so the response shows what would have happened.
+#[derive(Debug, Clone, Copy)]
+pub struct DryRun(pub bool);
+
+pub const DRY_RUN_HEADER: &str = "x-dry-run";
+
+#[derive(Debug, Deserialize)]
+struct DryRunParams {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+impl<S> FromRequestParts<S> for DryRun
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<DryRunParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| HttpError::bad_request(rejection.body_text()))?;
+        let header = match parts.headers.get(DRY_RUN_HEADER) {
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| HttpError::bad_request("X-Dry-Run must be true or false"))?,
+            None => false,
+        };
+        Ok(DryRun(params.dry_run || header))
+    }
+}
+
+/// Number of todos the client expects a bulk change to touch, from
+/// `X-Confirm-Count`; large bulk changes only go ahead when it matches
+#[derive(Debug, Clone, Copy)]
+pub struct ConfirmCount(pub Option<u64>);
+
+pub const CONFIRM_COUNT_HEADER: &str = "x-confirm-count";
+
+impl<S> FromRequestParts<S> for ConfirmCount
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.headers.get(CONFIRM_COUNT_HEADER) {
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .map(|count| ConfirmCount(Some(count)))
+                .ok_or_else(|| HttpError::bad_request("X-Confirm-Count must be a number")),
+            None => Ok(ConfirmCount(None)),
+        }
+    }
+}
+
+/// Who is making an edit, from `X-Lock-Holder` (trimmed), for checking it
+/// against the todo's edit lock
+#[derive(Debug, Clone)]
+pub struct LockHolder(pub Option<String>);
+
+pub const LOCK_HOLDER_HEADER: &str = "x-lock-holder";
+
+impl<S> FromRequestParts<S> for LockHolder
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.headers.get(LOCK_HOLDER_HEADER) {
+            Some(value) => value
+                .to_str()
+                .map(|holder| LockHolder(Some(holder.trim().to_string())))
+                .map_err(|_| HttpError::bad_request("X-Lock-Holder must be visible ASCII")),
+            None => Ok(LockHolder(None)),
+        }
+    }
+}
+
+/// JSON body extractor used instead of `axum::Json`.
+///
+/// Deserialization goes through serde_path_to_error so the error names the
+/// offending field and position, and every rejection uses the standard JSON
+/// error envelope: 415 for a missing/wrong content type, 400 for malformed
+/// JSON and 422 for well-formed JSON that doesn't match the expected shape.
+///
+/// When `Config::strict_json` is enabled, top-level fields the target type
+/// doesn't declare are rejected with a 422 listing the allowed fields instead
+/// of being silently ignored.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned,
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !has_json_content_type(&req) {
+            return Err(HttpError::new(
+                "Expected request with `Content-Type: application/json`",
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            )
+            .with_code("UNSUPPORTED_MEDIA_TYPE"));
+        }
+
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|rejection| HttpError::bad_request(rejection.body_text()))?;
+
+        if Arc::<Config>::from_ref(state).strict_json {
+            reject_unknown_fields::<T>(&bytes)?;
+        }
+
+        parse_json(&bytes).map(ValidatedJson)
+    }
+}
+
+fn has_json_content_type(req: &Request) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|mime| {
+            let mime = mime.trim();
+            mime.eq_ignore_ascii_case("application/json")
+                || (mime.starts_with("application/") && mime.ends_with("+json"))
+        })
+        .unwrap_or(false)
+}
+
+fn parse_json<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, HttpError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|err| {
+        let path = err.path().to_string();
+        let inner = err.inner();
+
+        if inner.is_syntax() || inner.is_eof() {
+            return malformed_json(inner);
+        }
+
+        let message = if path == "." {
+            format!("Invalid JSON body: {}", inner)
+        } else {
+            format!("Invalid value for field `{}`: {}", path, inner)
+        };
+        HttpError::new(message, StatusCode::UNPROCESSABLE_ENTITY).with_code("INVALID_JSON_BODY")
+    })?;
+
+    // Only whitespace may follow the value
+    deserializer.end().map_err(|err| malformed_json(&err))?;
+    Ok(value)
+}
+
+fn malformed_json(err: &serde_json::Error) -> HttpError {
+    HttpError::bad_request(format!("Malformed JSON body: {}", err)).with_code("MALFORMED_JSON")
+}
+
+/// Reject top-level object keys that `T` doesn't declare as fields
+fn reject_unknown_fields<T: DeserializeOwned>(bytes: &[u8]) -> Result<(), HttpError> {
+    let Some(allowed) = struct_fields::<T>() else {
+        return Ok(());
+    };
+    // Malformed bodies are reported by parse_json with position information
+    let Ok(serde_json::Value::Object(object)) = serde_json::from_slice(bytes) else {
+        return Ok(());
+    };
+
+    let unknown: Vec<&str> = object
+        .keys()
+        .map(String::as_str)
+        .filter(|key| !allowed.contains(key))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+
+    Err(HttpError::new(
+        format!(
+            "Unknown field(s): {}. Allowed fields: {}",
+            unknown.join(", "),
+            allowed.join(", ")
+        ),
+        StatusCode::UNPROCESSABLE_ENTITY,
+    )
+    .with_code("UNKNOWN_FIELD"))
+}
+
+/// Field names a derived `Deserialize` struct accepts, or None for non-structs
+fn struct_fields<T: DeserializeOwned>() -> Option<&'static [&'static str]> {
+    let mut fields = None;
+    let _ = T::deserialize(FieldsDeserializer {
+        fields: &mut fields,
+    });
+    fields
+}
+
+/// Deserializer that records the field list serde passes to
+/// `deserialize_struct` and then bails out without producing a value
+struct FieldsDeserializer<'a> {
+    fields: &'a mut Option<&'static [&'static str]>,
+}
+
+impl<'de> de::Deserializer<'de> for FieldsDeserializer<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.fields = Some(fields);
+        Err(de::Error::custom("fields captured"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Body {
+        title: String,
+    }
+
+    #[test]
+    fn rejects_data_after_the_body() {
+        let error = parse_json::<Body>(br#"{"title":"a"} garbage"#).unwrap_err();
+        assert_eq!(error.code.as_deref(), Some("MALFORMED_JSON"));
+        assert!(parse_json::<Body>(br#"{"title":"a"}{"title":"b"}"#).is_err());
+    }
+
+    #[test]
+    fn accepts_surrounding_whitespace() {
+        let body = parse_json::<Body>(b" {\"title\":\"a\"}\r\n").unwrap();
+        assert_eq!(body.title, "a");
+    }
+
+    #[test]
+    fn names_the_invalid_field() {
+        let error = parse_json::<Body>(br#"{"title":1}"#).unwrap_err();
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(error.message.contains("`title`"), "{}", error.message);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::models::Todo;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt::Write;
+
+/// Content type of Atom documents
+pub const ATOM_CONTENT_TYPE: &str = "application/atom+xml; charset=utf-8";
+
+/// Render the recently completed todos (newest first) as an Atom feed,
+/// optionally scoped to a single tag
+pub fn completed_feed(todos: &[Todo], tag: Option<&str>) -> String {
+    let (id, title) = match tag {
+        Some(tag) => (
+            format!("urn:axum-todo:feeds:completed:{}", tag),
+            format!("Recently completed todos tagged \"{}\"", tag),
+        ),
+        None => (
+            "urn:axum-todo:feeds:completed".to_string(),
+            "Recently completed todos".to_string(),
+        ),
+    };
+    let updated = todos.first().map(completed_at).unwrap_or_else(Utc::now);
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(out, "  <id>{}</id>", escape(&id));
+    let _ = writeln!(out, "  <title>{}</title>", escape(&title));
+    let _ = writeln!(out, "  <updated>{}</updated>", timestamp(updated));
+    out.push_str("  <author><name>Axum Todo API</name></author>\n");
+
+    for todo in todos {
+        out.push_str("  <entry>\n");
+        let _ = writeln!(out, "    <id>urn:uuid:{}</id>", todo.id);
+        let _ = writeln!(out, "    <title>{}</title>", escape(&todo.title));
+        let _ = writeln!(
+            out,
+            "    <updated>{}</updated>",
+            timestamp(completed_at(todo))
+        );
+        let _ = writeln!(
+            out,
+            "    <published>{}</published>",
+            timestamp(todo.created_at)
+        );
+        for tag in &todo.tags {
+            let _ = writeln!(out, "    <category term=\"{}\"/>", escape(tag));
+        }
+        if let Some(description) = &todo.description {
+            let _ = writeln!(
+                out,
+                "    <content type=\"text\">{}</content>",
+                escape(description)
+            );
+        }
+        out.push_str("  </entry>\n");
+    }
+
+    out.push_str("</feed>\n");
+    out
+}
+
+/// Completion time of a todo, falling back to its last update for rows
+/// completed before completion times were recorded
+fn completed_at(todo: &Todo) -> DateTime<Utc> {
+    todo.completed_at.unwrap_or(todo.updated_at)
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
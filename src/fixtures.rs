@@ -0,0 +1,195 @@
+use crate::db::DbPool;
+use crate::error::AppError;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::postgres::PgPoolCopyExt;
+use uuid::Uuid;
+
+/// Rows sent to the server per COPY chunk
+const CHUNK_ROWS: usize = 10_000;
+
+const VERBS: &[&str] = &[
+    "Write", "Review", "Fix", "Plan", "Call", "Email", "Update", "Prepare", "Clean", "Book",
+    "Order", "Schedule", "Research", "Draft", "Renew", "Pay",
+];
+
+const OBJECTS: &[&str] = &[
+    "quarterly report",
+    "release notes",
+    "dentist appointment",
+    "team offsite",
+    "garage",
+    "login bug",
+    "insurance policy",
+    "conference talk",
+    "grocery list",
+    "invoice",
+    "onboarding docs",
+    "birthday present",
+    "flight to Berlin",
+    "database migration",
+    "tax return",
+    "car service",
+];
+
+const TAGS: &[&str] = &[
+    "work", "home", "urgent", "errands", "finance", "health", "travel", "someday", "reading",
+    "family",
+];
+
+/// Shape of the generated data
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub todos: u64,
+    /// Share of todos that are completed, between 0 and 1
+    pub completed_ratio: f64,
+    /// Share of todos with a due date, between 0 and 1
+    pub due_ratio: f64,
+    /// Same seed, same data (apart from the ids)
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            todos: 10_000,
+            completed_ratio: 0.4,
+            due_ratio: 0.6,
+            seed: 42,
+        }
+    }
+}
+
+/// Bulk-insert synthetic todos with COPY, created over the past year with
+/// varied titles, tags, due dates and completion. Returns the rows inserted.
+pub async fn generate(pool: &DbPool, options: &Options) -> Result<u64, AppError> {
+    if !(0.0..=1.0).contains(&options.completed_ratio) || !(0.0..=1.0).contains(&options.due_ratio)
+    {
+        return Err(AppError::BadRequest(
+            "ratios must be between 0 and 1".to_string(),
+        ));
+    }
+
+    let mut rng = SplitMix64(options.seed);
+    // Open titles are unique, so every run gets its own suffix
+    let run = &Uuid::new_v4().simple().to_string()[..8];
+    let now = Utc::now();
+
+    let mut copy = pool
+        .copy_in_raw(
+            "COPY todos (title, description, completed, created_at, updated_at, completed_at, \
+             due_date, tags, estimate_minutes) FROM STDIN WITH (FORMAT csv)",
+        )
+        .await?;
+    let mut written = 0;
+    while written < options.todos {
+        let rows = (options.todos - written).min(CHUNK_ROWS as u64);
+        let chunk = match csv_chunk(&mut rng, options, now, run, written..written + rows) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                copy.abort(e.clone()).await?;
+                return Err(AppError::Internal(e));
+            }
+        };
+        copy.send(chunk).await?;
+        written += rows;
+        tracing::info!("Generated {}/{} todos", written, options.todos);
+    }
+    let inserted = copy.finish().await?;
+
+    // Fresh statistics, so plans and estimated counts reflect the new rows
+    sqlx::query("ANALYZE todos").execute(pool).await?;
+    Ok(inserted)
+}
+
+fn csv_chunk(
+    rng: &mut SplitMix64,
+    options: &Options,
+    now: DateTime<Utc>,
+    run: &str,
+    numbers: std::ops::Range<u64>,
+) -> Result<Vec<u8>, String> {
+    let mut chunk = csv::Writer::from_writer(Vec::new());
+    for n in numbers {
+        chunk
+            .write_record(todo_record(rng, options, now, run, n))
+            .map_err(|e| e.to_string())?;
+    }
+    chunk.into_inner().map_err(|e| e.to_string())
+}
+
+/// One CSV row in the column order of the COPY statement
+fn todo_record(
+    rng: &mut SplitMix64,
+    options: &Options,
+    now: DateTime<Utc>,
+    run: &str,
+    n: u64,
+) -> [String; 9] {
+    let title = format!("{} {} ({}-{})", rng.pick(VERBS), rng.pick(OBJECTS), run, n);
+    let description = if rng.chance(0.3) {
+        format!(
+            "Follow up on the {} before the end of the week.",
+            rng.pick(OBJECTS)
+        )
+    } else {
+        String::new()
+    };
+
+    let created_at = now - Duration::seconds(rng.below(365 * 24 * 3600) as i64);
+    let age = (now - created_at).num_seconds().max(1) as u64;
+    let completed = rng.chance(options.completed_ratio);
+    let completed_at = completed.then(|| created_at + Duration::seconds(rng.below(age) as i64));
+    let updated_at =
+        completed_at.unwrap_or_else(|| created_at + Duration::seconds(rng.below(age) as i64));
+    // Due dates from a month ago (overdue) to two months ahead
+    let due_date = rng
+        .chance(options.due_ratio)
+        .then(|| now + Duration::hours(rng.below(90 * 24) as i64 - 30 * 24));
+
+    let mut tags: Vec<&str> = (0..rng.below(4)).map(|_| rng.pick(TAGS)).collect();
+    tags.sort_unstable();
+    tags.dedup();
+    let estimate = if rng.chance(0.5) {
+        (15 * (1 + rng.below(16))).to_string()
+    } else {
+        String::new()
+    };
+
+    [
+        title,
+        description,
+        completed.to_string(),
+        created_at.to_rfc3339(),
+        updated_at.to_rfc3339(),
+        completed_at.map(|at| at.to_rfc3339()).unwrap_or_default(),
+        due_date.map(|at| at.to_rfc3339()).unwrap_or_default(),
+        format!("{{{}}}", tags.join(",")),
+        estimate,
+    ]
+}
+
+/// Small seedable generator; the data only has to look varied, and a fixed
+/// seed keeps runs comparable
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn chance(&mut self, ratio: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < ratio
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
@@ -1,13 +1,40 @@
+use crate::auth::{self, AdminAuth, IntegrationAuth};
+use crate::backup::{Backup, RestoreReport};
+use crate::build_info::{BuildInfo, VersionInfo};
+use crate::config::Config;
+use crate::custom_fields::{CreateCustomField, CustomField};
+use crate::diagnostics::{self, Diagnostics};
 use crate::error::AppError;
-use crate::models::{CreateTodo, TodoResponse, UpdateTodo};
-use crate::repository::TodoRepository;
+use crate::extractors::{ConfirmCount, DryRun, LockHolder, Path, ValidatedJson};
+use crate::feed;
+use crate::http_cache::{CachePolicy, IfModifiedSince};
+use crate::import::Provider;
+use crate::integrations::{HookSubscription, SubscribeHook, Trigger, TriggerItem};
+use crate::markdown;
+use crate::metrics::Metrics;
+use crate::models::{
+    Aggregate, BulkResult, BulkTagRequest, Calendar, CountMode, CreateRelation, CreateTodo,
+    GroupBy, ImportReport, LockTodo, Metric, NearbyTodo, OnConflict, RelationKind, RenderedTodo,
+    SetTagStyle, TagStyle, TagUsage, TodoLock, TodoQuery, TodoResponse, UnlockTodo, UpdateTodo,
+    WeeklyReview, Workload,
+};
+use crate::rate_limit::{RateLimitStatus, RateLimiter};
+use crate::retention::{Retention, RetentionStatus};
+use crate::rules::{CreateRule, Rule, RuleRun, UpdateRule};
+use crate::service::TodoService;
+use crate::shadow::{Shadow, ShadowStatus};
+use crate::stats::Streaks;
+use crate::usage::{ApiUsage, RateLimitQuota, UsageTracker};
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    Json,
+    body::Bytes,
+    extract::{ConnectInfo, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
+use chrono::NaiveDate;
 use serde::Deserialize;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -15,59 +42,698 @@ use uuid::Uuid;
 #[derive(Debug, Deserialize)]
 pub struct TodoFilter {
     completed: Option<bool>,
+    tag: Option<String>,
+    field: Option<String>,
+    /// List drafts instead of published todos
+    #[serde(default)]
+    draft: bool,
+    /// Leave out todos that haven't started yet
+    #[serde(default)]
+    active_only: bool,
+    page: Option<u32>,
+    per_page: Option<u32>,
+    count: Option<CountMode>,
+}
+
+/// Alternative representations of a todo's description
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFormat {
+    Html,
+}
+
+/// Query parameters for fetching a single todo
+#[derive(Debug, Deserialize)]
+pub struct GetParams {
+    render: Option<RenderFormat>,
+}
+
+/// Query parameters for aggregates; `group_by` and `metric` are parsed by
+/// hand so unsupported values get a descriptive error
+#[derive(Debug, Deserialize)]
+pub struct AggregateParams {
+    group_by: String,
+    metric: Option<String>,
+    completed: Option<bool>,
+    tag: Option<String>,
+    field: Option<String>,
+    #[serde(default)]
+    active_only: bool,
+}
+
+/// Query parameters for unlinking todos
+#[derive(Debug, Deserialize)]
+pub struct UnlinkParams {
+    kind: Option<RelationKind>,
+}
+
+/// Query parameters for nearby todos; `radius` is in meters
+#[derive(Debug, Deserialize)]
+pub struct NearbyParams {
+    lat: f64,
+    lng: f64,
+    radius: i32,
+}
+
+/// Query parameters for the workload report (UTC dates, inclusive)
+#[derive(Debug, Deserialize)]
+pub struct WorkloadParams {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+/// Query parameters for the calendar view (UTC dates, inclusive)
+#[derive(Debug, Deserialize)]
+pub struct CalendarParams {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+/// Query parameters for the stale todo list
+#[derive(Debug, Deserialize)]
+pub struct StaleParams {
+    /// How long a todo must have gone without an update, e.g. `60d`
+    inactive_for: Option<String>,
+    limit: Option<u32>,
 }
 
-/// Create a new todo
+/// Query parameters for the weekly review
+#[derive(Debug, Deserialize)]
+pub struct ReviewParams {
+    /// ISO week such as `2024-W23`; the current week when omitted
+    week: Option<String>,
+}
+
+/// Query parameters for tag autocomplete
+#[derive(Debug, Deserialize)]
+pub struct TagFilter {
+    starts_with: Option<String>,
+    limit: Option<u32>,
+}
+
+/// Query parameters for the Atom feeds (readers can't send auth headers)
+#[derive(Debug, Deserialize)]
+pub struct FeedParams {
+    token: Option<String>,
+    tag: Option<String>,
+}
+
+/// Query parameters for creating a todo
+#[derive(Debug, Deserialize)]
+pub struct CreateParams {
+    #[serde(default)]
+    on_conflict: OnConflict,
+}
+
+/// Query parameters for polling triggers
+#[derive(Debug, Deserialize)]
+pub struct TriggerParams {
+    tag: Option<String>,
+}
+
+/// Create a new todo (a dry run answers 200 with the todo it would create,
+/// and so does `?on_conflict=return_existing` with the todo already open
+/// under the title)
 pub async fn create_todo(
-    State(repo): State<Arc<dyn TodoRepository>>,
-    Json(payload): Json<CreateTodo>,
+    State(service): State<Arc<TodoService>>,
+    DryRun(dry_run): DryRun,
+    Query(params): Query<CreateParams>,
+    ValidatedJson(payload): ValidatedJson<CreateTodo>,
 ) -> Result<impl IntoResponse, AppError> {
-    let todo = repo.create(payload).await?;
-    Ok((StatusCode::CREATED, Json(todo)))
+    let (todo, created) = match params.on_conflict {
+        OnConflict::Fail => (service.create(payload, dry_run).await?, true),
+        OnConflict::ReturnExisting => service.create_or_existing(payload, dry_run).await?,
+    };
+    let status = if created && !dry_run {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(todo)))
 }
 
-/// List all todos with optional filtering
+/// List todos with optional filtering. Asking for a page (`page`,
+/// `per_page` or `count`) returns a `{data, meta}` page; otherwise every
+/// matching todo is returned as a bare array.
 pub async fn list_todos(
-    State(repo): State<Arc<dyn TodoRepository>>,
+    State(service): State<Arc<TodoService>>,
+    State(config): State<Arc<Config>>,
+    rate_limit: Option<Extension<RateLimitStatus>>,
+    if_modified_since: IfModifiedSince,
     Query(filter): Query<TodoFilter>,
-) -> Result<Json<Vec<TodoResponse>>, AppError> {
-    let todos = repo.list(filter.completed).await?;
-    Ok(Json(todos))
+) -> Result<Response, AppError> {
+    // Todos become active when their start date passes, without a write
+    let last_modified = if filter.active_only {
+        None
+    } else {
+        service.last_modified().await?
+    };
+    let cache = CachePolicy::new(
+        last_modified,
+        config.cache_max_age_secs,
+        service.clock().now(),
+    );
+    if cache.is_fresh(if_modified_since) {
+        return Ok(cache.not_modified());
+    }
+    let query = TodoQuery {
+        completed: filter.completed,
+        tag: filter.tag,
+        field: filter.field,
+        draft: filter.draft,
+        active_only: filter.active_only,
+        ..TodoQuery::default()
+    };
+    if filter.page.is_none() && filter.per_page.is_none() && filter.count.is_none() {
+        let todos = service.list_unpaged(query).await?;
+        return Ok(cache.apply(Json(todos).into_response()));
+    }
+
+    let mut todos = service
+        .list(
+            query,
+            filter.page,
+            filter.per_page,
+            filter.count.unwrap_or_default(),
+        )
+        .await?;
+    if let Some(Extension(status)) = rate_limit {
+        todos.meta.quota_warnings.extend(status.warning());
+    }
+    Ok(cache.apply(Json(todos).into_response()))
 }
 
-/// Get a specific todo by ID
+/// Get a specific todo by ID with its related todos (`?render=html` adds
+/// the rendered description)
 pub async fn get_todo(
-    State(repo): State<Arc<dyn TodoRepository>>,
+    State(service): State<Arc<TodoService>>,
+    State(config): State<Arc<Config>>,
     Path(id): Path<Uuid>,
-) -> Result<Json<TodoResponse>, AppError> {
-    let todo = repo.get(id).await?;
-    Ok(Json(todo))
+    if_modified_since: IfModifiedSince,
+    Query(params): Query<GetParams>,
+) -> Result<Response, AppError> {
+    // Read before the todo, so a concurrent write can only make it too old
+    let cache = CachePolicy::new(
+        service.last_modified().await?,
+        config.cache_max_age_secs,
+        service.clock().now(),
+    );
+    let todo = service.detail(id).await?;
+    if cache.is_fresh(if_modified_since) {
+        return Ok(cache.not_modified());
+    }
+    let response = match params.render {
+        Some(RenderFormat::Html) => {
+            let rendered = RenderedTodo {
+                description_html: todo.todo.description.as_deref().map(markdown::render_html),
+                todo,
+            };
+            Json(rendered).into_response()
+        }
+        None => Json(todo).into_response(),
+    };
+    Ok(cache.apply(response))
+}
+
+/// Link a todo to another one
+pub async fn link_todo(
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+    ValidatedJson(payload): ValidatedJson<CreateRelation>,
+) -> Result<impl IntoResponse, AppError> {
+    let relation = service.link(id, payload).await?;
+    Ok((StatusCode::CREATED, Json(relation)))
+}
+
+/// Remove the links between two todos
+pub async fn unlink_todo(
+    State(service): State<Arc<TodoService>>,
+    Path((id, related_id)): Path<(Uuid, Uuid)>,
+    Query(params): Query<UnlinkParams>,
+) -> Result<StatusCode, AppError> {
+    service.unlink(id, related_id, params.kind).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-/// Update a todo (partial update)
+/// Update a todo (partial update); 423 while someone else holds its edit lock
 pub async fn update_todo(
-    State(repo): State<Arc<dyn TodoRepository>>,
+    State(service): State<Arc<TodoService>>,
     Path(id): Path<Uuid>,
-    Json(payload): Json<UpdateTodo>,
+    DryRun(dry_run): DryRun,
+    LockHolder(holder): LockHolder,
+    ValidatedJson(payload): ValidatedJson<UpdateTodo>,
 ) -> Result<Json<TodoResponse>, AppError> {
-    let todo = repo.update(id, payload).await?;
+    let todo = service
+        .update(id, payload, holder.as_deref(), dry_run)
+        .await?;
     Ok(Json(todo))
 }
 
-/// Delete a todo
+/// Take or extend an edit lock on a todo
+pub async fn lock_todo(
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+    ValidatedJson(payload): ValidatedJson<LockTodo>,
+) -> Result<Json<TodoLock>, AppError> {
+    let lock = service.lock(id, payload).await?;
+    Ok(Json(lock))
+}
+
+/// Release an edit lock
+pub async fn unlock_todo(
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+    ValidatedJson(payload): ValidatedJson<UnlockTodo>,
+) -> Result<StatusCode, AppError> {
+    service.unlock(id, payload).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Delete a todo (soft delete)
 pub async fn delete_todo(
-    State(repo): State<Arc<dyn TodoRepository>>,
+    State(service): State<Arc<TodoService>>,
     Path(id): Path<Uuid>,
+    LockHolder(holder): LockHolder,
 ) -> Result<StatusCode, AppError> {
-    repo.delete(id).await?;
+    service.delete(id, holder.as_deref()).await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 /// Mark a todo as completed
 pub async fn mark_completed(
-    State(repo): State<Arc<dyn TodoRepository>>,
+    State(service): State<Arc<TodoService>>,
     Path(id): Path<Uuid>,
+    LockHolder(holder): LockHolder,
 ) -> Result<Json<TodoResponse>, AppError> {
-    let todo = repo.mark_completed(id).await?;
+    let todo = service.mark_completed(id, holder.as_deref()).await?;
     Ok(Json(todo))
 }
+
+/// Publish a draft
+pub async fn publish_todo(
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+    LockHolder(holder): LockHolder,
+) -> Result<Json<TodoResponse>, AppError> {
+    let todo = service.publish(id, holder.as_deref()).await?;
+    Ok(Json(todo))
+}
+
+/// Restore a deleted todo
+pub async fn restore_todo(
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<TodoResponse>, AppError> {
+    let todo = service.restore(id).await?;
+    Ok(Json(todo))
+}
+
+/// Merge another todo into this one
+pub async fn merge_todo(
+    State(service): State<Arc<TodoService>>,
+    Path((id, other_id)): Path<(Uuid, Uuid)>,
+    LockHolder(holder): LockHolder,
+) -> Result<Json<TodoResponse>, AppError> {
+    let todo = service.merge(id, other_id, holder.as_deref()).await?;
+    Ok(Json(todo))
+}
+
+/// Add or remove a tag across every todo matching a filter
+pub async fn bulk_tag(
+    State(service): State<Arc<TodoService>>,
+    DryRun(dry_run): DryRun,
+    LockHolder(holder): LockHolder,
+    ValidatedJson(payload): ValidatedJson<BulkTagRequest>,
+) -> Result<Json<BulkResult>, AppError> {
+    let result = service
+        .bulk_tag(payload, holder.as_deref(), dry_run)
+        .await?;
+    Ok(Json(result))
+}
+
+/// Apply a partial update to every todo matching the list filters
+/// (`?completed=`, `?tag=`, `?field=`) in one statement
+pub async fn bulk_update(
+    State(service): State<Arc<TodoService>>,
+    DryRun(dry_run): DryRun,
+    ConfirmCount(confirm): ConfirmCount,
+    LockHolder(holder): LockHolder,
+    Query(filter): Query<TodoQuery>,
+    ValidatedJson(payload): ValidatedJson<UpdateTodo>,
+) -> Result<Json<BulkResult>, AppError> {
+    let result = service
+        .bulk_update(filter, payload, confirm, holder.as_deref(), dry_run)
+        .await?;
+    Ok(Json(result))
+}
+
+/// Group todos by tag, status or day and compute a metric per group
+pub async fn aggregate_todos(
+    State(service): State<Arc<TodoService>>,
+    Query(params): Query<AggregateParams>,
+) -> Result<Json<Aggregate>, AppError> {
+    let group_by: GroupBy = params.group_by.parse()?;
+    let metric = match params.metric.as_deref() {
+        Some(metric) => metric.parse()?,
+        None => Metric::Count,
+    };
+    let query = TodoQuery {
+        completed: params.completed,
+        tag: params.tag,
+        field: params.field,
+        active_only: params.active_only,
+        ..TodoQuery::default()
+    };
+    let aggregate = service.aggregate(query, group_by, metric).await?;
+    Ok(Json(aggregate))
+}
+
+/// Open todos to be reminded of around a location, nearest first
+pub async fn nearby_todos(
+    State(service): State<Arc<TodoService>>,
+    Query(params): Query<NearbyParams>,
+) -> Result<Json<Vec<NearbyTodo>>, AppError> {
+    let todos = service
+        .nearby(params.lat, params.lng, params.radius)
+        .await?;
+    Ok(Json(todos))
+}
+
+/// Todos bucketed by due day, for month and week views
+pub async fn todo_calendar(
+    State(service): State<Arc<TodoService>>,
+    Query(params): Query<CalendarParams>,
+) -> Result<Json<Calendar>, AppError> {
+    let calendar = service.calendar(params.from, params.to).await?;
+    Ok(Json(calendar))
+}
+
+/// Open todos nobody has touched for a while, least recently updated first
+pub async fn stale_todos(
+    State(service): State<Arc<TodoService>>,
+    Query(params): Query<StaleParams>,
+) -> Result<Json<Vec<TodoResponse>>, AppError> {
+    let todos = service
+        .stale(params.inactive_for.as_deref(), params.limit)
+        .await?;
+    Ok(Json(todos))
+}
+
+/// List tags with usage counts, most used first (for typeahead)
+pub async fn list_tags(
+    State(service): State<Arc<TodoService>>,
+    Query(filter): Query<TagFilter>,
+) -> Result<Json<Vec<TagUsage>>, AppError> {
+    let tags = service
+        .tag_suggestions(filter.starts_with, filter.limit)
+        .await?;
+    Ok(Json(tags))
+}
+
+/// Set a tag's icon and color
+pub async fn set_tag_style(
+    State(service): State<Arc<TodoService>>,
+    Path(tag): Path<String>,
+    ValidatedJson(payload): ValidatedJson<SetTagStyle>,
+) -> Result<Json<TagStyle>, AppError> {
+    let style = service.set_tag_style(&tag, payload).await?;
+    Ok(Json(style))
+}
+
+/// Atom feed of recently completed todos (token-authenticated)
+pub async fn completed_feed(
+    State(service): State<Arc<TodoService>>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<FeedParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let expected = config
+        .feed_token
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("Feeds are not enabled".to_string()))?;
+    if !auth::token_matches(params.token.as_deref(), expected) {
+        return Err(AppError::Unauthorized("Invalid feed token".to_string()));
+    }
+
+    let todos = service.recently_completed(params.tag.clone()).await?;
+    Ok((
+        [(header::CONTENT_TYPE, feed::ATOM_CONTENT_TYPE)],
+        feed::completed_feed(&todos, params.tag.as_deref()),
+    ))
+}
+
+/// Import todos from another service's export file
+pub async fn import_todos(
+    State(service): State<Arc<TodoService>>,
+    Path(provider): Path<String>,
+    DryRun(dry_run): DryRun,
+    body: Bytes,
+) -> Result<Json<ImportReport>, AppError> {
+    let provider: Provider = provider.parse()?;
+    let items = provider.parse(&body)?;
+    let report = service.import(provider.name(), items, dry_run).await?;
+    Ok(Json(report))
+}
+
+/// Retention policies and the outcome of their latest run (admin only)
+pub async fn get_retention(
+    _admin: AdminAuth,
+    State(retention): State<Arc<Retention>>,
+) -> Json<Vec<RetentionStatus>> {
+    Json(retention.statuses())
+}
+
+/// Version and commit of the running build
+pub async fn get_version() -> Json<VersionInfo> {
+    Json(BuildInfo::current().into())
+}
+
+/// Build, configuration and runtime state for support tickets (admin only)
+pub async fn get_diagnostics(
+    _admin: AdminAuth,
+    State(service): State<Arc<TodoService>>,
+    State(config): State<Arc<Config>>,
+    State(metrics): State<Arc<Metrics>>,
+    State(retention): State<Arc<Retention>>,
+) -> Json<Diagnostics> {
+    Json(diagnostics::collect(
+        &config,
+        &metrics,
+        &retention,
+        service.events().as_ref(),
+    ))
+}
+
+/// Traffic shadowing counters and recent mismatches (admin only)
+pub async fn get_shadow(
+    _admin: AdminAuth,
+    State(shadow): State<Option<Arc<Shadow>>>,
+) -> Result<Json<ShadowStatus>, AppError> {
+    let shadow =
+        shadow.ok_or_else(|| AppError::NotFound("Traffic shadowing is disabled".to_string()))?;
+    Ok(Json(shadow.status()))
+}
+
+/// Download a full backup of the database (admin only)
+pub async fn create_backup(
+    _admin: AdminAuth,
+    State(service): State<Arc<TodoService>>,
+) -> Result<impl IntoResponse, AppError> {
+    let backup = service.create_backup().await?;
+    let disposition = format!(
+        "attachment; filename=\"todos-backup-{}.json\"",
+        backup.created_at.format("%Y%m%dT%H%M%SZ")
+    );
+    Ok(([(header::CONTENT_DISPOSITION, disposition)], Json(backup)))
+}
+
+/// Restore a backup into an empty database (admin only)
+pub async fn restore_backup(
+    _admin: AdminAuth,
+    State(service): State<Arc<TodoService>>,
+    ValidatedJson(backup): ValidatedJson<Backup>,
+) -> Result<impl IntoResponse, AppError> {
+    let report: RestoreReport = service.restore_backup(backup).await?;
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+/// Poll a trigger: its latest occurrences, newest first, each with a
+/// deduplication `id` (Zapier/IFTTT polling)
+pub async fn poll_trigger(
+    _auth: IntegrationAuth,
+    State(service): State<Arc<TodoService>>,
+    Path(trigger): Path<String>,
+    Query(params): Query<TriggerParams>,
+) -> Result<Json<Vec<TriggerItem>>, AppError> {
+    let trigger: Trigger = trigger.parse()?;
+    let items = service.trigger_items(trigger, params.tag).await?;
+    Ok(Json(items))
+}
+
+/// Subscribe a REST hook to a trigger
+pub async fn subscribe_hook(
+    _auth: IntegrationAuth,
+    State(service): State<Arc<TodoService>>,
+    ValidatedJson(payload): ValidatedJson<SubscribeHook>,
+) -> Result<impl IntoResponse, AppError> {
+    let hook: HookSubscription = service.subscribe_hook(payload).await?;
+    Ok((StatusCode::CREATED, Json(hook)))
+}
+
+/// Unsubscribe a REST hook
+pub async fn unsubscribe_hook(
+    _auth: IntegrationAuth,
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    service.unsubscribe_hook(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Current and longest completion streaks with a weekly heatmap
+pub async fn get_streaks(
+    State(service): State<Arc<TodoService>>,
+) -> Result<Json<Streaks>, AppError> {
+    let streaks = service.streaks().await?;
+    Ok(Json(streaks.as_ref().clone()))
+}
+
+/// Recompute the cached streaks now instead of waiting for the refresh job
+/// (admin only)
+pub async fn refresh_stats(
+    _admin: AdminAuth,
+    State(service): State<Arc<TodoService>>,
+) -> Result<Json<Streaks>, AppError> {
+    let streaks = service.refresh_streaks().await?;
+    Ok(Json(streaks.as_ref().clone()))
+}
+
+/// The calling client's requests, errors and rate limit standing over the
+/// last hour
+pub async fn get_api_usage(
+    State(usage): State<Arc<UsageTracker>>,
+    State(limiter): State<Option<Arc<RateLimiter>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Json<ApiUsage> {
+    let mut api_usage = usage.usage(addr.ip());
+    api_usage.rate_limit = limiter.map(|limiter| {
+        let status = limiter.peek(addr.ip());
+        RateLimitQuota {
+            limit: status.limit,
+            remaining: status.remaining,
+            reset: status.reset,
+        }
+    });
+    Json(api_usage)
+}
+
+/// Usage of every client active in the last hour, busiest first (admin only)
+pub async fn list_api_usage(
+    _admin: AdminAuth,
+    State(usage): State<Arc<UsageTracker>>,
+) -> Json<Vec<ApiUsage>> {
+    Json(usage.all())
+}
+
+/// Estimated work per due day, with warnings for days over capacity
+pub async fn get_workload(
+    State(service): State<Arc<TodoService>>,
+    Query(params): Query<WorkloadParams>,
+) -> Result<Json<Workload>, AppError> {
+    let workload = service.workload(params.from, params.to).await?;
+    Ok(Json(workload))
+}
+
+/// Weekly review: completed, carried-over, created and stale todos of a week
+pub async fn get_weekly_review(
+    State(service): State<Arc<TodoService>>,
+    Query(params): Query<ReviewParams>,
+) -> Result<Json<WeeklyReview>, AppError> {
+    let review = service.weekly_review(params.week.as_deref()).await?;
+    Ok(Json(review))
+}
+
+/// Define a custom field
+pub async fn create_custom_field(
+    State(service): State<Arc<TodoService>>,
+    ValidatedJson(payload): ValidatedJson<CreateCustomField>,
+) -> Result<impl IntoResponse, AppError> {
+    let field = service.create_custom_field(payload).await?;
+    Ok((StatusCode::CREATED, Json(field)))
+}
+
+/// List custom field definitions
+pub async fn list_custom_fields(
+    State(service): State<Arc<TodoService>>,
+) -> Result<Json<Vec<CustomField>>, AppError> {
+    let fields = service.custom_fields().await?;
+    Ok(Json(fields))
+}
+
+/// Delete a custom field and its values
+pub async fn delete_custom_field(
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    service.delete_custom_field(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Create an automation rule
+pub async fn create_rule(
+    _auth: IntegrationAuth,
+    State(service): State<Arc<TodoService>>,
+    ValidatedJson(payload): ValidatedJson<CreateRule>,
+) -> Result<impl IntoResponse, AppError> {
+    let rule = service.create_rule(payload).await?;
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// List automation rules
+pub async fn list_rules(
+    _auth: IntegrationAuth,
+    State(service): State<Arc<TodoService>>,
+) -> Result<Json<Vec<Rule>>, AppError> {
+    let rules = service.list_rules().await?;
+    Ok(Json(rules))
+}
+
+/// Get an automation rule
+pub async fn get_rule(
+    _auth: IntegrationAuth,
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Rule>, AppError> {
+    let rule = service.get_rule(id).await?;
+    Ok(Json(rule))
+}
+
+/// Update an automation rule
+pub async fn update_rule(
+    _auth: IntegrationAuth,
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+    ValidatedJson(payload): ValidatedJson<UpdateRule>,
+) -> Result<Json<Rule>, AppError> {
+    let rule = service.update_rule(id, payload).await?;
+    Ok(Json(rule))
+}
+
+/// Delete an automation rule and its execution log
+pub async fn delete_rule(
+    _auth: IntegrationAuth,
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    service.delete_rule(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Latest executions of an automation rule
+pub async fn list_rule_runs(
+    _auth: IntegrationAuth,
+    State(service): State<Arc<TodoService>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<RuleRun>>, AppError> {
+    let runs = service.rule_runs(id).await?;
+    Ok(Json(runs))
+}
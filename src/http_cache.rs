@@ -0,0 +1,92 @@
+use crate::timezone::TIMEZONE_HEADER;
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use std::convert::Infallible;
+
+/// IMF-fixdate, the format of HTTP date headers
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// The `If-Modified-Since` date of a request; a malformed date is ignored
+/// rather than rejected, as HTTP requires
+#[derive(Debug, Clone, Copy)]
+pub struct IfModifiedSince(pub Option<DateTime<Utc>>);
+
+impl<S> FromRequestParts<S> for IfModifiedSince
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let since = parts
+            .headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .map(|date| date.with_timezone(&Utc));
+        Ok(IfModifiedSince(since))
+    }
+}
+
+/// Caching headers of a GET response whose content last changed at
+/// `last_modified`. Responses are `private`: browsers may keep them for
+/// `max_age` seconds and revalidate afterwards, shared caches must not store
+/// them. Without a last change the response is never revalidated as fresh.
+/// `now` is the time of the request, from the service clock.
+#[derive(Debug, Clone, Copy)]
+pub struct CachePolicy {
+    last_modified: Option<DateTime<Utc>>,
+    max_age: u64,
+}
+
+impl CachePolicy {
+    pub fn new(last_modified: Option<DateTime<Utc>>, max_age: u64, now: DateTime<Utc>) -> Self {
+        // HTTP dates have whole seconds: a change in the current second could
+        // be followed by another one in the same second, which a client
+        // holding this response would never be told about
+        let now = now.timestamp();
+        Self {
+            last_modified: last_modified.filter(|modified| modified.timestamp() < now),
+            max_age,
+        }
+    }
+
+    /// Whether the client's copy, from `since` or later, is still current
+    pub fn is_fresh(&self, since: IfModifiedSince) -> bool {
+        match (self.last_modified, since.0) {
+            (Some(modified), Some(since)) => modified.timestamp() <= since.timestamp(),
+            _ => false,
+        }
+    }
+
+    /// `304 Not Modified` carrying the same caching headers
+    pub fn not_modified(&self) -> Response {
+        self.apply(StatusCode::NOT_MODIFIED.into_response())
+    }
+
+    pub fn apply(&self, mut response: Response) -> Response {
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_str(&format!("private, max-age={}", self.max_age))
+                .expect("cache control is a valid header value"),
+        );
+        headers.append(
+            header::VARY,
+            HeaderValue::from_str(&format!("authorization, {}", TIMEZONE_HEADER))
+                .expect("vary is a valid header value"),
+        );
+        if let Some(modified) = self.last_modified {
+            headers.insert(
+                header::LAST_MODIFIED,
+                HeaderValue::from_str(&modified.format(HTTP_DATE).to_string())
+                    .expect("an HTTP date is a valid header value"),
+            );
+        }
+        response
+    }
+}
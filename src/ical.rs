@@ -0,0 +1,452 @@
+use crate::models::TodoResponse;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+/// Content type of iCalendar objects holding a single VTODO
+pub const ICALENDAR_CONTENT_TYPE: &str = "text/calendar; charset=utf-8; component=vtodo";
+
+const PRODID: &str = "-//axum-todo-api//Todos//EN";
+
+/// Longest content line allowed by RFC 5545, in octets
+const MAX_LINE_LENGTH: usize = 75;
+
+/// The fields of a VTODO (or VEVENT) that map onto a todo
+#[derive(Debug, Default)]
+pub struct VTodo {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub completed: bool,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub cancelled: bool,
+    pub due: Option<DateTime<Utc>>,
+    pub start: Option<DateTime<Utc>>,
+    pub created: Option<DateTime<Utc>>,
+    pub categories: Vec<String>,
+    /// Set for VEVENTs, which have a start rather than a due date
+    pub event: bool,
+}
+
+/// Render a todo as an iCalendar object with one VTODO whose UID is the todo id
+pub fn render_vtodo(todo: &TodoResponse) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, &format!("PRODID:{}", PRODID));
+    push_line(&mut out, "BEGIN:VTODO");
+    push_line(&mut out, &format!("UID:{}", todo.id));
+    push_line(
+        &mut out,
+        &format!("DTSTAMP:{}", format_date(todo.updated_at)),
+    );
+    push_line(
+        &mut out,
+        &format!("CREATED:{}", format_date(todo.created_at)),
+    );
+    push_line(
+        &mut out,
+        &format!("LAST-MODIFIED:{}", format_date(todo.updated_at)),
+    );
+    push_line(&mut out, &format!("SUMMARY:{}", escape(&todo.title)));
+    if let Some(description) = todo.description.as_deref().filter(|d| !d.is_empty()) {
+        push_line(&mut out, &format!("DESCRIPTION:{}", escape(description)));
+    }
+    if todo.completed {
+        push_line(&mut out, "STATUS:COMPLETED");
+        push_line(&mut out, "PERCENT-COMPLETE:100");
+        if let Some(completed_at) = todo.completed_at {
+            push_line(
+                &mut out,
+                &format!("COMPLETED:{}", format_date(completed_at)),
+            );
+        }
+    } else {
+        push_line(&mut out, "STATUS:NEEDS-ACTION");
+    }
+    if let Some(due_date) = todo.due_date {
+        push_line(&mut out, &format!("DUE:{}", format_date(due_date)));
+    }
+    if !todo.tags.is_empty() {
+        let tags: Vec<String> = todo.tags.iter().map(|tag| escape(tag)).collect();
+        push_line(&mut out, &format!("CATEGORIES:{}", tags.join(",")));
+    }
+    push_line(&mut out, "END:VTODO");
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// Parse the first VTODO of an iCalendar object. Nested components (alarms)
+/// and properties without a todo equivalent are ignored.
+pub fn parse_vtodo(data: &str) -> Result<VTodo, String> {
+    let mut todo = None;
+    let mut depth = 0;
+    for line in unfold(data).lines().filter(|line| !line.is_empty()) {
+        let (name, params, value) = split_property(line)?;
+        match (name.as_str(), todo.is_some()) {
+            ("BEGIN", false) if value.eq_ignore_ascii_case("VTODO") => {
+                todo = Some(VTodo::default())
+            }
+            ("BEGIN", true) => depth += 1,
+            ("END", true) if depth > 0 => depth -= 1,
+            ("END", true) => break,
+            (_, true) if depth == 0 => {
+                let todo = todo.as_mut().expect("inside a VTODO");
+                apply_property(todo, &name, &params, &value)?;
+            }
+            _ => {}
+        }
+    }
+
+    todo.ok_or_else(|| "no VTODO component found".to_string())
+}
+
+/// Parse every top-level VTODO and VEVENT of an iCalendar file, in file order
+pub fn parse_calendar(data: &str) -> Result<Vec<VTodo>, String> {
+    let mut components = Vec::new();
+    let mut current = None;
+    let mut depth = 0;
+    for line in unfold(data).lines().filter(|line| !line.is_empty()) {
+        let (name, params, value) = split_property(line)?;
+        match (name.as_str(), current.is_some()) {
+            ("BEGIN", false) if value.eq_ignore_ascii_case("VTODO") => {
+                current = Some(VTodo::default())
+            }
+            ("BEGIN", false) if value.eq_ignore_ascii_case("VEVENT") => {
+                current = Some(VTodo {
+                    event: true,
+                    ..VTodo::default()
+                })
+            }
+            ("BEGIN", true) => depth += 1,
+            ("END", true) if depth > 0 => depth -= 1,
+            ("END", true) => components.extend(current.take()),
+            (_, true) if depth == 0 => {
+                let component = current.as_mut().expect("inside a component");
+                apply_property(component, &name, &params, &value)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(components)
+}
+
+/// Join folded content lines back together
+fn unfold(data: &str) -> String {
+    data.replace("\r\n ", "")
+        .replace("\r\n\t", "")
+        .replace("\n ", "")
+        .replace("\n\t", "")
+}
+
+fn apply_property(todo: &mut VTodo, name: &str, params: &str, value: &str) -> Result<(), String> {
+    match name {
+        "UID" => todo.uid = Some(value.to_string()),
+        "SUMMARY" => todo.summary = Some(unescape(value)),
+        "DESCRIPTION" => todo.description = Some(unescape(value)),
+        "STATUS" => {
+            todo.completed = value.eq_ignore_ascii_case("COMPLETED");
+            todo.cancelled = value.eq_ignore_ascii_case("CANCELLED");
+        }
+        "COMPLETED" => {
+            todo.completed = true;
+            todo.completed_at = parse_date(value, params);
+        }
+        "DUE" => {
+            let due = parse_date(value, params)
+                .ok_or_else(|| format!("invalid DUE value '{}'", value))?;
+            todo.due = Some(due);
+        }
+        "DTSTART" => {
+            let start = parse_date(value, params)
+                .ok_or_else(|| format!("invalid DTSTART value '{}'", value))?;
+            todo.start = Some(start);
+        }
+        "CREATED" => todo.created = parse_date(value, params),
+        "CATEGORIES" => todo.categories.extend(
+            split_list(value)
+                .into_iter()
+                .map(|category| unescape(&category))
+                .filter(|category| !category.trim().is_empty()),
+        ),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Split `NAME;PARAM=...:value` into its upper-cased name, raw parameters
+/// and value. Parameter values may be quoted and contain colons.
+fn split_property(line: &str) -> Result<(String, String, String), String> {
+    let mut quoted = false;
+    let colon = line
+        .char_indices()
+        .find(|&(_, c)| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ':' && !quoted
+        })
+        .map(|(index, _)| index)
+        .ok_or_else(|| format!("invalid content line '{}'", line))?;
+
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let (name, params) = head.split_once(';').unwrap_or((head, ""));
+    Ok((
+        name.to_ascii_uppercase(),
+        params.to_string(),
+        value.to_string(),
+    ))
+}
+
+/// Accepts UTC (`20240101T120000Z`), floating and zoned date-times, taken
+/// as UTC since zones aren't tracked, and dates (`VALUE=DATE`, midnight UTC)
+fn parse_date(value: &str, params: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    let is_date = params
+        .split(';')
+        .any(|param| param.eq_ignore_ascii_case("VALUE=DATE"));
+    if is_date || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|date| date.and_utc());
+    }
+    NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape a TEXT value (RFC 5545 section 3.3.11)
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Split a multi-valued property on commas that aren't escaped
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let current = items.last_mut().expect("items is never empty");
+                current.push(c);
+                current.extend(chars.next());
+            }
+            ',' => items.push(String::new()),
+            c => items.last_mut().expect("items is never empty").push(c),
+        }
+    }
+    items
+}
+
+/// Append a content line, folded at 75 octets without splitting characters
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE_LENGTH {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn calendar(body: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n",
+            body
+        )
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_the_fields_of_a_vtodo() {
+        let todo = parse_vtodo(&calendar(
+            "BEGIN:VTODO\r\n\
+             UID:abc\r\n\
+             SUMMARY:Buy milk\\, eggs\r\n\
+             DESCRIPTION:Line one\\nLine two\r\n\
+             STATUS:COMPLETED\r\n\
+             COMPLETED:20240102T030405Z\r\n\
+             DUE:20240105T120000Z\r\n\
+             CATEGORIES:home,errands\\,misc\r\n\
+             END:VTODO\r\n",
+        ))
+        .unwrap();
+
+        assert_eq!(todo.uid.as_deref(), Some("abc"));
+        assert_eq!(todo.summary.as_deref(), Some("Buy milk, eggs"));
+        assert_eq!(todo.description.as_deref(), Some("Line one\nLine two"));
+        assert!(todo.completed);
+        assert_eq!(
+            todo.completed_at,
+            Some(Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap())
+        );
+        assert_eq!(todo.due, Some(utc(2024, 1, 5, 12, 0)));
+        assert_eq!(todo.categories, vec!["home", "errands,misc"]);
+        assert!(!todo.event);
+    }
+
+    #[test]
+    fn unfolds_continuation_lines() {
+        let todo = parse_vtodo(&calendar(
+            "BEGIN:VTODO\r\nSUMMARY:A long\r\n  title\r\n\tcontinued\r\nEND:VTODO\r\n",
+        ))
+        .unwrap();
+        assert_eq!(todo.summary.as_deref(), Some("A long titlecontinued"));
+    }
+
+    #[test]
+    fn ignores_properties_of_nested_components() {
+        let todo = parse_vtodo(&calendar(
+            "BEGIN:VTODO\r\n\
+             SUMMARY:Outer\r\n\
+             BEGIN:VALARM\r\n\
+             DESCRIPTION:Alarm text\r\n\
+             END:VALARM\r\n\
+             END:VTODO\r\n",
+        ))
+        .unwrap();
+        assert_eq!(todo.summary.as_deref(), Some("Outer"));
+        assert_eq!(todo.description, None);
+    }
+
+    #[test]
+    fn missing_properties_stay_unset() {
+        let todo = parse_vtodo(&calendar("BEGIN:VTODO\r\nEND:VTODO\r\n")).unwrap();
+        assert_eq!(todo.uid, None);
+        assert_eq!(todo.summary, None);
+        assert_eq!(todo.due, None);
+        assert!(!todo.completed);
+        assert!(todo.categories.is_empty());
+    }
+
+    #[test]
+    fn rejects_a_calendar_without_vtodo() {
+        let error = parse_vtodo(&calendar("BEGIN:VEVENT\r\nEND:VEVENT\r\n")).unwrap_err();
+        assert_eq!(error, "no VTODO component found");
+    }
+
+    #[test]
+    fn rejects_a_line_without_value() {
+        let error = parse_vtodo(&calendar("BEGIN:VTODO\r\nSUMMARY\r\nEND:VTODO\r\n")).unwrap_err();
+        assert!(error.starts_with("invalid content line"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_an_unreadable_due_date() {
+        let error =
+            parse_vtodo(&calendar("BEGIN:VTODO\r\nDUE:tomorrow\r\nEND:VTODO\r\n")).unwrap_err();
+        assert_eq!(error, "invalid DUE value 'tomorrow'");
+    }
+
+    #[test]
+    fn quoted_parameters_may_contain_colons() {
+        let (name, params, value) = split_property(r#"due;X-NOTE="a:b":20240105T120000Z"#).unwrap();
+        assert_eq!(name, "DUE");
+        assert_eq!(params, r#"X-NOTE="a:b""#);
+        assert_eq!(value, "20240105T120000Z");
+    }
+
+    #[test]
+    fn reads_utc_floating_and_zoned_times_as_utc() {
+        let expected = Some(utc(2024, 3, 31, 1, 30));
+        assert_eq!(parse_date("20240331T013000Z", ""), expected);
+        assert_eq!(parse_date("20240331T013000", ""), expected);
+        // Zones aren't tracked: the local time is taken as UTC
+        assert_eq!(
+            parse_date("20240331T013000", "TZID=Europe/Berlin"),
+            expected
+        );
+    }
+
+    #[test]
+    fn reads_dates_as_midnight_utc() {
+        let expected = Some(utc(2024, 2, 29, 0, 0));
+        assert_eq!(parse_date("20240229", "VALUE=DATE"), expected);
+        assert_eq!(parse_date("20240229", ""), expected);
+        assert_eq!(parse_date("20230229", "VALUE=DATE"), None);
+        assert_eq!(parse_date("2024-02-29T00:00:00Z", ""), None);
+    }
+
+    #[test]
+    fn parses_every_todo_and_event_of_a_calendar() {
+        let components = parse_calendar(&calendar(
+            "BEGIN:VTODO\r\nSUMMARY:First\r\nEND:VTODO\r\n\
+             BEGIN:VEVENT\r\nSUMMARY:Meeting\r\nDTSTART;VALUE=DATE:20240110\r\nEND:VEVENT\r\n\
+             BEGIN:VJOURNAL\r\nSUMMARY:Ignored\r\nEND:VJOURNAL\r\n\
+             BEGIN:VTODO\r\nSUMMARY:Dropped\r\nSTATUS:CANCELLED\r\nEND:VTODO\r\n",
+        ))
+        .unwrap();
+
+        let summaries: Vec<_> = components
+            .iter()
+            .map(|component| component.summary.as_deref().unwrap())
+            .collect();
+        assert_eq!(summaries, ["First", "Meeting", "Dropped"]);
+        assert!(components[1].event);
+        assert_eq!(components[1].start, Some(utc(2024, 1, 10, 0, 0)));
+        assert!(components[2].cancelled);
+    }
+
+    #[test]
+    fn escaping_round_trips() {
+        let text = "a;b,c\\d\nnext line";
+        assert_eq!(unescape(&escape(text)), text);
+    }
+
+    #[test]
+    fn folds_long_lines_without_splitting_characters() {
+        let mut out = String::new();
+        push_line(&mut out, &format!("SUMMARY:{}", "é".repeat(60)));
+        for line in out.split("\r\n") {
+            assert!(line.len() <= MAX_LINE_LENGTH, "{:?}", line);
+        }
+        assert_eq!(
+            unfold(&out).trim_end(),
+            format!("SUMMARY:{}", "é".repeat(60))
+        );
+    }
+}
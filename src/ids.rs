@@ -0,0 +1,74 @@
+use serde::Serialize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Source of the ids the service assigns to new todos, rules, rule runs,
+/// hooks, custom fields and relations
+pub trait IdGenerator: Send + Sync {
+    fn generate(&self) -> Uuid;
+}
+
+/// Random ids (the default)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UuidV4;
+
+impl IdGenerator for UuidV4 {
+    fn generate(&self) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+/// Time-ordered ids, which keep the primary key index append-mostly
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UuidV7;
+
+impl IdGenerator for UuidV7 {
+    fn generate(&self) -> Uuid {
+        Uuid::now_v7()
+    }
+}
+
+/// 00000000-0000-0000-0000-000000000001, ...-000000000002 and so on, for
+/// tests that need ids known in advance
+#[derive(Debug, Default)]
+pub struct SequentialIds {
+    last: AtomicU64,
+}
+
+impl IdGenerator for SequentialIds {
+    fn generate(&self) -> Uuid {
+        Uuid::from_u128(u128::from(self.last.fetch_add(1, Ordering::Relaxed) + 1))
+    }
+}
+
+/// UUID version generated for new records (`ID_FORMAT`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdFormat {
+    #[default]
+    V4,
+    V7,
+}
+
+impl IdFormat {
+    pub fn generator(self) -> Arc<dyn IdGenerator> {
+        match self {
+            IdFormat::V4 => Arc::new(UuidV4),
+            IdFormat::V7 => Arc::new(UuidV7),
+        }
+    }
+}
+
+impl FromStr for IdFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v4" => Ok(IdFormat::V4),
+            "v7" => Ok(IdFormat::V7),
+            other => Err(format!("Unknown id format: {} (expected v4 or v7)", other)),
+        }
+    }
+}
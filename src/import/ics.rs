@@ -0,0 +1,112 @@
+use super::{invalid_file, non_empty, ImportItem, Provider, Skipped};
+use crate::error::AppError;
+use crate::ical;
+use crate::models::ImportedTodo;
+
+/// iCalendar files (`.ics`) as exported by calendar and task apps. VTODOs
+/// become todos due at their DUE; VEVENTs become todos due when they start.
+/// Categories become tags. Recurrence rules are dropped, so a repeating
+/// entry is imported once, at its first occurrence.
+pub fn parse(data: &[u8]) -> Result<Vec<ImportItem>, AppError> {
+    let data = std::str::from_utf8(data).map_err(|e| invalid_file(Provider::Ics, e))?;
+    let components = ical::parse_calendar(data).map_err(|e| invalid_file(Provider::Ics, e))?;
+    if components.is_empty() {
+        return Err(invalid_file(
+            Provider::Ics,
+            "no VTODO or VEVENT component found",
+        ));
+    }
+
+    let items = components
+        .into_iter()
+        .map(|component| {
+            let title = component.summary.unwrap_or_default();
+            if component.cancelled {
+                return Err(Skipped {
+                    title,
+                    reason: "Cancelled in the calendar".to_string(),
+                });
+            }
+
+            Ok(ImportedTodo {
+                title,
+                description: component.description.and_then(non_empty),
+                tags: component.categories,
+                due_date: if component.event {
+                    component.start
+                } else {
+                    component.due
+                },
+                completed: component.completed,
+                completed_at: component.completed_at,
+                created_at: component.created,
+            })
+        })
+        .collect();
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn imports_todos_and_events() {
+        let items = parse(
+            b"BEGIN:VCALENDAR\r\n\
+              BEGIN:VTODO\r\nSUMMARY:Call Bob\r\nDUE:20240105T120000Z\r\nCATEGORIES:work\r\nEND:VTODO\r\n\
+              BEGIN:VEVENT\r\nSUMMARY:Dentist\r\nDTSTART:20240110T083000Z\r\nDESCRIPTION: \r\nEND:VEVENT\r\n\
+              END:VCALENDAR\r\n",
+        )
+        .unwrap();
+
+        let todo = items[0].as_ref().unwrap();
+        assert_eq!(todo.title, "Call Bob");
+        assert_eq!(todo.tags, vec!["work"]);
+        assert_eq!(
+            todo.due_date,
+            Some(Utc.with_ymd_and_hms(2024, 1, 5, 12, 0, 0).unwrap())
+        );
+        let event = items[1].as_ref().unwrap();
+        assert_eq!(
+            event.due_date,
+            Some(Utc.with_ymd_and_hms(2024, 1, 10, 8, 30, 0).unwrap())
+        );
+        assert_eq!(event.description, None);
+    }
+
+    #[test]
+    fn skips_cancelled_entries() {
+        let items = parse(
+            b"BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nSUMMARY:Old\r\nSTATUS:CANCELLED\r\nEND:VTODO\r\nEND:VCALENDAR\r\n",
+        )
+        .unwrap();
+        let skipped = items[0].as_ref().unwrap_err();
+        assert_eq!(skipped.title, "Old");
+    }
+
+    #[test]
+    fn a_todo_without_summary_gets_an_empty_title() {
+        let items =
+            parse(b"BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nEND:VTODO\r\nEND:VCALENDAR\r\n").unwrap();
+        assert_eq!(items[0].as_ref().unwrap().title, "");
+    }
+
+    #[test]
+    fn rejects_files_without_entries_or_not_utf8() {
+        for data in [
+            &b"BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n"[..],
+            &b"\xff\xfe"[..],
+            &b"BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nDUE:soon\r\nEND:VTODO\r\n"[..],
+        ] {
+            let error = parse(data).unwrap_err();
+            assert!(
+                matches!(&error, AppError::BadRequest(message) if message.starts_with("Not a valid ics export")),
+                "{:?}",
+                error
+            );
+        }
+    }
+}
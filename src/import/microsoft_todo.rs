@@ -0,0 +1,151 @@
+use super::{invalid_file, non_empty, parse_date, ImportItem, Provider};
+use crate::error::AppError;
+use crate::models::ImportedTodo;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Export {
+    Lists { lists: Vec<TaskList> },
+    Tasks { value: Vec<Task> },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskList {
+    display_name: String,
+    #[serde(default)]
+    tasks: Vec<Task>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Task {
+    title: String,
+    body: Option<Body>,
+    #[serde(default)]
+    status: String,
+    due_date_time: Option<GraphDateTime>,
+    completed_date_time: Option<GraphDateTime>,
+    created_date_time: Option<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Body {
+    #[serde(default)]
+    content: String,
+}
+
+/// Graph's `dateTimeTimeZone`; exports request UTC, so the zone is ignored
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphDateTime {
+    date_time: String,
+}
+
+/// Microsoft To Do, as returned by the Microsoft Graph API: either one list's
+/// tasks (`GET /me/todo/lists/{id}/tasks`, a `value` array) or every list with
+/// its tasks (`{"lists": [{"displayName": ..., "tasks": [...]}]}`).
+///
+/// Tasks become todos tagged with their list name and categories. HTML
+/// bodies are kept as-is: descriptions are Markdown, which allows HTML.
+pub fn parse(data: &[u8]) -> Result<Vec<ImportItem>, AppError> {
+    let export: Export =
+        serde_json::from_slice(data).map_err(|e| invalid_file(Provider::MicrosoftTodo, e))?;
+    let tasks: Vec<(Option<String>, Task)> = match export {
+        Export::Lists { lists } => lists
+            .into_iter()
+            .flat_map(|list| {
+                let name = list.display_name;
+                list.tasks
+                    .into_iter()
+                    .map(move |task| (Some(name.clone()), task))
+            })
+            .collect(),
+        Export::Tasks { value } => value.into_iter().map(|task| (None, task)).collect(),
+    };
+
+    let items = tasks
+        .into_iter()
+        .map(|(list, task)| {
+            let mut tags: Vec<String> = list.into_iter().collect();
+            tags.extend(task.categories);
+
+            Ok(ImportedTodo {
+                title: task.title,
+                description: task.body.and_then(|body| non_empty(body.content)),
+                tags,
+                due_date: task
+                    .due_date_time
+                    .and_then(|due| parse_date(&due.date_time)),
+                completed: task.status == "completed",
+                completed_at: task
+                    .completed_date_time
+                    .and_then(|completed| parse_date(&completed.date_time)),
+                created_at: task.created_date_time.as_deref().and_then(parse_date),
+            })
+        })
+        .collect();
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn imports_every_list() {
+        let items = parse(
+            br#"{"lists": [{"displayName": "Groceries", "tasks": [
+                {"title": "Milk", "status": "completed", "categories": ["Blue category"],
+                 "body": {"content": "<p>2 litres</p>", "contentType": "html"},
+                 "dueDateTime": {"dateTime": "2024-05-03T00:00:00.0000000", "timeZone": "UTC"},
+                 "completedDateTime": {"dateTime": "2024-05-02T17:45:10.0000000", "timeZone": "UTC"},
+                 "createdDateTime": "2024-05-01T08:00:00.1234567Z"}
+            ]}, {"displayName": "Empty"}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(items.len(), 1);
+        let milk = items[0].as_ref().unwrap();
+        assert_eq!(milk.tags, vec!["Groceries", "Blue category"]);
+        assert_eq!(milk.description.as_deref(), Some("<p>2 litres</p>"));
+        assert!(milk.completed);
+        assert_eq!(
+            milk.completed_at,
+            Some(Utc.with_ymd_and_hms(2024, 5, 2, 17, 45, 10).unwrap())
+        );
+        assert!(milk.created_at.is_some());
+    }
+
+    #[test]
+    fn imports_a_single_list_response() {
+        let items =
+            parse(br#"{"value": [{"title": "Call mum", "status": "notStarted"}]}"#).unwrap();
+        let todo = items[0].as_ref().unwrap();
+        assert_eq!(todo.title, "Call mum");
+        assert!(todo.tags.is_empty());
+        assert!(!todo.completed);
+        assert_eq!(todo.due_date, None);
+    }
+
+    #[test]
+    fn rejects_tasks_without_title_or_unknown_shapes() {
+        for data in [
+            &br#"{"value": [{"status": "notStarted"}]}"#[..],
+            &br#"{"tasks": []}"#[..],
+            &b"not json"[..],
+        ] {
+            let error = parse(data).unwrap_err();
+            assert!(
+                matches!(&error, AppError::BadRequest(message) if message.starts_with("Not a valid microsoft-todo export")),
+                "{:?}",
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::error::AppError;
+use crate::models::ImportedTodo;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::str::FromStr;
+
+pub mod ics;
+pub mod microsoft_todo;
+pub mod todoist;
+pub mod trello;
+
+/// Largest export file accepted by the import endpoint
+pub const MAX_IMPORT_BYTES: usize = 10 * 1024 * 1024;
+
+/// Services whose exports can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    Todoist,
+    Trello,
+    MicrosoftTodo,
+    Ics,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 4] = [
+        Provider::Todoist,
+        Provider::Trello,
+        Provider::MicrosoftTodo,
+        Provider::Ics,
+    ];
+
+    /// Name used in the import URL (`/import/{provider}`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::Todoist => "todoist",
+            Provider::Trello => "trello",
+            Provider::MicrosoftTodo => "microsoft-todo",
+            Provider::Ics => "ics",
+        }
+    }
+
+    /// Parse an export file of this provider. Projects, lists, boards and
+    /// categories become tags, since todos here are only grouped by tag.
+    pub fn parse(&self, data: &[u8]) -> Result<Vec<ImportItem>, AppError> {
+        match self {
+            Provider::Todoist => todoist::parse(data),
+            Provider::Trello => trello::parse(data),
+            Provider::MicrosoftTodo => microsoft_todo::parse(data),
+            Provider::Ics => ics::parse(data),
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = AppError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Provider::ALL
+            .into_iter()
+            .find(|provider| provider.name() == name)
+            .ok_or_else(|| {
+                let supported: Vec<&str> = Provider::ALL.iter().map(Provider::name).collect();
+                AppError::NotFound(format!(
+                    "Unknown import provider '{}', supported: {}",
+                    name,
+                    supported.join(", ")
+                ))
+            })
+    }
+}
+
+/// An entry of an export file that is deliberately not imported
+#[derive(Debug, Clone)]
+pub struct Skipped {
+    pub title: String,
+    pub reason: String,
+}
+
+/// One entry of an export file, in file order
+pub type ImportItem = Result<ImportedTodo, Skipped>;
+
+/// Parse the date formats found in exports: RFC 3339, a date-time without
+/// offset (taken as UTC) or a bare date (midnight UTC)
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Some(date.and_utc());
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc())
+}
+
+/// Treat empty strings in exports as missing
+fn non_empty(value: String) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn invalid_file(provider: Provider, error: impl std::fmt::Display) -> AppError {
+    AppError::BadRequest(format!("Not a valid {} export: {}", provider.name(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn converts_offsets_to_utc() {
+        assert_eq!(
+            parse_date("2024-06-01T09:30:00+02:00"),
+            Some(Utc.with_ymd_and_hms(2024, 6, 1, 7, 30, 0).unwrap())
+        );
+        assert_eq!(
+            parse_date(" 2024-06-01T09:30:00.000Z "),
+            Some(Utc.with_ymd_and_hms(2024, 6, 1, 9, 30, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn reads_times_without_offset_as_utc() {
+        assert_eq!(
+            parse_date("2024-06-01T09:30:00.0000000"),
+            Some(Utc.with_ymd_and_hms(2024, 6, 1, 9, 30, 0).unwrap())
+        );
+        assert_eq!(
+            parse_date("2024-06-01"),
+            Some(Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn unreadable_dates_are_dropped() {
+        assert_eq!(parse_date(""), None);
+        assert_eq!(parse_date("every monday"), None);
+        assert_eq!(parse_date("2024-02-30"), None);
+    }
+
+    #[test]
+    fn providers_are_found_by_url_name() {
+        for provider in Provider::ALL {
+            assert_eq!(provider.name().parse::<Provider>().unwrap(), provider);
+        }
+        let error = "asana".parse::<Provider>().unwrap_err();
+        assert!(matches!(error, AppError::NotFound(message) if message.contains("todoist")));
+    }
+}
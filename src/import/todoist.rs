@@ -0,0 +1,237 @@
+use super::{invalid_file, non_empty, parse_date, ImportItem, Provider, Skipped};
+use crate::error::AppError;
+use crate::models::ImportedTodo;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+struct Backup {
+    #[serde(default)]
+    projects: Vec<Project>,
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    id: Value,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Item {
+    content: String,
+    #[serde(default)]
+    description: String,
+    project_id: Option<Value>,
+    #[serde(default)]
+    labels: Vec<String>,
+    due: Option<Due>,
+    #[serde(default)]
+    checked: bool,
+    completed_at: Option<String>,
+    added_at: Option<String>,
+    #[serde(default)]
+    is_deleted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Due {
+    date: String,
+}
+
+/// Todoist exports: the JSON backup returned by the Sync API (`projects` and
+/// `items`) or the CSV template exported per project
+pub fn parse(data: &[u8]) -> Result<Vec<ImportItem>, AppError> {
+    if data.trim_ascii_start().starts_with(b"{") {
+        parse_json(data)
+    } else {
+        parse_csv(data)
+    }
+}
+
+fn parse_json(data: &[u8]) -> Result<Vec<ImportItem>, AppError> {
+    let backup: Backup =
+        serde_json::from_slice(data).map_err(|e| invalid_file(Provider::Todoist, e))?;
+    // Ids are strings in current exports and numbers in older ones
+    let projects: HashMap<String, String> = backup
+        .projects
+        .into_iter()
+        .map(|project| (project.id.to_string(), project.name))
+        .collect();
+
+    let items = backup
+        .items
+        .into_iter()
+        .map(|item| {
+            if item.is_deleted {
+                return Err(Skipped {
+                    title: item.content,
+                    reason: "Deleted in Todoist".to_string(),
+                });
+            }
+
+            let mut tags = Vec::new();
+            if let Some(project) = item.project_id.and_then(|id| projects.get(&id.to_string())) {
+                tags.push(project.clone());
+            }
+            tags.extend(item.labels);
+
+            Ok(ImportedTodo {
+                title: item.content,
+                description: non_empty(item.description),
+                tags,
+                due_date: item.due.and_then(|due| parse_date(&due.date)),
+                completed: item.checked,
+                completed_at: item.completed_at.as_deref().and_then(parse_date),
+                created_at: item.added_at.as_deref().and_then(parse_date),
+            })
+        })
+        .collect();
+
+    Ok(items)
+}
+
+/// The CSV export has one row per task, section or note; only tasks are
+/// imported. Labels are written inline in the content as `@label`, and the
+/// due date column may hold natural language ("every monday"), which is
+/// dropped.
+fn parse_csv(data: &[u8]) -> Result<Vec<ImportItem>, AppError> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+    let headers = reader
+        .headers()
+        .map_err(|e| invalid_file(Provider::Todoist, e))?
+        .clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name))
+    };
+    let (Some(kind), Some(content)) = (column("TYPE"), column("CONTENT")) else {
+        return Err(invalid_file(
+            Provider::Todoist,
+            "missing TYPE or CONTENT column",
+        ));
+    };
+    let description = column("DESCRIPTION");
+    let date = column("DATE");
+
+    let mut items = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| invalid_file(Provider::Todoist, e))?;
+        if record.get(kind).map(str::trim) != Some("task") {
+            continue;
+        }
+
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| record.get(index))
+                .unwrap_or_default()
+                .to_string()
+        };
+        let (title, tags) = split_labels(&field(Some(content)));
+        items.push(Ok(ImportedTodo {
+            title,
+            description: non_empty(field(description)),
+            tags,
+            due_date: parse_date(&field(date)),
+            ..ImportedTodo::default()
+        }));
+    }
+
+    Ok(items)
+}
+
+/// Split `Buy milk @errands @home` into the title and its labels
+fn split_labels(content: &str) -> (String, Vec<String>) {
+    let mut labels = Vec::new();
+    let mut words = Vec::new();
+    for word in content.split_whitespace() {
+        match word.strip_prefix('@') {
+            Some(label) if !label.is_empty() => labels.push(label.to_string()),
+            _ => words.push(word),
+        }
+    }
+    (words.join(" "), labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn imports_a_json_backup() {
+        let items = parse(
+            br#"{
+                "projects": [{"id": "p1", "name": "Work"}, {"id": 7, "name": "Home"}],
+                "items": [
+                    {"content": "Report", "project_id": "p1", "labels": ["urgent"],
+                     "due": {"date": "2024-05-02"}, "checked": true,
+                     "completed_at": "2024-05-01T10:00:00Z", "added_at": "2024-04-01T08:00:00+02:00"},
+                    {"content": "Plants", "project_id": 7, "description": "  "},
+                    {"content": "Gone", "is_deleted": true}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let report = items[0].as_ref().unwrap();
+        assert_eq!(report.tags, vec!["Work", "urgent"]);
+        assert!(report.completed);
+        assert_eq!(
+            report.due_date,
+            Some(Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap())
+        );
+        assert_eq!(
+            report.created_at,
+            Some(Utc.with_ymd_and_hms(2024, 4, 1, 6, 0, 0).unwrap())
+        );
+        let plants = items[1].as_ref().unwrap();
+        assert_eq!(plants.tags, vec!["Home"]);
+        assert_eq!(plants.description, None);
+        assert_eq!(items[2].as_ref().unwrap_err().title, "Gone");
+    }
+
+    #[test]
+    fn rejects_a_backup_without_items() {
+        let error = parse(br#"{"projects": []}"#).unwrap_err();
+        assert!(matches!(error, AppError::BadRequest(message) if message.contains("items")));
+    }
+
+    #[test]
+    fn imports_tasks_of_a_csv_export() {
+        let items = parse(
+            b"TYPE,CONTENT,DESCRIPTION,PRIORITY,DATE\n\
+              section,Errands,,,\n\
+              task,Buy milk @errands @home,Semi-skimmed,1,2024-05-03\n\
+              task,Stretch,,4,every morning\n",
+        )
+        .unwrap();
+
+        assert_eq!(items.len(), 2);
+        let milk = items[0].as_ref().unwrap();
+        assert_eq!(milk.title, "Buy milk");
+        assert_eq!(milk.tags, vec!["errands", "home"]);
+        assert_eq!(milk.description.as_deref(), Some("Semi-skimmed"));
+        assert!(milk.due_date.is_some());
+        // Natural-language dates are dropped
+        assert_eq!(items[1].as_ref().unwrap().due_date, None);
+    }
+
+    #[test]
+    fn rejects_a_csv_without_required_columns() {
+        let error = parse(b"NAME,DATE\nBuy milk,2024-05-03\n").unwrap_err();
+        assert!(
+            matches!(error, AppError::BadRequest(message) if message.contains("missing TYPE or CONTENT"))
+        );
+    }
+
+    #[test]
+    fn a_lone_at_sign_stays_in_the_title() {
+        assert_eq!(
+            split_labels("Meet @ noon @work"),
+            ("Meet @ noon".to_string(), vec!["work".to_string()])
+        );
+    }
+}
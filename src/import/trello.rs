@@ -0,0 +1,148 @@
+use super::{invalid_file, non_empty, parse_date, ImportItem, Provider, Skipped};
+use crate::error::AppError;
+use crate::models::ImportedTodo;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+#[derive(Debug, Deserialize)]
+struct Board {
+    name: String,
+    #[serde(default)]
+    lists: Vec<List>,
+    cards: Vec<Card>,
+}
+
+#[derive(Debug, Deserialize)]
+struct List {
+    id: String,
+    #[serde(default)]
+    closed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Card {
+    name: String,
+    #[serde(default)]
+    desc: String,
+    #[serde(default)]
+    closed: bool,
+    due: Option<String>,
+    #[serde(default)]
+    due_complete: bool,
+    id_list: Option<String>,
+    #[serde(default)]
+    labels: Vec<Label>,
+    date_last_activity: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    #[serde(default)]
+    name: String,
+    color: Option<String>,
+}
+
+/// Trello board exports (Board menu > Print, export and share > JSON).
+///
+/// Cards become todos tagged with the board name and their labels (unnamed
+/// labels by color). A card counts as completed when its due date is marked
+/// complete; archived cards and cards in archived lists are skipped.
+pub fn parse(data: &[u8]) -> Result<Vec<ImportItem>, AppError> {
+    let board: Board =
+        serde_json::from_slice(data).map_err(|e| invalid_file(Provider::Trello, e))?;
+    let archived_lists: HashSet<String> = board
+        .lists
+        .into_iter()
+        .filter(|list| list.closed)
+        .map(|list| list.id)
+        .collect();
+
+    let items = board
+        .cards
+        .into_iter()
+        .map(|card| {
+            let in_archived_list = card
+                .id_list
+                .as_ref()
+                .is_some_and(|list| archived_lists.contains(list));
+            if card.closed || in_archived_list {
+                return Err(Skipped {
+                    title: card.name,
+                    reason: "Archived in Trello".to_string(),
+                });
+            }
+
+            let mut tags = vec![board.name.clone()];
+            tags.extend(
+                card.labels
+                    .into_iter()
+                    .filter_map(|label| non_empty(label.name).or(label.color)),
+            );
+
+            Ok(ImportedTodo {
+                title: card.name,
+                description: non_empty(card.desc),
+                tags,
+                due_date: card.due.as_deref().and_then(parse_date),
+                completed: card.due_complete,
+                // Trello doesn't record when a due date was marked complete
+                completed_at: card
+                    .due_complete
+                    .then(|| card.date_last_activity.as_deref().and_then(parse_date))
+                    .flatten(),
+                created_at: None,
+            })
+        })
+        .collect();
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &[u8] = br#"{
+        "name": "Launch",
+        "lists": [{"id": "l1", "closed": false}, {"id": "l2", "closed": true}],
+        "cards": [
+            {"name": "Write copy", "desc": "Landing page", "idList": "l1",
+             "due": "2024-05-03T12:00:00.000Z", "dueComplete": true,
+             "dateLastActivity": "2024-05-02T09:00:00.000Z",
+             "labels": [{"name": "marketing", "color": "green"}, {"name": "", "color": "red"}]},
+            {"name": "Old idea", "idList": "l2"},
+            {"name": "Dropped", "idList": "l1", "closed": true},
+            {"name": "No due", "idList": "l1", "dateLastActivity": "2024-05-02T09:00:00.000Z"}
+        ]
+    }"#;
+
+    #[test]
+    fn imports_open_cards_with_board_and_labels_as_tags() {
+        let items = parse(BOARD).unwrap();
+
+        let copy = items[0].as_ref().unwrap();
+        assert_eq!(copy.tags, vec!["Launch", "marketing", "red"]);
+        assert_eq!(copy.description.as_deref(), Some("Landing page"));
+        assert!(copy.completed);
+        assert!(copy.due_date.is_some());
+        assert!(copy.completed_at.is_some());
+
+        let open = items[3].as_ref().unwrap();
+        assert!(!open.completed);
+        assert_eq!(open.completed_at, None);
+    }
+
+    #[test]
+    fn skips_archived_cards_and_lists() {
+        let items = parse(BOARD).unwrap();
+        assert_eq!(items[1].as_ref().unwrap_err().title, "Old idea");
+        assert_eq!(items[2].as_ref().unwrap_err().title, "Dropped");
+    }
+
+    #[test]
+    fn rejects_exports_without_cards() {
+        let error = parse(br#"{"name": "Launch", "lists": []}"#).unwrap_err();
+        assert!(matches!(error, AppError::BadRequest(message) if message.contains("cards")));
+    }
+}
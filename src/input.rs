@@ -0,0 +1,58 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Input-processing pass applied to user supplied text before validation
+/// and storage.
+///
+/// Every string is NFC-normalized, stripped of control characters and
+/// trimmed. Titles are single-line, so all whitespace runs can optionally be
+/// collapsed to one space; descriptions keep their line breaks and tabs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sanitizer {
+    /// Collapse runs of whitespace inside titles into a single space
+    pub collapse_whitespace: bool,
+}
+
+impl Sanitizer {
+    pub fn new(collapse_whitespace: bool) -> Self {
+        Self {
+            collapse_whitespace,
+        }
+    }
+
+    /// Sanitize a todo title
+    pub fn title(&self, title: &str) -> String {
+        let title: String = title
+            .nfc()
+            .filter(|c| !c.is_control() || *c == '\t' || *c == '\n')
+            .map(|c| if c.is_whitespace() { ' ' } else { c })
+            .collect();
+
+        if self.collapse_whitespace {
+            title.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else {
+            title.trim().to_string()
+        }
+    }
+
+    /// Sanitize a todo description, keeping line breaks and tabs
+    pub fn description(&self, description: &str) -> String {
+        description
+            .nfc()
+            .filter(|c| !c.is_control() || *c == '\t' || *c == '\n')
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    /// Sanitize a tag: single-line, whitespace collapsed and lowercased so
+    /// `Work`, ` work ` and `WORK` are the same tag
+    pub fn tag(&self, tag: &str) -> String {
+        tag.nfc()
+            .filter(|c| !c.is_control())
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+}
@@ -0,0 +1,279 @@
+use crate::error::AppError;
+use crate::events::TodoEvent;
+use crate::models::TodoResponse;
+use crate::outbound;
+use crate::service::TodoService;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// Number of items returned by a polling trigger
+pub const TRIGGER_ITEMS: i64 = 50;
+
+/// How long a hook target gets to accept a delivery
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events integration platforms can poll for or subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    NewTodo,
+    CompletedTodo,
+}
+
+impl Trigger {
+    /// Name used in trigger URLs and stored with subscriptions
+    pub fn name(&self) -> &'static str {
+        match self {
+            Trigger::NewTodo => "new_todo",
+            Trigger::CompletedTodo => "completed_todo",
+        }
+    }
+
+    /// Trigger fired by a domain event, if any
+    pub fn for_event(event: &TodoEvent) -> Option<(Trigger, &TodoResponse)> {
+        match event {
+            TodoEvent::Created(todo) => Some((Trigger::NewTodo, todo)),
+            TodoEvent::Completed(todo) => Some((Trigger::CompletedTodo, todo)),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Trigger {
+    type Err = AppError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [Trigger::NewTodo, Trigger::CompletedTodo]
+            .into_iter()
+            .find(|trigger| trigger.name() == name)
+            .ok_or_else(|| AppError::NotFound(format!("Unknown trigger '{}'", name)))
+    }
+}
+
+/// One trigger occurrence. `id` identifies the occurrence rather than the
+/// todo, so platforms deduplicating on it see a todo completed again after
+/// being reopened as a new item.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct TriggerItem {
+    pub id: String,
+    pub trigger: Trigger,
+    pub occurred_at: DateTime<Utc>,
+    pub todo: TodoResponse,
+}
+
+impl TriggerItem {
+    pub fn new(trigger: Trigger, todo: TodoResponse) -> Self {
+        let occurred_at = match trigger {
+            Trigger::NewTodo => todo.created_at,
+            Trigger::CompletedTodo => todo.completed_at.unwrap_or(todo.updated_at),
+        };
+        let id = match trigger {
+            Trigger::NewTodo => todo.id.to_string(),
+            Trigger::CompletedTodo => format!("{}-{}", todo.id, occurred_at.timestamp_micros()),
+        };
+        Self {
+            id,
+            trigger,
+            occurred_at,
+            todo,
+        }
+    }
+}
+
+/// Order items newest first, as polling platforms expect, breaking ties by
+/// id so the order is the same on every poll
+pub fn newest_first(items: &mut [TriggerItem]) {
+    items.sort_by(|a, b| {
+        b.occurred_at
+            .cmp(&a.occurred_at)
+            .then_with(|| b.id.cmp(&a.id))
+    });
+}
+
+/// Request DTO for subscribing a REST hook
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct SubscribeHook {
+    pub target_url: String,
+    pub event: Trigger,
+}
+
+/// A REST hook: every occurrence of `event` is POSTed to `target_url`
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct HookSubscription {
+    pub id: Uuid,
+    pub event: Trigger,
+    pub target_url: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Deliver trigger items to subscribed hooks as events are published. A
+/// target answering 410 Gone is unsubscribed, as REST hooks expect; other
+/// failures are logged and not retried.
+pub fn spawn_hook_delivery(service: Arc<TodoService>) {
+    let mut events = service.events().subscribe();
+    let http = outbound::client(DELIVERY_TIMEOUT);
+
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "Hook delivery fell behind, events were dropped");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let Some((trigger, todo)) = Trigger::for_event(&event) else {
+                continue;
+            };
+
+            let hooks = match service.hooks(trigger).await {
+                Ok(hooks) => hooks,
+                Err(e) => {
+                    tracing::error!("Failed to load hook subscriptions: {}", e);
+                    continue;
+                }
+            };
+            let item = Arc::new(TriggerItem::new(trigger, todo.clone()));
+            for hook in hooks {
+                tokio::spawn(deliver(service.clone(), http.clone(), hook, item.clone()));
+            }
+        }
+    });
+}
+
+async fn deliver(
+    service: Arc<TodoService>,
+    http: reqwest::Client,
+    hook: HookSubscription,
+    item: Arc<TriggerItem>,
+) {
+    // The client's resolver keeps hostnames on public addresses, but IP
+    // literals never reach it
+    let target = reqwest::Url::parse(&hook.target_url).map_err(|e| e.to_string());
+    if let Err(e) = target.and_then(|url| outbound::check_host(&url)) {
+        tracing::warn!(hook_id = %hook.id, "Hook target refused: {}", e);
+        return;
+    }
+    match http.post(&hook.target_url).json(&*item).send().await {
+        Ok(response) if response.status() == reqwest::StatusCode::GONE => {
+            tracing::info!(hook_id = %hook.id, "Hook target is gone, unsubscribing");
+            if let Err(e) = service.unsubscribe_hook(hook.id).await {
+                tracing::error!(hook_id = %hook.id, "Failed to unsubscribe hook: {}", e);
+            }
+        }
+        Ok(response) if !response.status().is_success() => {
+            tracing::warn!(hook_id = %hook.id, status = %response.status(), "Hook delivery rejected");
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!(hook_id = %hook.id, "Hook delivery failed: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use serde_json::json;
+
+    fn todo(created_at: DateTime<Utc>, completed_at: Option<DateTime<Utc>>) -> TodoResponse {
+        TodoResponse {
+            id: Uuid::new_v4(),
+            title: "Write report".to_string(),
+            description: None,
+            completed: completed_at.is_some(),
+            completed_at,
+            created_at,
+            updated_at: completed_at.unwrap_or(created_at),
+            tags: Vec::new(),
+            due_date: None,
+            latitude: None,
+            longitude: None,
+            radius_m: None,
+            estimate_minutes: None,
+            custom_fields: json!({}),
+            metadata: json!({}),
+            draft: false,
+            start_date: None,
+        }
+    }
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 9, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn maps_events_to_triggers() {
+        let todo = todo(at(0), None);
+        let created = TodoEvent::Created(todo.clone());
+        assert_eq!(
+            Trigger::for_event(&created).map(|(trigger, _)| trigger),
+            Some(Trigger::NewTodo)
+        );
+        let completed = TodoEvent::Completed(todo.clone());
+        assert_eq!(
+            Trigger::for_event(&completed).map(|(trigger, _)| trigger),
+            Some(Trigger::CompletedTodo)
+        );
+        assert!(Trigger::for_event(&TodoEvent::Updated(todo)).is_none());
+    }
+
+    #[test]
+    fn parses_trigger_names() {
+        for trigger in [Trigger::NewTodo, Trigger::CompletedTodo] {
+            assert_eq!(trigger.name().parse::<Trigger>().unwrap(), trigger);
+        }
+        assert!(matches!(
+            "updated_todo".parse::<Trigger>(),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn new_todo_items_are_identified_by_the_todo() {
+        let todo = todo(at(0), None);
+        let item = TriggerItem::new(Trigger::NewTodo, todo.clone());
+        assert_eq!(item.id, todo.id.to_string());
+        assert_eq!(item.occurred_at, todo.created_at);
+    }
+
+    #[test]
+    fn completions_get_a_new_id_each_time() {
+        let mut todo = todo(at(0), Some(at(5)));
+        let first = TriggerItem::new(Trigger::CompletedTodo, todo.clone());
+        assert_eq!(
+            first.id,
+            format!("{}-{}", todo.id, at(5).timestamp_micros())
+        );
+        assert_eq!(first.occurred_at, at(5));
+
+        // Reopened and completed again
+        todo.completed_at = Some(at(5) + Duration::microseconds(1));
+        let again = TriggerItem::new(Trigger::CompletedTodo, todo);
+        assert_ne!(again.id, first.id);
+    }
+
+    #[test]
+    fn orders_items_newest_first() {
+        let old = TriggerItem::new(Trigger::NewTodo, todo(at(1), None));
+        let new = TriggerItem::new(Trigger::NewTodo, todo(at(3), None));
+        let tied_a = TriggerItem::new(Trigger::NewTodo, todo(at(2), None));
+        let tied_b = TriggerItem::new(Trigger::NewTodo, todo(at(2), None));
+        let (low, high) = if tied_a.id < tied_b.id {
+            (tied_a.id.clone(), tied_b.id.clone())
+        } else {
+            (tied_b.id.clone(), tied_a.id.clone())
+        };
+
+        let mut items = vec![tied_a, old.clone(), new.clone(), tied_b];
+        newest_first(&mut items);
+        let ids: Vec<&str> = items.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(ids, [new.id.as_str(), &high, &low, old.id.as_str()]);
+    }
+}
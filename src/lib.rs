@@ -0,0 +1,46 @@
+pub mod activation;
+pub mod auth;
+pub mod backup;
+pub mod body_log;
+pub mod build_info;
+pub mod caldav;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod client;
+pub mod clock;
+pub mod config;
+pub mod custom_fields;
+pub mod db;
+pub mod diagnostics;
+pub mod doctor;
+pub mod error;
+pub mod events;
+pub mod extractors;
+pub mod feed;
+pub mod fixtures;
+pub mod handlers;
+pub mod http_cache;
+pub mod ical;
+pub mod ids;
+pub mod import;
+pub mod input;
+pub mod integrations;
+pub mod loadtest;
+pub mod markdown;
+pub mod metrics;
+pub mod models;
+pub mod outbound;
+pub mod query_lint;
+pub mod rate_limit;
+pub mod repository;
+pub mod retention;
+pub mod rules;
+pub mod server;
+pub mod service;
+pub mod shadow;
+pub mod state;
+pub mod stats;
+pub mod timezone;
+pub mod typegen;
+pub mod ui;
+pub mod usage;
@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::process::Command;
+use uuid::Uuid;
+
+/// Server `loadtest` runs against when no URL is given
+pub const DEFAULT_URL: &str = "http://127.0.0.1:3000";
+
+/// Settings passed on to every wrk run
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub url: String,
+    pub duration: Duration,
+    pub connections: u32,
+    pub threads: u32,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_URL.to_string(),
+            duration: Duration::from_secs(10),
+            connections: 32,
+            threads: 4,
+        }
+    }
+}
+
+/// wrk script creating todos; open titles are unique, so each request gets
+/// one made of the run id, the wrk thread and a per-thread counter
+const CREATE_SCRIPT: &str = r#"
+wrk.method = "POST"
+wrk.headers["Content-Type"] = "application/json"
+
+local threads = 0
+function setup(thread)
+  threads = threads + 1
+  thread:set("id", threads)
+end
+
+local n = 0
+function request()
+  n = n + 1
+  return wrk.format(nil, "/todos", nil,
+    string.format('{"title":"loadtest {run} %d-%d","tags":["loadtest"]}', id, n))
+end
+"#;
+
+const LIST_SCRIPT: &str = r#"
+wrk.method = "GET"
+wrk.path = "/todos?per_page=20"
+"#;
+
+const UPDATE_SCRIPT: &str = r#"
+wrk.method = "PATCH"
+wrk.path = "/todos/{id}"
+wrk.headers["Content-Type"] = "application/json"
+wrk.body = '{"description":"loadtest {run}"}'
+"#;
+
+/// Drive a running server with wrk: create, list and update throughput,
+/// one scenario after the other. The todos created are left behind, so point
+/// it at a scratch deployment.
+pub async fn run(options: &Options) -> Result<(), String> {
+    let url = options.url.trim_end_matches('/');
+    let run_id = Uuid::new_v4().simple().to_string();
+    let target = seed_todo(url, &run_id).await?;
+
+    let scenarios = [
+        ("create", CREATE_SCRIPT.replace("{run}", &run_id)),
+        ("list", LIST_SCRIPT.to_string()),
+        (
+            "update",
+            UPDATE_SCRIPT
+                .replace("{id}", &target.to_string())
+                .replace("{run}", &run_id),
+        ),
+    ];
+    for (name, script) in scenarios {
+        let path = script_path(name, &run_id);
+        tokio::fs::write(&path, script)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        println!("== {} ==", name);
+        let status = Command::new("wrk")
+            .arg(format!("--threads={}", options.threads))
+            .arg(format!("--connections={}", options.connections))
+            .arg(format!("--duration={}s", options.duration.as_secs().max(1)))
+            .arg("--latency")
+            .arg("--script")
+            .arg(&path)
+            .arg(url)
+            .status()
+            .await;
+        let _ = tokio::fs::remove_file(&path).await;
+
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => return Err(format!("wrk failed on {} ({})", name, status)),
+            Err(e) => return Err(format!("Failed to run wrk (is it installed?): {}", e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// The todo every update request patches
+async fn seed_todo(url: &str, run_id: &str) -> Result<Uuid, String> {
+    #[derive(serde::Deserialize)]
+    struct Created {
+        id: Uuid,
+    }
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/todos", url))
+        .json(&serde_json::json!({ "title": format!("loadtest {} target", run_id) }))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to create a todo on {}: {}", url, e))?;
+    let created: Created = response
+        .json()
+        .await
+        .map_err(|e| format!("Unexpected response from {}: {}", url, e))?;
+    Ok(created.id)
+}
+
+fn script_path(name: &str, run_id: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("axum_todo-loadtest-{}-{}.lua", run_id, name))
+}
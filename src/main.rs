@@ -1,18 +1,46 @@
-mod db;
-mod error;
-mod handlers;
-mod models;
-mod repository;
-
 use axum::{
-    routing::{delete, get, patch, post},
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{any, delete, get, patch, post, put},
     Router,
 };
-use db::create_pool;
+use axum_todo::activation::spawn_activation;
+use axum_todo::backup::{Backup, MAX_BACKUP_BYTES};
+use axum_todo::body_log::{log_bodies, BodyLogOptions, BodyLogger};
+use axum_todo::caldav;
+#[cfg(feature = "chaos")]
+use axum_todo::chaos::{inject_faults, Chaos};
+use axum_todo::config::Config;
+use axum_todo::db::{create_pool, sync_open_title_index, verify_schema, DbPool};
+use axum_todo::doctor;
+use axum_todo::events::{BroadcastEventBus, EventBus};
+use axum_todo::fixtures;
+use axum_todo::handlers;
+use axum_todo::import::MAX_IMPORT_BYTES;
+use axum_todo::input::Sanitizer;
+use axum_todo::integrations::spawn_hook_delivery;
+use axum_todo::loadtest;
+use axum_todo::metrics::{metrics_handler, track_metrics, Metrics};
+use axum_todo::query_lint;
+use axum_todo::rate_limit::{rate_limit, RateLimiter};
+use axum_todo::repository::{PostgresTodoRepository, TodoRepository};
+use axum_todo::retention::{spawn_retention, Retention};
+use axum_todo::rules::spawn_rule_engine;
+use axum_todo::server::{listen, serve, shutdown_signal, ServerOptions};
+use axum_todo::service::{PageLimits, TodoService};
+use axum_todo::shadow::{shadow_reads, Shadow};
+use axum_todo::state::AppState;
+use axum_todo::stats::spawn_streaks_refresh;
+use axum_todo::timezone::localize_timestamps;
+use axum_todo::typegen::{export_types, DEFAULT_TYPES_DIR};
+use axum_todo::ui;
+use axum_todo::usage::{track_usage, UsageTracker};
 use dotenvy::dotenv;
-use repository::PostgresTodoRepository;
+use std::fs;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -31,47 +59,379 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // Get environment variables
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let port: u16 = std::env::var("PORT")
-        .unwrap_or_else(|_| "3000".to_string())
-        .parse()
-        .expect("PORT must be a valid u16");
+    // Subcommands run instead of the server
+    let mut args = std::env::args().skip(1);
+    if let Some(command) = args.next() {
+        if let Err(e) = run_command(&command, args).await {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Load configuration from environment variables
+    let config = Config::from_env().expect("Invalid configuration");
 
     // Create database connection pool
-    let pool = create_pool(&database_url)
+    let pool = create_pool(&config)
         .await
         .expect("Failed to create database pool");
 
     tracing::info!("Connected to database");
 
-    // Create repository
-    let repo: Arc<dyn repository::TodoRepository> = Arc::new(PostgresTodoRepository::new(pool));
+    // Refuse to serve against a schema this build wasn't compiled for
+    if config.verify_schema {
+        if let Err(e) = verify_schema(&pool).await {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+        tracing::info!("Database schema verified");
+    }
+
+    if let Err(e) = sync_open_title_index(&pool, config.unique_open_titles).await {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
+
+    // Create repository, event bus and service
+    let repo: Arc<dyn TodoRepository> = Arc::new(
+        PostgresTodoRepository::new(pool.clone())
+            .with_unique_open_titles(config.unique_open_titles),
+    );
+    let events: Arc<dyn EventBus> = Arc::new(BroadcastEventBus::default());
+    let service = Arc::new(
+        TodoService::new(repo, events)
+            .with_sanitizer(Sanitizer::new(config.collapse_whitespace))
+            .with_page_limits(PageLimits {
+                default_per_page: config.default_page_size,
+                max_per_page: config.max_page_size,
+            })
+            .with_workload_capacity(config.workload_capacity_minutes)
+            .with_id_generator(config.id_format.generator())
+            .with_bulk_confirm_threshold(config.bulk_confirm_threshold),
+    );
+    spawn_hook_delivery(service.clone());
+    spawn_rule_engine(service.clone());
+    spawn_activation(
+        service.clone(),
+        Duration::from_secs(config.activation_interval_secs.max(1)),
+    );
+    spawn_streaks_refresh(
+        service.clone(),
+        Duration::from_secs(config.stats_refresh_secs),
+    );
+    let retention = Arc::new(Retention::new(
+        config.retention_deleted_todos_days,
+        config.retention_rule_runs_days,
+        config.retention_batch_size,
+    ));
+    if !retention.statuses().is_empty() {
+        spawn_retention(
+            retention.clone(),
+            service.clone(),
+            Duration::from_secs(config.retention_interval_secs),
+        );
+    }
+    let public_addr = SocketAddr::new(config.host, config.port);
+    let internal_addr = config
+        .internal_port
+        .map(|port| SocketAddr::new(config.internal_host, port));
+    let server_options = ServerOptions::from(&config);
+    let reuse_port = config.reuse_port;
+    let rate_limiter = (config.rate_limit_requests > 0).then(|| {
+        Arc::new(RateLimiter::new(
+            config.rate_limit_requests,
+            Duration::from_secs(config.rate_limit_window_secs),
+        ))
+    });
+    let body_logger = config.log_bodies.then(|| {
+        Arc::new(BodyLogger::new(BodyLogOptions {
+            sample_rate: config.log_body_sample_rate,
+            routes: config.log_body_routes.clone(),
+            redact: config.log_body_redact.clone(),
+            max_per_minute: config.log_body_max_per_minute,
+            max_bytes: config.log_body_max_bytes,
+        }))
+    });
+    #[cfg(feature = "chaos")]
+    let chaos = config
+        .chaos_enabled
+        .then(|| Arc::new(Chaos::from_specs(&config.chaos_rules).expect("Invalid CHAOS_RULES")));
+    #[cfg(not(feature = "chaos"))]
+    if config.chaos_enabled {
+        tracing::warn!("CHAOS_ENABLED is ignored: this build lacks the chaos feature");
+    }
+    let shadow = config
+        .shadow_base_url
+        .as_deref()
+        .map(|base_url| Arc::new(Shadow::new(base_url, config.shadow_sample_rate)));
+    let metrics = Arc::new(Metrics::new().with_pool(pool));
+    let usage = Arc::new(UsageTracker::new());
+    let state = AppState {
+        service,
+        config: Arc::new(config),
+        metrics: metrics.clone(),
+        usage: usage.clone(),
+        retention,
+        rate_limiter: rate_limiter.clone(),
+        shadow: shadow.clone(),
+    };
 
     // Build our application with routes
-    let app = Router::new()
+    let mut app = Router::new()
+        .route("/", get(ui::index))
+        .route("/ui/{*path}", get(ui::asset))
+        .route("/version", get(handlers::get_version))
         .route("/todos", post(handlers::create_todo))
         .route("/todos", get(handlers::list_todos))
+        .route("/todos", patch(handlers::bulk_update))
+        .route("/todos/tags/bulk", post(handlers::bulk_tag))
+        .route("/todos/aggregate", get(handlers::aggregate_todos))
+        .route("/todos/nearby", get(handlers::nearby_todos))
+        .route("/todos/stale", get(handlers::stale_todos))
+        .route("/todos/calendar", get(handlers::todo_calendar))
+        .route("/custom-fields", post(handlers::create_custom_field))
+        .route("/custom-fields", get(handlers::list_custom_fields))
+        .route("/custom-fields/{id}", delete(handlers::delete_custom_field))
+        .route("/tags", get(handlers::list_tags))
+        .route("/tags/{tag}/style", put(handlers::set_tag_style))
+        .route("/me/streaks", get(handlers::get_streaks))
+        .route("/me/workload", get(handlers::get_workload))
+        .route("/me/review", get(handlers::get_weekly_review))
+        .route("/me/api-usage", get(handlers::get_api_usage))
         .route("/todos/{id}", get(handlers::get_todo))
         .route("/todos/{id}", patch(handlers::update_todo))
         .route("/todos/{id}", delete(handlers::delete_todo))
         .route("/todos/{id}/complete", patch(handlers::mark_completed))
+        .route("/todos/{id}/restore", post(handlers::restore_todo))
+        .route("/todos/{id}/publish", post(handlers::publish_todo))
+        .route("/todos/{id}/lock", post(handlers::lock_todo))
+        .route("/todos/{id}/unlock", post(handlers::unlock_todo))
+        .route("/todos/{id}/merge/{other_id}", post(handlers::merge_todo))
+        .route("/todos/{id}/relations", post(handlers::link_todo))
+        .route(
+            "/todos/{id}/relations/{related_id}",
+            delete(handlers::unlink_todo),
+        )
+        .route(
+            "/import/{provider}",
+            post(handlers::import_todos).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route("/feeds/completed.atom", get(handlers::completed_feed))
+        .route(
+            "/integrations/triggers/{trigger}",
+            get(handlers::poll_trigger),
+        )
+        .route("/integrations/hooks", post(handlers::subscribe_hook))
+        .route(
+            "/integrations/hooks/{id}",
+            delete(handlers::unsubscribe_hook),
+        )
+        .route("/rules", post(handlers::create_rule))
+        .route("/rules", get(handlers::list_rules))
+        .route("/rules/{id}", get(handlers::get_rule))
+        .route("/rules/{id}", patch(handlers::update_rule))
+        .route("/rules/{id}", delete(handlers::delete_rule))
+        .route("/rules/{id}/runs", get(handlers::list_rule_runs))
+        .route("/.well-known/caldav", any(caldav::well_known))
+        .route("/dav", any(caldav::home))
+        .route("/dav/", any(caldav::home))
+        .route("/dav/todos", any(caldav::calendar))
+        .route("/dav/todos/", any(caldav::calendar))
+        .route("/dav/todos/{name}", any(caldav::object));
+
+    // Operational endpoints, which can be kept off the public interface
+    let internal = Router::new()
+        .route("/admin/backup", get(handlers::create_backup))
+        .route("/admin/api-usage", get(handlers::list_api_usage))
+        .route("/admin/retention", get(handlers::get_retention))
+        .route("/admin/stats/refresh", post(handlers::refresh_stats))
+        .route("/admin/diagnostics", get(handlers::get_diagnostics))
+        .route("/admin/shadow", get(handlers::get_shadow))
+        .route(
+            "/admin/restore",
+            post(handlers::restore_backup).layer(DefaultBodyLimit::max(MAX_BACKUP_BYTES)),
+        )
+        .route("/metrics", get(metrics_handler));
+    if let Some(shadow) = shadow {
+        app = app.layer(middleware::from_fn_with_state(shadow, shadow_reads));
+    }
+    // Faults go on the API routes only, so monitoring keeps working
+    #[cfg(feature = "chaos")]
+    if let Some(chaos) = chaos {
+        tracing::warn!(rules = ?chaos.rules(), "Chaos mode is on: injecting latency and errors");
+        app = app.layer(middleware::from_fn_with_state(chaos, inject_faults));
+    }
+    if internal_addr.is_none() {
+        app = app.merge(internal.clone());
+    }
+
+    if let Some(logger) = body_logger {
+        app = app.layer(middleware::from_fn_with_state(logger, log_bodies));
+    }
+    if let Some(limiter) = rate_limiter {
+        app = app.layer(middleware::from_fn_with_state(limiter, rate_limit));
+    }
+    app = app.layer(middleware::from_fn(localize_timestamps));
+    app = app.layer(middleware::from_fn_with_state(usage, track_usage));
+    app = app.layer(middleware::from_fn_with_state(metrics, track_metrics));
+
+    let app = app
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
                 .allow_methods(Any)
-                .allow_headers(Any),
+                .allow_headers(Any)
+                .expose_headers(Any),
         )
+        .layer(middleware::from_fn(caldav::discovery_options))
         .layer(TraceLayer::new_for_http())
-        .with_state(repo);
+        .with_state(state.clone());
+
+    // Run the servers until a shutdown signal, then drain open connections
+    let internal_server = internal_addr.map(|addr| {
+        let internal = internal.layer(TraceLayer::new_for_http()).with_state(state);
+        let listener = listen(addr, 1, reuse_port).expect("Failed to bind the internal address");
+        log_listening("Internal endpoints", &listener, addr);
+        tokio::spawn(serve(
+            listener,
+            internal,
+            server_options.clone(),
+            shutdown_signal(),
+        ))
+    });
+
+    let listener = listen(public_addr, 0, reuse_port).expect("Failed to bind to address");
+    log_listening("Server", &listener, public_addr);
 
-    // Run the server
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    tracing::info!("Server listening on {}", addr);
+    serve(listener, app, server_options, shutdown_signal()).await;
+    if let Some(internal_server) = internal_server {
+        let _ = internal_server.await;
+    }
+    tracing::info!("Server stopped");
+}
+
+/// Log the address actually bound, which differs from the configured one for
+/// sockets inherited through socket activation
+fn log_listening(what: &str, listener: &tokio::net::TcpListener, configured: SocketAddr) {
+    let addr = listener.local_addr().unwrap_or(configured);
+    tracing::info!("{} listening on {}", what, addr);
+}
 
-    let listener = tokio::net::TcpListener::bind(addr)
+const USAGE: &str =
+    "Usage: axum_todo [export-types [DIR] | backup FILE | restore FILE | doctor | explain \
+                     | loadtest [URL] [SECONDS] \
+                     | generate [--todos N] [--completed RATIO] [--due RATIO] [--seed N]]";
+
+/// Run a CLI subcommand instead of the server
+async fn run_command(command: &str, mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    match command {
+        "export-types" => {
+            let dir = PathBuf::from(args.next().unwrap_or_else(|| DEFAULT_TYPES_DIR.into()));
+            export_types(&dir).map_err(|e| format!("Failed to export TypeScript types: {}", e))?;
+            tracing::info!("TypeScript types written to {}", dir.display());
+        }
+        "backup" => {
+            let path = args.next().ok_or(USAGE)?;
+            let backup = cli_service()
+                .await?
+                .create_backup()
+                .await
+                .map_err(|e| e.to_string())?;
+            let json = serde_json::to_vec_pretty(&backup).map_err(|e| e.to_string())?;
+            fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            tracing::info!("Backed up {} todos to {}", backup.todos.len(), path);
+        }
+        "restore" => {
+            let path = args.next().ok_or(USAGE)?;
+            let data = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let backup: Backup = serde_json::from_slice(&data)
+                .map_err(|e| format!("{} is not a valid backup: {}", path, e))?;
+            let report = cli_service()
+                .await?
+                .restore_backup(backup)
+                .await
+                .map_err(|e| e.to_string())?;
+            tracing::info!(
+                "Restored {} todos from {} (backup version {})",
+                report.todos,
+                path,
+                report.version
+            );
+        }
+        "doctor" => {
+            let report = doctor::run().await;
+            print!("{}", report);
+            match report.failures() {
+                0 => println!("All checks passed"),
+                failures => return Err(format!("{} check(s) failed", failures)),
+            }
+        }
+        "explain" => {
+            let report = query_lint::lint(&cli_pool().await?)
+                .await
+                .map_err(|e| e.to_string())?;
+            print!("{}", report);
+            match report.warnings() {
+                0 => println!("Every query can use an index"),
+                warnings => return Err(format!("{} query(s) need a sequential scan", warnings)),
+            }
+        }
+        "loadtest" => {
+            let mut options = loadtest::Options::default();
+            if let Some(url) = args.next() {
+                options.url = url;
+            }
+            if let Some(seconds) = args.next() {
+                let seconds = seconds
+                    .parse()
+                    .map_err(|_| format!("Invalid duration: {}\n{}", seconds, USAGE))?;
+                options.duration = Duration::from_secs(seconds);
+            }
+            loadtest::run(&options).await?;
+        }
+        "generate" => {
+            let mut options = fixtures::Options::default();
+            while let Some(flag) = args.next() {
+                let value = args.next().ok_or(USAGE)?;
+                let invalid = || format!("Invalid value for {}: {}\n{}", flag, value, USAGE);
+                match flag.as_str() {
+                    "--todos" => options.todos = value.parse().map_err(|_| invalid())?,
+                    "--completed" => {
+                        options.completed_ratio = value.parse().map_err(|_| invalid())?
+                    }
+                    "--due" => options.due_ratio = value.parse().map_err(|_| invalid())?,
+                    "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+                    _ => return Err(format!("Unknown option: {}\n{}", flag, USAGE)),
+                }
+            }
+            let inserted = fixtures::generate(&cli_pool().await?, &options)
+                .await
+                .map_err(|e| e.to_string())?;
+            tracing::info!("Inserted {} synthetic todos", inserted);
+        }
+        other => return Err(format!("Unknown command: {}\n{}", other, USAGE)),
+    }
+
+    Ok(())
+}
+
+/// Pool for the configured database (schema verified), for CLI commands
+async fn cli_pool() -> Result<DbPool, String> {
+    let config = Config::from_env()?;
+    let pool = create_pool(&config)
         .await
-        .expect("Failed to bind to address");
+        .map_err(|e| format!("Failed to create database pool: {}", e))?;
+    if config.verify_schema {
+        verify_schema(&pool).await.map_err(|e| e.to_string())?;
+    }
+    Ok(pool)
+}
 
-    axum::serve(listener, app).await.expect("Server error");
+/// Service connected to the configured database, for CLI commands
+async fn cli_service() -> Result<TodoService, String> {
+    let pool = cli_pool().await?;
+    let repo: Arc<dyn TodoRepository> = Arc::new(PostgresTodoRepository::new(pool));
+    let events: Arc<dyn EventBus> = Arc::new(BroadcastEventBus::default());
+    Ok(TodoService::new(repo, events))
 }
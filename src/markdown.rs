@@ -0,0 +1,28 @@
+use ammonia::Builder;
+use pulldown_cmark::{html, Options, Parser};
+use std::sync::LazyLock;
+
+/// HTML sanitizer: ammonia's defaults, plus the read-only checkboxes
+/// produced by task lists
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["checked", "disabled"])
+        .set_tag_attribute_value("input", "type", "checkbox")
+        .set_tag_attribute_value("input", "disabled", "");
+    builder
+});
+
+/// Render Markdown to HTML that is safe to inject into a page: raw HTML in
+/// the source is sanitized (scripts, event handlers, `javascript:` links...)
+pub fn render_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options));
+
+    SANITIZER.clean(&unsafe_html).to_string()
+}
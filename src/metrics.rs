@@ -0,0 +1,373 @@
+use crate::db::DbPool;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Upper bounds (seconds) of the request latency histogram buckets
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Response header carrying the trace id recorded in exemplars
+pub const TRACE_ID_HEADER: &str = "x-trace-id";
+
+/// Content type of the OpenMetrics text format (required for exemplars)
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct RouteKey {
+    method: String,
+    route: String,
+}
+
+#[derive(Debug, Clone)]
+struct Exemplar {
+    trace_id: String,
+    value: f64,
+    timestamp: f64,
+}
+
+#[derive(Debug, Default)]
+struct RouteMetrics {
+    in_flight: i64,
+    responses: BTreeMap<u16, u64>,
+    /// Non-cumulative observation counts, one slot per bucket plus +Inf
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    exemplars: [Option<Exemplar>; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+/// Saturation of the database pool
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PoolStats {
+    pub busy: u32,
+    pub idle: u32,
+    pub max: u32,
+}
+
+/// In-process request metrics registry, rendered in OpenMetrics format
+#[derive(Default)]
+pub struct Metrics {
+    routes: Mutex<HashMap<RouteKey, RouteMetrics>>,
+    /// Database pool whose saturation is reported alongside the requests
+    pool: Option<DbPool>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also report connection gauges for the given database pool
+    pub fn with_pool(mut self, pool: DbPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Connection counts of the attached database pool, if any
+    pub fn pool_stats(&self) -> Option<PoolStats> {
+        self.pool.as_ref().map(|pool| {
+            let open = pool.size();
+            let idle = pool.num_idle() as u32;
+            PoolStats {
+                busy: open.saturating_sub(idle),
+                idle,
+                max: pool.options().get_max_connections(),
+            }
+        })
+    }
+
+    fn start<'a>(&'a self, key: &'a RouteKey) -> InFlightGuard<'a> {
+        let mut routes = self.routes.lock().unwrap();
+        routes.entry(key.clone()).or_default().in_flight += 1;
+        InFlightGuard { metrics: self, key }
+    }
+
+    fn finish(&self, key: &RouteKey, status: u16, seconds: f64, trace_id: String) {
+        let mut routes = self.routes.lock().unwrap();
+        let route = routes.entry(key.clone()).or_default();
+
+        *route.responses.entry(status).or_default() += 1;
+
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        route.buckets[bucket] += 1;
+        route.exemplars[bucket] = Some(Exemplar {
+            trace_id,
+            value: seconds,
+            timestamp: unix_now(),
+        });
+        route.sum += seconds;
+        route.count += 1;
+    }
+
+    /// Render all metrics in the OpenMetrics text exposition format
+    pub fn render(&self) -> String {
+        let routes = self.routes.lock().unwrap();
+        let mut keys: Vec<&RouteKey> = routes.keys().collect();
+        keys.sort();
+
+        let mut out = String::new();
+
+        out.push_str("# HELP http_requests Completed HTTP requests by route and status.\n");
+        out.push_str("# TYPE http_requests counter\n");
+        for key in &keys {
+            for (status, count) in &routes[*key].responses {
+                let _ = writeln!(
+                    out,
+                    "http_requests_total{{{},status=\"{}\"}} {}",
+                    labels(key),
+                    status,
+                    count
+                );
+            }
+        }
+
+        out.push_str("# HELP http_request_duration_seconds HTTP request latency by route.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for key in &keys {
+            let route = &routes[*key];
+            let mut cumulative = 0;
+            for (i, count) in route.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = LATENCY_BUCKETS
+                    .get(i)
+                    .map(|bound| format!("{:?}", bound))
+                    .unwrap_or_else(|| "+Inf".to_string());
+                let _ = write!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels(key),
+                    le,
+                    cumulative
+                );
+                if let Some(exemplar) = &route.exemplars[i] {
+                    let _ = write!(
+                        out,
+                        " # {{trace_id=\"{}\"}} {} {:.3}",
+                        exemplar.trace_id, exemplar.value, exemplar.timestamp
+                    );
+                }
+                out.push('\n');
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_sum{{{}}} {}",
+                labels(key),
+                route.sum
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_count{{{}}} {}",
+                labels(key),
+                route.count
+            );
+        }
+
+        out.push_str("# HELP http_requests_in_flight HTTP requests currently being served.\n");
+        out.push_str("# TYPE http_requests_in_flight gauge\n");
+        for key in &keys {
+            let _ = writeln!(
+                out,
+                "http_requests_in_flight{{{}}} {}",
+                labels(key),
+                routes[*key].in_flight
+            );
+        }
+
+        if let Some(pool) = self.pool_stats() {
+            out.push_str("# HELP db_pool_connections Open database connections by state.\n");
+            out.push_str("# TYPE db_pool_connections gauge\n");
+            let _ = writeln!(out, "db_pool_connections{{state=\"busy\"}} {}", pool.busy);
+            let _ = writeln!(out, "db_pool_connections{{state=\"idle\"}} {}", pool.idle);
+            out.push_str(
+                "# HELP db_pool_max_connections Connections the database pool may open.\n",
+            );
+            out.push_str("# TYPE db_pool_max_connections gauge\n");
+            let _ = writeln!(out, "db_pool_max_connections {}", pool.max);
+        }
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// Decrements the in-flight gauge when the request finishes or is cancelled
+struct InFlightGuard<'a> {
+    metrics: &'a Metrics,
+    key: &'a RouteKey,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        let mut routes = self.metrics.routes.lock().unwrap();
+        if let Some(route) = routes.get_mut(self.key) {
+            route.in_flight -= 1;
+        }
+    }
+}
+
+fn labels(key: &RouteKey) -> String {
+    format!("method=\"{}\",route=\"{}\"", key.method, escape(&key.route))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// Trace id of the incoming request: taken from a W3C `traceparent` header
+/// when an upstream proxy started the trace, otherwise freshly generated
+fn trace_id(headers: &HeaderMap) -> String {
+    headers
+        .get("traceparent")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split('-').nth(1))
+        .filter(|id| id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_else(|| Uuid::new_v4().simple().to_string())
+}
+
+/// Middleware recording per-route latency (with trace id exemplars), status
+/// counts and in-flight requests
+pub async fn track_metrics(
+    State(metrics): State<Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = RouteKey {
+        method: request.method().to_string(),
+        route: request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_string())
+            .unwrap_or_else(|| "unmatched".to_string()),
+    };
+    let trace_id = trace_id(request.headers());
+
+    let in_flight = metrics.start(&key);
+    let started = Instant::now();
+    let mut response = next.run(request).await;
+    let elapsed = started.elapsed().as_secs_f64();
+    drop(in_flight);
+
+    if let Ok(value) = HeaderValue::from_str(&trace_id) {
+        response.headers_mut().insert(TRACE_ID_HEADER, value);
+    }
+    metrics.finish(&key, response.status().as_u16(), elapsed, trace_id);
+
+    response
+}
+
+/// Expose the collected metrics for Prometheus scraping
+pub async fn metrics_handler(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)],
+        metrics.render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> RouteKey {
+        RouteKey {
+            method: "GET".to_string(),
+            route: "/todos/{id}".to_string(),
+        }
+    }
+
+    #[test]
+    fn renders_cumulative_buckets_with_exemplars() {
+        let metrics = Metrics::new();
+        metrics.finish(&key(), 200, 0.003, "a".repeat(32));
+        metrics.finish(&key(), 200, 0.2, "b".repeat(32));
+        metrics.finish(&key(), 404, 20.0, "c".repeat(32));
+        let out = metrics.render();
+        let labels = "method=\"GET\",route=\"/todos/{id}\"";
+
+        assert!(out.contains(&format!(
+            "http_requests_total{{{},status=\"200\"}} 2\n",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "http_requests_total{{{},status=\"404\"}} 1\n",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "http_request_duration_seconds_bucket{{{},le=\"0.005\"}} 1 # {{trace_id=\"{}\"}} 0.003 ",
+            labels,
+            "a".repeat(32)
+        )));
+        assert!(out.contains(&format!(
+            "http_request_duration_seconds_bucket{{{},le=\"0.1\"}} 1\n",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "http_request_duration_seconds_bucket{{{},le=\"0.25\"}} 2 ",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 3 ",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "http_request_duration_seconds_count{{{}}} 3\n",
+            labels
+        )));
+        assert!(out.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn in_flight_gauge_follows_the_guard() {
+        let metrics = Metrics::new();
+        let key = key();
+        let gauge = |n: i64| format!("http_requests_in_flight{{{}}} {}\n", labels(&key), n);
+        let guard = metrics.start(&key);
+        assert!(metrics.render().contains(&gauge(1)));
+        drop(guard);
+        assert!(metrics.render().contains(&gauge(0)));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn takes_the_trace_id_from_traceparent() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            HeaderValue::from_static("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"),
+        );
+        assert_eq!(trace_id(&headers), "4bf92f3577b34da6a3ce929d0e0e4736");
+
+        headers.insert("traceparent", HeaderValue::from_static("00-not-a-trace-01"));
+        let generated = trace_id(&headers);
+        assert_eq!(generated.len(), 32);
+        assert_ne!(generated, "not");
+    }
+}
@@ -1,33 +1,656 @@
-use chrono::{DateTime, Utc};
+use crate::error::AppError;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use sqlx::FromRow;
+use std::collections::HashMap;
+use std::str::FromStr;
+use ts_rs::TS;
 use uuid::Uuid;
 
 /// Full Todo model from database
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, TS)]
 pub struct Todo {
     pub id: Uuid,
     pub title: String,
     pub description: Option<String>,
     pub completed: bool,
+    /// When the todo was completed; `None` while it is open
+    pub completed_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Geofence around the location, in meters
+    pub radius_m: Option<i32>,
+    /// Expected effort
+    pub estimate_minutes: Option<i32>,
+    /// Values of custom fields, keyed by field name
+    #[ts(type = "Record<string, unknown>")]
+    pub custom_fields: Value,
+    /// Client state stored as given
+    #[ts(type = "Record<string, unknown>")]
+    pub metadata: Value,
+    /// Still being filled in; hidden from lists until published
+    pub draft: bool,
+    /// Before this the todo is scheduled rather than active
+    pub start_date: Option<DateTime<Utc>>,
+}
+
+impl Todo {
+    /// Whether the write that produced this version is the one that completed
+    /// the todo: `completed_at` is set to the write's timestamp only when an
+    /// open todo is completed, and kept as is by later writes
+    pub fn just_completed(&self) -> bool {
+        self.completed && self.completed_at == Some(self.updated_at)
+    }
 }
 
 /// Request DTO for creating a new todo
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, TS)]
 pub struct CreateTodo {
+    /// Client-generated id (offline-first clients); generated when absent.
+    /// 409 if any todo, even a deleted one, already has it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub id: Option<Uuid>,
     pub title: String,
+    #[ts(optional = nullable)]
     pub description: Option<String>,
+    #[serde(default)]
+    #[ts(as = "Option<Vec<String>>", optional)]
+    pub tags: Vec<String>,
+    #[ts(optional = nullable)]
+    pub due_date: Option<DateTime<Utc>>,
+    /// Hidden from `?active_only=true` lists until then; not after `due_date`
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub start_date: Option<DateTime<Utc>>,
+    /// Set together with `longitude`
+    #[ts(optional = nullable)]
+    pub latitude: Option<f64>,
+    #[ts(optional = nullable)]
+    pub longitude: Option<f64>,
+    /// Needs a location
+    #[ts(optional = nullable)]
+    pub radius_m: Option<i32>,
+    #[ts(optional = nullable)]
+    pub estimate_minutes: Option<i32>,
+    /// Values of defined custom fields; null values are left out
+    #[serde(default)]
+    #[ts(as = "Option<HashMap<String, Value>>", optional)]
+    pub custom_fields: Map<String, Value>,
+    #[serde(default)]
+    #[ts(as = "Option<HashMap<String, Value>>", optional)]
+    pub metadata: Map<String, Value>,
+    /// Create a draft, which may have an empty title until it is published
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub draft: bool,
 }
 
 /// Request DTO for updating an existing todo
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, TS)]
 pub struct UpdateTodo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
     pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
     pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub completed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub due_date: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub start_date: Option<DateTime<Utc>>,
+    /// Set together with `longitude`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub latitude: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub longitude: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub radius_m: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub estimate_minutes: Option<i32>,
+    /// Custom field values to set; a null value removes the field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(as = "Option<HashMap<String, Value>>", optional)]
+    pub custom_fields: Option<Map<String, Value>>,
+    /// JSON Merge Patch applied to the metadata: objects merge recursively
+    /// and a null value removes a key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(as = "Option<HashMap<String, Value>>", optional)]
+    pub metadata: Option<Map<String, Value>>,
+}
+
+impl UpdateTodo {
+    /// Whether the update leaves every field as it is
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.completed.is_none()
+            && self.tags.is_none()
+            && self.due_date.is_none()
+            && self.start_date.is_none()
+            && self.latitude.is_none()
+            && self.longitude.is_none()
+            && self.radius_m.is_none()
+            && self.estimate_minutes.is_none()
+            && self.custom_fields.is_none()
+            && self.metadata.is_none()
+    }
+}
+
+/// Filter selecting a set of todos (used by list and bulk endpoints)
+#[derive(Debug, Default, Clone, Serialize, Deserialize, TS)]
+pub struct TodoQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
     pub completed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub tag: Option<String>,
+    /// Custom field filter, `name:value`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub field: Option<String>,
+    /// Select drafts instead of published todos
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub draft: bool,
+    /// Leave out todos whose start date is still ahead
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub active_only: bool,
+    /// `field` resolved against its definition by the service
+    #[serde(skip)]
+    #[ts(skip)]
+    pub custom_fields: Option<Value>,
+}
+
+/// Operation applied by a bulk tag request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum TagAction {
+    Add,
+    Remove,
+}
+
+/// Request DTO for adding/removing a tag across every todo matching a filter
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct BulkTagRequest {
+    pub action: TagAction,
+    pub tag: String,
+    #[serde(default)]
+    #[ts(as = "Option<TodoQuery>", optional)]
+    pub filter: TodoQuery,
+}
+
+/// Tag with the number of live todos using it and its display style
+#[derive(Debug, Serialize, Deserialize, FromRow, TS)]
+pub struct TagUsage {
+    pub tag: String,
+    #[ts(type = "number")]
+    pub count: i64,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+}
+
+/// How clients should render a tag
+#[derive(Debug, Serialize, Deserialize, FromRow, TS)]
+pub struct TagStyle {
+    pub tag: String,
+    /// A single emoji
+    pub icon: Option<String>,
+    /// Hex color, normalized to `#rrggbb`
+    pub color: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request DTO for setting a tag's style; omitted or null fields are cleared
+#[derive(Debug, Default, Serialize, Deserialize, TS)]
+pub struct SetTagStyle {
+    #[serde(default)]
+    #[ts(optional)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub color: Option<String>,
+}
+
+/// Dimension todos are grouped by in an aggregate. `priority` is refused
+/// with a 400 rather than left out silently: todos have no priority field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    /// Every tag of a todo (a todo with several tags counts in each)
+    Tag,
+    /// `open` or `completed`
+    Status,
+    /// Creation day (UTC, `YYYY-MM-DD`)
+    Day,
+}
+
+/// Value computed for each group of an aggregate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Count,
+    /// Mean seconds from creation to completion, over completed todos
+    AvgCompletionTime,
+}
+
+impl FromStr for GroupBy {
+    type Err = AppError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "tag" => Ok(GroupBy::Tag),
+            "status" => Ok(GroupBy::Status),
+            "day" => Ok(GroupBy::Day),
+            "priority" => Err(AppError::BadRequest(
+                "group_by=priority is not supported: todos have no priority".to_string(),
+            )),
+            other => Err(AppError::BadRequest(format!(
+                "Unknown group_by '{}', supported: tag, status, day (not priority: todos have none)",
+                other
+            ))),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = AppError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "count" => Ok(Metric::Count),
+            "avg_completion_time" => Ok(Metric::AvgCompletionTime),
+            other => Err(AppError::BadRequest(format!(
+                "Unknown metric '{}', supported: count, avg_completion_time",
+                other
+            ))),
+        }
+    }
+}
+
+/// One group of an aggregate; `value` is null when no todo of the group
+/// contributes to the metric (e.g. none completed)
+#[derive(Debug, Serialize, Deserialize, FromRow, TS)]
+pub struct AggregateBucket {
+    pub key: String,
+    pub value: Option<f64>,
+}
+
+/// Response DTO for aggregates, with buckets ordered by key
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct Aggregate {
+    pub group_by: GroupBy,
+    pub metric: Metric,
+    pub buckets: Vec<AggregateBucket>,
+}
+
+/// Estimated effort of the open todos due on one UTC day
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct WorkloadDay {
+    pub date: NaiveDate,
+    /// Sum of the estimates of the day's todos
+    #[ts(type = "number")]
+    pub estimate_minutes: i64,
+    #[ts(type = "number")]
+    pub todos: i64,
+    /// Todos due that day without an estimate (not counted in the sum)
+    #[ts(type = "number")]
+    pub unestimated: i64,
+    pub over_capacity: bool,
+}
+
+/// Response DTO for the workload report, one entry per day of the range
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct Workload {
+    pub capacity_minutes: u32,
+    pub days: Vec<WorkloadDay>,
+    /// One message per day over capacity
+    pub warnings: Vec<String>,
+}
+
+/// The todos due on one UTC day, earliest due first
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    pub todos: Vec<TodoResponse>,
+}
+
+/// Response DTO for the calendar view, one entry per day of the range
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct Calendar {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub days: Vec<CalendarDay>,
+}
+
+/// Response DTO for the weekly review of one ISO week (Monday to Sunday, UTC)
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct WeeklyReview {
+    /// ISO week, e.g. `2024-W23`
+    pub week: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Todos completed during the week, earliest first
+    pub completed: Vec<TodoResponse>,
+    /// Todos created before the week and still open at its end, oldest first
+    pub carried_over: Vec<TodoResponse>,
+    /// Number of todos created during the week
+    #[ts(type = "number")]
+    pub created: i64,
+    /// Open todos untouched for a while, candidates for closing
+    pub stale: Vec<TodoResponse>,
+}
+
+/// Response DTO for bulk operations
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct BulkResult {
+    #[ts(type = "number")]
+    pub affected: u64,
+    /// Matching todos left alone because someone else holds their edit lock
+    #[serde(default)]
+    #[ts(type = "number")]
+    pub locked: u64,
+    /// Matching todos a tag wasn't added to because they already have the
+    /// maximum number of tags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<Uuid>>", optional)]
+    pub at_tag_limit: Vec<Uuid>,
+    /// Nothing was changed; `affected` is how many todos would have been
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// A todo read from another service's export, ready to be inserted as-is
+/// (completion state and timestamps are preserved)
+#[derive(Debug, Clone, Default)]
+pub struct ImportedTodo {
+    pub title: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub completed: bool,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// An entry of an import that was not (or would not be) imported
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct ImportIssue {
+    /// 1-based position of the entry in the export file
+    #[ts(type = "number")]
+    pub item: usize,
+    pub title: Option<String>,
+    pub reason: String,
+}
+
+/// Outcome of an import; with `dry_run` nothing was written and `imported`
+/// counts the todos that would have been created
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct ImportReport {
+    pub provider: String,
+    pub dry_run: bool,
+    #[ts(type = "number")]
+    pub total: usize,
+    #[ts(type = "number")]
+    pub imported: usize,
+    #[ts(type = "number")]
+    pub skipped: usize,
+    pub issues: Vec<ImportIssue>,
 }
 
 /// Response DTO for todo operations
-pub type TodoResponse = Todo;
\ No newline at end of file
+pub type TodoResponse = Todo;
+
+/// How a todo is linked to another one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationKind {
+    /// Undirected: the link reads the same from both todos
+    RelatesTo,
+    Duplicates,
+    CausedBy,
+}
+
+impl RelationKind {
+    /// Name stored in the database (matches the serialized form)
+    pub fn name(&self) -> &'static str {
+        match self {
+            RelationKind::RelatesTo => "relates_to",
+            RelationKind::Duplicates => "duplicates",
+            RelationKind::CausedBy => "caused_by",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            RelationKind::RelatesTo,
+            RelationKind::Duplicates,
+            RelationKind::CausedBy,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+}
+
+/// Which end of a link a todo is on: `outgoing` when the todo is the
+/// subject ("this duplicates X"), `incoming` when it is the object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationDirection {
+    Outgoing,
+    Incoming,
+}
+
+/// Request DTO for linking a todo to another one
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct CreateRelation {
+    pub related_id: Uuid,
+    pub kind: RelationKind,
+}
+
+/// A link: `todo_id` <kind> `related_id`
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct Relation {
+    pub id: Uuid,
+    pub todo_id: Uuid,
+    pub related_id: Uuid,
+    pub kind: RelationKind,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A live todo linked to the one being read
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct RelatedTodo {
+    pub id: Uuid,
+    pub title: String,
+    pub completed: bool,
+    pub kind: RelationKind,
+    pub direction: RelationDirection,
+}
+
+/// A todo with the todos linked to it (detail view)
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct TodoDetail {
+    #[serde(flatten)]
+    pub todo: Todo,
+    pub related: Vec<RelatedTodo>,
+}
+
+/// Todo with its Markdown description rendered to sanitized HTML
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct RenderedTodo {
+    #[serde(flatten)]
+    pub todo: TodoDetail,
+    pub description_html: Option<String>,
+}
+
+/// Todo whose location is near a point, with its distance to the point
+#[derive(Debug, Serialize, Deserialize, FromRow, TS)]
+pub struct NearbyTodo {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub todo: Todo,
+    pub distance_m: f64,
+}
+
+/// What creating a todo does when an open todo already has its title
+/// (`?on_conflict=`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+    /// 409 DUPLICATE_TODO naming the existing todo
+    #[default]
+    Fail,
+    /// 200 with the existing todo, which is left unchanged
+    ReturnExisting,
+}
+
+/// How a list endpoint computes `meta.total` (`?count=`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum CountMode {
+    /// `COUNT(*)` over the filter; slows down as the table grows
+    #[default]
+    Exact,
+    /// The query planner's row estimate; flat cost, approximate
+    Estimated,
+    /// No total; use `has_more` to page
+    None,
+}
+
+/// Pagination metadata returned alongside a page of results
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct PageMeta {
+    pub page: u32,
+    pub per_page: u32,
+    /// Omitted with `count=none`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number", optional)]
+    pub total: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number", optional)]
+    pub total_pages: Option<i64>,
+    /// True when `total` is a planner estimate rather than an exact count
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub total_estimated: bool,
+    /// Whether a next page exists
+    pub has_more: bool,
+    /// Quotas the client has used most of
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<QuotaWarning>>", optional)]
+    pub quota_warnings: Vec<QuotaWarning>,
+}
+
+/// A quota the client is close to exhausting
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct QuotaWarning {
+    /// Which quota, e.g. `rate`
+    pub quota: String,
+    #[ts(type = "number")]
+    pub used: u64,
+    #[ts(type = "number")]
+    pub limit: u64,
+    pub message: String,
+}
+
+/// A single page of results
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub meta: PageMeta,
+}
+
+/// Request DTO for taking (or extending) an edit lock on a todo
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct LockTodo {
+    /// Who is editing, shown to anyone else trying to (e.g. a user name)
+    pub holder: String,
+    /// How long the lock lasts unless released or extended (default 300)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub ttl_secs: Option<u32>,
+}
+
+/// Request DTO for releasing an edit lock
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct UnlockTodo {
+    pub holder: String,
+}
+
+/// An edit lock held on a todo
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, TS)]
+pub struct TodoLock {
+    pub todo_id: Uuid,
+    pub holder: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl TodoLock {
+    /// Whether the lock keeps `holder` from writing at `now`: it hasn't
+    /// expired and someone else holds it
+    pub fn blocks(&self, holder: Option<&str>, now: DateTime<Utc>) -> bool {
+        self.expires_at > now && holder != Some(self.holder.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn locks_block_other_holders_until_they_expire() {
+        let now = Utc::now();
+        let lock = TodoLock {
+            todo_id: Uuid::new_v4(),
+            holder: "Alice".to_string(),
+            expires_at: now + Duration::minutes(5),
+        };
+        assert!(lock.blocks(Some("Bob"), now));
+        assert!(lock.blocks(None, now));
+        assert!(!lock.blocks(Some("Alice"), now));
+        // Holder names are compared exactly, after trimming on the way in
+        assert!(lock.blocks(Some("alice"), now));
+        assert!(!lock.blocks(Some("Bob"), lock.expires_at));
+        assert!(!lock.blocks(None, now + Duration::minutes(6)));
+    }
+
+    #[test]
+    fn group_by_names_its_dimensions() {
+        assert_eq!("tag".parse::<GroupBy>().unwrap(), GroupBy::Tag);
+        assert_eq!("status".parse::<GroupBy>().unwrap(), GroupBy::Status);
+        assert_eq!("day".parse::<GroupBy>().unwrap(), GroupBy::Day);
+    }
+
+    #[test]
+    fn group_by_priority_explains_why_it_is_refused() {
+        let error = "priority".parse::<GroupBy>().unwrap_err();
+        assert!(
+            matches!(&error, AppError::BadRequest(message) if message.contains("no priority")),
+            "{}",
+            error
+        );
+        let error = "owner".parse::<GroupBy>().unwrap_err();
+        assert!(matches!(error, AppError::BadRequest(message) if message.contains("priority")));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::db::DbPool;
+use crate::error::AppError;
+use serde_json::Value;
+use sqlx::types::Json;
+use std::fmt;
+
+/// The hot read queries, with sample values in place of their parameters.
+/// They mirror the SQL in `repository.rs` and must be updated with it.
+const QUERIES: &[(&str, &str)] = &[
+    (
+        "list todos",
+        "SELECT id FROM todos WHERE deleted_at IS NULL \
+         ORDER BY created_at DESC LIMIT 20",
+    ),
+    (
+        "list open todos",
+        "SELECT id FROM todos WHERE deleted_at IS NULL AND completed = false \
+         ORDER BY created_at DESC LIMIT 20",
+    ),
+    (
+        "list todos by tag",
+        "SELECT id FROM todos WHERE deleted_at IS NULL AND tags @> ARRAY['work'] \
+         ORDER BY created_at DESC LIMIT 20",
+    ),
+    (
+        "count todos by tag",
+        "SELECT COUNT(*) FROM todos WHERE deleted_at IS NULL AND tags @> ARRAY['work']",
+    ),
+    (
+        "count todos by custom field",
+        "SELECT COUNT(*) FROM todos WHERE deleted_at IS NULL \
+         AND custom_fields @> '{\"env\": \"prod\"}'",
+    ),
+    (
+        "recently completed",
+        "SELECT id FROM todos WHERE completed AND deleted_at IS NULL \
+         ORDER BY completed_at DESC NULLS LAST LIMIT 50",
+    ),
+    (
+        "workload",
+        "SELECT (due_date AT TIME ZONE 'UTC')::date, COUNT(*) FROM todos \
+         WHERE due_date IS NOT NULL AND deleted_at IS NULL AND completed IS NOT TRUE \
+           AND (due_date AT TIME ZONE 'UTC')::date BETWEEN CURRENT_DATE AND CURRENT_DATE + 13 \
+         GROUP BY 1",
+    ),
+    (
+        "related todos",
+        "SELECT r.id FROM todo_relations r \
+         WHERE r.todo_id = '00000000-0000-0000-0000-000000000001' \
+            OR r.related_id = '00000000-0000-0000-0000-000000000001'",
+    ),
+];
+
+/// EXPLAIN outcome of one query
+#[derive(Debug, Clone)]
+pub struct QueryPlan {
+    pub name: &'static str,
+    /// Tables read with a sequential scan even though they were discouraged
+    pub seq_scans: Vec<String>,
+    pub total_cost: f64,
+}
+
+/// Plans of every linted query
+#[derive(Debug, Default)]
+pub struct Report {
+    pub plans: Vec<QueryPlan>,
+}
+
+impl Report {
+    pub fn warnings(&self) -> usize {
+        self.plans
+            .iter()
+            .filter(|plan| !plan.seq_scans.is_empty())
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for plan in &self.plans {
+            if plan.seq_scans.is_empty() {
+                writeln!(f, "[OK]   {} (cost {:.1})", plan.name, plan.total_cost)?;
+            } else {
+                writeln!(
+                    f,
+                    "[WARN] {} (cost {:.1}): sequential scan on {}",
+                    plan.name,
+                    plan.total_cost,
+                    plan.seq_scans.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// EXPLAIN every hot query and flag those that can only be answered with a
+/// sequential scan. Sequential scans are disabled while planning, so a small
+/// development table doesn't hide a missing index: one still showing up
+/// means no index can serve the query.
+pub async fn lint(pool: &DbPool) -> Result<Report, AppError> {
+    let mut report = Report::default();
+
+    for (name, sql) in QUERIES {
+        let mut tx = pool.begin().await?;
+        sqlx::query("SET LOCAL enable_seqscan = off")
+            .execute(&mut *tx)
+            .await?;
+        let plan: Json<Value> = sqlx::query_scalar(&format!("EXPLAIN (FORMAT JSON) {}", sql))
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.rollback().await?;
+
+        let root = &plan.0[0]["Plan"];
+        let mut seq_scans = Vec::new();
+        collect_seq_scans(root, &mut seq_scans);
+        report.plans.push(QueryPlan {
+            name,
+            seq_scans,
+            total_cost: root["Total Cost"].as_f64().unwrap_or_default(),
+        });
+    }
+
+    Ok(report)
+}
+
+fn collect_seq_scans(node: &Value, found: &mut Vec<String>) {
+    if node["Node Type"] == "Seq Scan" {
+        if let Some(relation) = node["Relation Name"].as_str() {
+            if !found.iter().any(|name| name == relation) {
+                found.push(relation.to_string());
+            }
+        }
+    }
+    for child in node["Plans"].as_array().into_iter().flatten() {
+        collect_seq_scans(child, found);
+    }
+}
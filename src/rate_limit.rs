@@ -0,0 +1,229 @@
+use crate::error::HttpError;
+use crate::models::QuotaWarning;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Number of tracked clients after which expired windows are swept
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Share of a quota after which responses carry a warning
+pub const QUOTA_WARNING_RATIO: f64 = 0.8;
+
+/// Response header announcing quotas that are nearly used up
+pub const QUOTA_WARNING_HEADER: &str = "x-quota-warning";
+
+/// Outcome of a rate limit check, used to populate the response headers
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the current window resets
+    pub reset: u64,
+    pub allowed: bool,
+}
+
+impl RateLimitStatus {
+    /// Warning to send when at least `QUOTA_WARNING_RATIO` of the window's
+    /// requests are used
+    pub fn warning(&self) -> Option<QuotaWarning> {
+        let used = self.limit - self.remaining;
+        (f64::from(used) >= f64::from(self.limit) * QUOTA_WARNING_RATIO).then(|| QuotaWarning {
+            quota: "rate".to_string(),
+            used: u64::from(used),
+            limit: u64::from(self.limit),
+            message: format!(
+                "{} of {} requests used, the window resets in {} seconds",
+                used, self.limit, self.reset
+            ),
+        })
+    }
+}
+
+struct Window {
+    started: Instant,
+    count: u32,
+}
+
+/// In-memory fixed-window rate limiter keyed by client IP
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    clients: Mutex<HashMap<IpAddr, Window>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a request from `client` and report whether it is allowed
+    pub fn check(&self, client: IpAddr) -> RateLimitStatus {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+
+        if clients.len() >= SWEEP_THRESHOLD {
+            clients.retain(|_, window| now.duration_since(window.started) < self.window);
+        }
+
+        let window = clients.entry(client).or_insert(Window {
+            started: now,
+            count: 0,
+        });
+        if now.duration_since(window.started) >= self.window {
+            window.started = now;
+            window.count = 0;
+        }
+
+        let allowed = window.count < self.limit;
+        if allowed {
+            window.count += 1;
+        }
+
+        let elapsed = now.duration_since(window.started);
+        RateLimitStatus {
+            limit: self.limit,
+            remaining: self.limit - window.count,
+            reset: self.window.saturating_sub(elapsed).as_secs_f64().ceil() as u64,
+            allowed,
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Current standing of `client` without counting a request
+    pub fn peek(&self, client: IpAddr) -> RateLimitStatus {
+        let now = Instant::now();
+        let clients = self.clients.lock().unwrap();
+        let (count, reset) = match clients.get(&client) {
+            Some(window) if now.duration_since(window.started) < self.window => (
+                window.count,
+                self.window - now.duration_since(window.started),
+            ),
+            _ => (0, self.window),
+        };
+        RateLimitStatus {
+            limit: self.limit,
+            remaining: self.limit - count,
+            reset: reset.as_secs_f64().ceil() as u64,
+            allowed: count < self.limit,
+        }
+    }
+}
+
+/// Middleware enforcing the rate limit and exposing X-RateLimit-* headers on
+/// every response so clients can throttle themselves before hitting 429s
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let status = limiter.check(addr.ip());
+
+    let mut response = if status.allowed {
+        // Lets handlers report the standing in their response metadata
+        request.extensions_mut().insert(status);
+        next.run(request).await
+    } else {
+        let mut response = HttpError::new(
+            "Too many requests, please slow down",
+            StatusCode::TOO_MANY_REQUESTS,
+        )
+        .with_code("RATE_LIMITED")
+        .into_response();
+        response
+            .headers_mut()
+            .insert("retry-after", HeaderValue::from(status.reset));
+        response
+    };
+
+    insert_headers(response.headers_mut(), &status);
+    response
+}
+
+fn insert_headers(headers: &mut HeaderMap, status: &RateLimitStatus) {
+    headers.insert("x-ratelimit-limit", HeaderValue::from(status.limit));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(status.remaining));
+    headers.insert("x-ratelimit-reset", HeaderValue::from(status.reset));
+    if let Some(warning) = status.warning() {
+        let value = format!("rate; used={}; limit={}", warning.used, warning.limit);
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(QUOTA_WARNING_HEADER, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+    #[test]
+    fn refuses_requests_over_the_limit() {
+        let limiter = RateLimiter::new(3, Duration::from_secs(60));
+        for remaining in [2, 1, 0] {
+            let status = limiter.check(CLIENT);
+            assert!(status.allowed);
+            assert_eq!(status.remaining, remaining);
+        }
+        let status = limiter.check(CLIENT);
+        assert!(!status.allowed);
+        assert_eq!(status.remaining, 0);
+        assert!(status.reset > 0 && status.reset <= 60);
+    }
+
+    #[test]
+    fn counts_clients_separately() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        assert!(limiter.check(CLIENT).allowed);
+        assert!(!limiter.check(CLIENT).allowed);
+        assert!(limiter.check(IpAddr::V4(Ipv4Addr::LOCALHOST)).allowed);
+    }
+
+    #[test]
+    fn refills_when_the_window_ends() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(50));
+        assert!(limiter.check(CLIENT).allowed);
+        assert!(!limiter.check(CLIENT).allowed);
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(limiter.peek(CLIENT).remaining, 1);
+        let status = limiter.check(CLIENT);
+        assert!(status.allowed);
+        assert_eq!(status.remaining, 0);
+    }
+
+    #[test]
+    fn peek_does_not_count() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        limiter.check(CLIENT);
+        for _ in 0..3 {
+            let status = limiter.peek(CLIENT);
+            assert_eq!(status.remaining, 1);
+            assert!(status.allowed);
+        }
+    }
+
+    #[test]
+    fn warns_near_the_limit() {
+        let limiter = RateLimiter::new(5, Duration::from_secs(60));
+        for _ in 0..3 {
+            assert!(limiter.check(CLIENT).warning().is_none());
+        }
+        let warning = limiter.check(CLIENT).warning().unwrap();
+        assert_eq!((warning.used, warning.limit), (4, 5));
+    }
+}
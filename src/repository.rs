@@ -1,87 +1,777 @@
+use crate::backup::BackupTodo;
+use crate::clock::{Clock, SystemClock};
+use crate::custom_fields::{CreateCustomField, CustomField, FieldType};
 use crate::db::DbPool;
 use crate::error::AppError;
-use crate::models::{CreateTodo, TodoResponse, UpdateTodo};
+use crate::integrations::{HookSubscription, Trigger};
+use crate::models::{
+    AggregateBucket, CreateRelation, CreateTodo, GroupBy, ImportedTodo, Metric, NearbyTodo,
+    RelatedTodo, Relation, RelationDirection, RelationKind, TagAction, TagStyle, TagUsage,
+    TodoLock, TodoQuery, TodoResponse, UpdateTodo, WorkloadDay,
+};
+use crate::rules::{Action, Condition, CreateRule, Rule, RuleRun, RuleTrigger};
+use crate::service::{MAX_GEOFENCE_RADIUS_M, MAX_METADATA_BYTES, MAX_TAGS};
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+use sqlx::types::Json;
+use sqlx::{Connection, Error as SqlxError, PgConnection, Postgres, QueryBuilder, Transaction};
+use std::sync::Arc;
 use uuid::Uuid;
 
+/// Column list matching TodoResponse, for queries built at runtime
+const TODO_COLUMNS: &str = "id, title, description, completed, created_at, updated_at, tags, \
+    completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, \
+    draft, start_date";
+
+/// Partial unique index preventing two open todos with the same title
+const OPEN_TITLE_INDEX: &str = "idx_todos_open_title";
+const PRIMARY_KEY: &str = "todos_pkey";
+const LOCATION_CHECK: &str = "todos_location_check";
+/// Caps the serialized metadata at MAX_METADATA_BYTES
+const METADATA_CHECK: &str = "todos_metadata_check";
+/// Keeps start_date on or before due_date
+const SCHEDULE_CHECK: &str = "todos_schedule_check";
+
 /// Trait defining todo repository operations
 #[async_trait]
 pub trait TodoRepository: Send + Sync {
-    async fn create(&self, payload: CreateTodo) -> Result<TodoResponse, AppError>;
-    async fn list(&self, completed: Option<bool>) -> Result<Vec<TodoResponse>, AppError>;
+    /// Insert a todo under `id`, which the service generated or the client
+    /// picked. With `dry_run` the insert is rolled back once it succeeded.
+    async fn create(
+        &self,
+        id: Uuid,
+        payload: CreateTodo,
+        dry_run: bool,
+    ) -> Result<TodoResponse, AppError>;
+    /// Like `create`, but when an open todo already has the title nothing is
+    /// inserted and that todo is returned; the flag tells whether it was created
+    async fn create_or_existing(
+        &self,
+        id: Uuid,
+        payload: CreateTodo,
+        dry_run: bool,
+    ) -> Result<(TodoResponse, bool), AppError>;
+    async fn list(
+        &self,
+        query: &TodoQuery,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<TodoResponse>, AppError>;
+    async fn count(&self, query: &TodoQuery) -> Result<i64, AppError>;
+    /// The planner's estimate of `count`, without scanning the rows
+    async fn estimate_count(&self, query: &TodoQuery) -> Result<i64, AppError>;
     async fn get(&self, id: Uuid) -> Result<TodoResponse, AppError>;
-    async fn update(&self, id: Uuid, payload: UpdateTodo) -> Result<TodoResponse, AppError>;
-    async fn delete(&self, id: Uuid) -> Result<(), AppError>;
-    async fn mark_completed(&self, id: Uuid) -> Result<TodoResponse, AppError>;
+    /// Writes to existing todos are made as the lock `holder`: a todo someone
+    /// else has an unexpired edit lock on is left alone, with Locked
+    async fn update(
+        &self,
+        id: Uuid,
+        payload: UpdateTodo,
+        holder: Option<&str>,
+        dry_run: bool,
+    ) -> Result<TodoResponse, AppError>;
+    async fn delete(&self, id: Uuid, holder: Option<&str>) -> Result<(), AppError>;
+    /// Complete `id`; the flag is false when it already was completed, in
+    /// which case it is returned unchanged
+    async fn mark_completed(
+        &self,
+        id: Uuid,
+        holder: Option<&str>,
+    ) -> Result<(TodoResponse, bool), AppError>;
+    async fn restore(&self, id: Uuid) -> Result<TodoResponse, AppError>;
+    /// Turn the draft `id` into a regular todo; NotFound unless it is a live
+    /// draft
+    async fn publish(&self, id: Uuid, holder: Option<&str>) -> Result<TodoResponse, AppError>;
+    /// Locked when either todo is locked by someone other than `holder`
+    async fn merge(
+        &self,
+        id: Uuid,
+        other_id: Uuid,
+        holder: Option<&str>,
+    ) -> Result<TodoResponse, AppError>;
+    /// Add or remove `tag` on every matching todo that changes, returning
+    /// those, how many matching todos were skipped because someone other
+    /// than `holder` has them locked, and the ids of those skipped because
+    /// they already have `MAX_TAGS` tags
+    async fn bulk_tag(
+        &self,
+        query: &TodoQuery,
+        action: TagAction,
+        tag: &str,
+        holder: Option<&str>,
+        dry_run: bool,
+    ) -> Result<(Vec<TodoResponse>, u64, Vec<Uuid>), AppError>;
+    /// Apply `payload` to every todo matching `query` in one statement,
+    /// returning the updated todos and how many were skipped because someone
+    /// other than `holder` has them locked
+    async fn bulk_update(
+        &self,
+        query: &TodoQuery,
+        payload: UpdateTodo,
+        holder: Option<&str>,
+        dry_run: bool,
+    ) -> Result<(Vec<TodoResponse>, u64), AppError>;
+    async fn tag_usage(&self, prefix: &str, limit: i64) -> Result<Vec<TagUsage>, AppError>;
+    /// Create or replace the style of `tag`
+    async fn set_tag_style(
+        &self,
+        tag: &str,
+        icon: Option<&str>,
+        color: Option<&str>,
+    ) -> Result<TagStyle, AppError>;
+    async fn aggregate(
+        &self,
+        query: &TodoQuery,
+        group_by: GroupBy,
+        metric: Metric,
+    ) -> Result<Vec<AggregateBucket>, AppError>;
+    async fn recently_completed(
+        &self,
+        tag: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<TodoResponse>, AppError>;
+    /// Open todos whose geofence reaches within `radius_m` of a point,
+    /// nearest first
+    async fn nearby(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        radius_m: i32,
+        limit: i64,
+    ) -> Result<Vec<NearbyTodo>, AppError>;
+    /// Estimates of the open todos due on each UTC day between `from` and
+    /// `to` (inclusive), for days with at least one todo, oldest first;
+    /// `over_capacity` is left false
+    async fn workload(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<WorkloadDay>, AppError>;
+    /// Published todos due on a UTC day in `[from, to]`, earliest due first
+    async fn due_between(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<TodoResponse>, AppError>;
+    /// Number of live todos completed on each UTC day, oldest day first
+    async fn completion_days(&self) -> Result<Vec<(NaiveDate, i64)>, AppError>;
+    /// Open, published todos whose start date is in `(after, until]`,
+    /// earliest start first
+    async fn started_between(
+        &self,
+        after: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<TodoResponse>, AppError>;
+    /// Published todos completed in `[from, to)`, earliest first
+    async fn completed_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<TodoResponse>, AppError>;
+    /// Published todos created before `from` that were still open at `to`,
+    /// oldest first
+    async fn carried_over(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<TodoResponse>, AppError>;
+    /// Number of live, published todos created in `[from, to)`
+    async fn count_created_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<i64, AppError>;
+    /// Open, published todos last updated before `before`, least recently
+    /// touched first
+    async fn stale(&self, before: DateTime<Utc>, limit: i64)
+        -> Result<Vec<TodoResponse>, AppError>;
+    /// When any todo last changed (was written or soft-deleted); None while
+    /// there are no todos
+    async fn last_modified(&self) -> Result<Option<DateTime<Utc>>, AppError>;
+    /// Insert imported todos under the paired ids in one transaction (rolled
+    /// back when `dry_run`); entries that fail individually don't abort the others
+    async fn import(
+        &self,
+        todos: Vec<(Uuid, ImportedTodo)>,
+        dry_run: bool,
+    ) -> Result<Vec<Result<TodoResponse, AppError>>, AppError>;
+    /// Every todo row, including soft-deleted ones
+    async fn dump(&self) -> Result<Vec<BackupTodo>, AppError>;
+    /// Insert dumped rows verbatim; fails with Conflict unless the table is empty
+    async fn load_dump(&self, todos: &[BackupTodo]) -> Result<u64, AppError>;
+    async fn create_hook(
+        &self,
+        id: Uuid,
+        event: Trigger,
+        target_url: &str,
+    ) -> Result<HookSubscription, AppError>;
+    async fn delete_hook(&self, id: Uuid) -> Result<(), AppError>;
+    async fn hooks(&self, event: Trigger) -> Result<Vec<HookSubscription>, AppError>;
+    async fn create_rule(&self, id: Uuid, rule: &CreateRule) -> Result<Rule, AppError>;
+    async fn list_rules(&self) -> Result<Vec<Rule>, AppError>;
+    async fn get_rule(&self, id: Uuid) -> Result<Rule, AppError>;
+    /// Overwrite every editable field of the rule with `rule.id`
+    async fn update_rule(&self, rule: &Rule) -> Result<Rule, AppError>;
+    async fn delete_rule(&self, id: Uuid) -> Result<(), AppError>;
+    /// Enabled rules for `trigger`, oldest first (the order they run in)
+    async fn enabled_rules(&self, trigger: RuleTrigger) -> Result<Vec<Rule>, AppError>;
+    async fn insert_rule_run(
+        &self,
+        id: Uuid,
+        rule_id: Uuid,
+        todo_id: Uuid,
+        error: Option<&str>,
+    ) -> Result<(), AppError>;
+    /// Latest runs of a rule, newest first
+    async fn rule_runs(&self, rule_id: Uuid, limit: i64) -> Result<Vec<RuleRun>, AppError>;
+    async fn create_custom_field(
+        &self,
+        id: Uuid,
+        field: &CreateCustomField,
+    ) -> Result<CustomField, AppError>;
+    /// Link two existing todos; Conflict when they already are
+    async fn create_relation(
+        &self,
+        id: Uuid,
+        todo_id: Uuid,
+        relation: &CreateRelation,
+    ) -> Result<Relation, AppError>;
+    /// Remove the links between two todos, in either direction, optionally
+    /// only those of one kind; returns how many were removed
+    async fn delete_relations(
+        &self,
+        todo_id: Uuid,
+        related_id: Uuid,
+        kind: Option<RelationKind>,
+    ) -> Result<u64, AppError>;
+    /// Live todos linked to a todo, oldest link first
+    async fn related(&self, todo_id: Uuid) -> Result<Vec<RelatedTodo>, AppError>;
+    /// Lock a todo for `holder` until `expires_at`, unless someone else holds
+    /// an unexpired lock (Locked); the holder itself extends its lock
+    async fn lock(
+        &self,
+        todo_id: Uuid,
+        holder: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<TodoLock, AppError>;
+    /// Release `holder`'s lock; Locked when someone else holds it
+    async fn unlock(&self, todo_id: Uuid, holder: &str) -> Result<(), AppError>;
+    /// The unexpired lock on a todo, if any
+    async fn active_lock(&self, todo_id: Uuid) -> Result<Option<TodoLock>, AppError>;
+    /// Permanently delete up to `limit` todos soft-deleted before `before`,
+    /// returning how many were removed. Todos other deleted todos were merged
+    /// into are kept until those are purged.
+    async fn purge_deleted_todos(&self, before: DateTime<Utc>, limit: i64)
+        -> Result<u64, AppError>;
+    /// Delete up to `limit` rule runs older than `before`
+    async fn purge_rule_runs(&self, before: DateTime<Utc>, limit: i64) -> Result<u64, AppError>;
+    /// Every custom field definition, by name
+    async fn custom_fields(&self) -> Result<Vec<CustomField>, AppError>;
+    /// Delete a definition and remove its value from every todo
+    async fn delete_custom_field(&self, id: Uuid) -> Result<(), AppError>;
+}
+
+/// A `rules` row; conditions and actions are stored as JSONB
+struct RuleRow {
+    id: Uuid,
+    name: String,
+    trigger: String,
+    conditions: Json<Vec<Condition>>,
+    actions: Json<Vec<Action>>,
+    enabled: bool,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<RuleRow> for Rule {
+    type Error = AppError;
+
+    fn try_from(row: RuleRow) -> Result<Self, Self::Error> {
+        let trigger = RuleTrigger::from_name(&row.trigger).ok_or_else(|| {
+            AppError::Internal(format!(
+                "Rule {} has unknown trigger '{}'",
+                row.id, row.trigger
+            ))
+        })?;
+        Ok(Rule {
+            id: row.id,
+            name: row.name,
+            trigger,
+            conditions: row.conditions.0,
+            actions: row.actions.0,
+            enabled: row.enabled,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+/// Commit `tx`, or roll it back when the caller only wanted to see what the
+/// writes would do
+async fn finish(tx: Transaction<'_, Postgres>, dry_run: bool) -> Result<(), SqlxError> {
+    if dry_run {
+        tx.rollback().await
+    } else {
+        tx.commit().await
+    }
+}
+
+/// Append the condition that nobody but `holder` has an unexpired edit lock
+/// on the row to the WHERE clause of an `UPDATE todos` statement
+fn push_unlocked(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    now: DateTime<Utc>,
+    holder: Option<&str>,
+) {
+    builder
+        .push(
+            " AND NOT EXISTS (SELECT 1 FROM todo_locks WHERE todo_id = todos.id AND expires_at > ",
+        )
+        .push_bind(now)
+        .push(" AND holder IS DISTINCT FROM ")
+        .push_bind(holder.map(str::to_string))
+        .push(")");
+}
+
+/// Number of todos matching `query` that someone other than `holder` has an
+/// unexpired edit lock on; with `tag`, only those the tag change would touch
+async fn count_locked(
+    conn: &mut PgConnection,
+    query: &TodoQuery,
+    now: DateTime<Utc>,
+    holder: Option<&str>,
+    tag: Option<(TagAction, &str)>,
+) -> Result<u64, SqlxError> {
+    let count = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM todos t
+        JOIN todo_locks l ON l.todo_id = t.id
+        WHERE t.deleted_at IS NULL
+          AND ($1::boolean IS NULL OR t.completed = $1)
+          AND ($2::text IS NULL OR $2 = ANY(t.tags))
+          AND ($3::jsonb IS NULL OR t.custom_fields @> $3)
+          AND t.draft = $4
+          AND (NOT $5 OR t.start_date IS NULL OR t.start_date <= $6)
+          AND ($8::text IS NULL OR ($8 = ANY(t.tags)) <> $9)
+          AND l.expires_at > $6 AND l.holder IS DISTINCT FROM $7
+        "#,
+        query.completed,
+        query.tag,
+        query.custom_fields,
+        query.draft,
+        query.active_only,
+        now,
+        holder,
+        tag.map(|(_, tag)| tag),
+        tag.map(|(action, _)| matches!(action, TagAction::Add))
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(count as u64)
+}
+
+fn start_after_due() -> AppError {
+    AppError::BadRequest("start_date must not be after due_date".to_string())
+}
+
+fn metadata_too_large() -> AppError {
+    AppError::BadRequest(format!(
+        "metadata must not be more than {} bytes once merged",
+        MAX_METADATA_BYTES
+    ))
+}
+
+/// Append the `, column = value` assignments of the fields set in `payload`
+/// to an `UPDATE todos SET ...` statement
+fn push_changes(builder: &mut QueryBuilder<'_, Postgres>, payload: UpdateTodo, now: DateTime<Utc>) {
+    if let Some(title) = payload.title {
+        builder.push(", title = ").push_bind(title);
+    }
+    if let Some(description) = payload.description {
+        builder.push(", description = ").push_bind(description);
+    }
+    if let Some(completed) = payload.completed {
+        builder.push(", completed = ").push_bind(completed);
+        // Keep the original completion time if it was already completed
+        if completed {
+            builder
+                .push(", completed_at = COALESCE(completed_at, ")
+                .push_bind(now)
+                .push(")");
+        } else {
+            builder.push(", completed_at = NULL");
+        }
+    }
+    if let Some(tags) = payload.tags {
+        builder.push(", tags = ").push_bind(tags);
+    }
+    if let Some(due_date) = payload.due_date {
+        builder.push(", due_date = ").push_bind(due_date);
+    }
+    if let Some(start_date) = payload.start_date {
+        builder.push(", start_date = ").push_bind(start_date);
+    }
+    if let Some(latitude) = payload.latitude {
+        builder.push(", latitude = ").push_bind(latitude);
+    }
+    if let Some(longitude) = payload.longitude {
+        builder.push(", longitude = ").push_bind(longitude);
+    }
+    if let Some(radius_m) = payload.radius_m {
+        builder.push(", radius_m = ").push_bind(radius_m);
+    }
+    if let Some(estimate_minutes) = payload.estimate_minutes {
+        builder
+            .push(", estimate_minutes = ")
+            .push_bind(estimate_minutes);
+    }
+    if let Some(custom_fields) = payload.custom_fields {
+        // Merged into the current values; nulls remove fields
+        builder
+            .push(", custom_fields = jsonb_strip_nulls(custom_fields || ")
+            .push_bind(Value::Object(custom_fields))
+            .push(")");
+    }
+    if let Some(metadata) = payload.metadata {
+        builder
+            .push(", metadata = jsonb_merge_patch(metadata, ")
+            .push_bind(Value::Object(metadata))
+            .push(")");
+    }
 }
 
 /// PostgreSQL implementation of TodoRepository
 pub struct PostgresTodoRepository {
     pool: DbPool,
+    /// Timestamps are written from this clock rather than the database's NOW()
+    clock: Arc<dyn Clock>,
+    /// Whether the unique index on open titles exists (`UNIQUE_OPEN_TITLES`)
+    unique_open_titles: bool,
 }
 
 impl PostgresTodoRepository {
     pub fn new(pool: DbPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            clock: Arc::new(SystemClock),
+            unique_open_titles: false,
+        }
+    }
+
+    /// Replace the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Declare that the unique index on open titles was built (see
+    /// `db::sync_open_title_index`)
+    pub fn with_unique_open_titles(mut self, unique: bool) -> Self {
+        self.unique_open_titles = unique;
+        self
+    }
+
+    /// The oldest open todo titled `title` (case-insensitive)
+    async fn find_open_by_title(&self, title: &str) -> Result<Option<TodoResponse>, AppError> {
+        let todo = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            FROM todos
+            WHERE lower(title) = lower($1) AND deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft
+            ORDER BY created_at, id
+            LIMIT 1
+            "#,
+            title
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(todo)
+    }
+
+    /// Why a write to `id` matched no row: Locked when someone other than
+    /// `holder` has an unexpired edit lock on it, otherwise `missing`
+    async fn locked_or(&self, id: Uuid, holder: Option<&str>, missing: AppError) -> AppError {
+        match self.active_lock(id).await {
+            Ok(Some(lock)) if lock.blocks(holder, self.clock.now()) => AppError::Locked {
+                holder: lock.holder,
+                expires_at: lock.expires_at,
+            },
+            Ok(_) => missing,
+            Err(e) => e,
+        }
+    }
+
+    /// Whether `error` is a violation of the open-title index
+    fn is_duplicate(error: &SqlxError) -> bool {
+        matches!(
+            error,
+            SqlxError::Database(e) if e.constraint() == Some(OPEN_TITLE_INDEX)
+        )
+    }
+
+    /// Map a violation of the open-title index to DuplicateTodo, naming the
+    /// open todo that already uses `title`
+    async fn duplicate_error(&self, error: SqlxError, id: Option<Uuid>, title: &str) -> AppError {
+        if !Self::is_duplicate(&error) {
+            return error.into();
+        }
+
+        let existing = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM todos
+            WHERE lower(title) = lower($1) AND deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft
+            "#,
+            title
+        )
+        .fetch_optional(&self.pool)
+        .await;
+
+        match existing {
+            Ok(Some(existing_id)) => AppError::DuplicateTodo { id, existing_id },
+            Ok(None) => error.into(),
+            Err(e) => e.into(),
+        }
     }
 }
 
 #[async_trait]
 impl TodoRepository for PostgresTodoRepository {
-    async fn create(&self, payload: CreateTodo) -> Result<TodoResponse, AppError> {
+    async fn create(
+        &self,
+        id: Uuid,
+        payload: CreateTodo,
+        dry_run: bool,
+    ) -> Result<TodoResponse, AppError> {
+        let mut tx = self.pool.begin().await?;
         let todo = sqlx::query_as!(
             TodoResponse,
             r#"
-            INSERT INTO todos (title, description)
-            VALUES ($1, $2)
-            RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!"
+            INSERT INTO todos (id, title, description, tags, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, created_at, updated_at, draft, start_date)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, jsonb_merge_patch('{}', $11), $12, $12, $13, $14)
+            RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
             "#,
+            id,
             payload.title,
-            payload.description
+            payload.description,
+            &payload.tags,
+            payload.due_date,
+            payload.latitude,
+            payload.longitude,
+            payload.radius_m,
+            payload.estimate_minutes,
+            Value::Object(payload.custom_fields.clone()),
+            Value::Object(payload.metadata.clone()),
+            self.clock.now(),
+            payload.draft,
+            payload.start_date
         )
-        .fetch_one(&self.pool)
-        .await?;
+        .fetch_one(&mut *tx)
+        .await;
 
-        Ok(todo)
+        match todo {
+            Ok(todo) => {
+                finish(tx, dry_run).await?;
+                Ok(todo)
+            }
+            Err(SqlxError::Database(e)) if e.constraint() == Some(SCHEDULE_CHECK) => {
+                Err(start_after_due())
+            }
+            Err(SqlxError::Database(e)) if e.constraint() == Some(METADATA_CHECK) => {
+                Err(metadata_too_large())
+            }
+            Err(SqlxError::Database(e)) if e.constraint() == Some(PRIMARY_KEY) => Err(
+                AppError::Conflict(format!("Todo with id {} already exists", id)),
+            ),
+            Err(e) => Err(self.duplicate_error(e, None, &payload.title).await),
+        }
     }
 
-    async fn list(&self, completed: Option<bool>) -> Result<Vec<TodoResponse>, AppError> {
-        let todos = if let Some(completed) = completed {
-            sqlx::query_as!(
-                TodoResponse,
-                r#"
-                SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!"
-                FROM todos
-                WHERE completed = $1
-                ORDER BY created_at DESC
-                "#,
-                completed
-            )
-            .fetch_all(&self.pool)
-            .await?
-        } else {
-            sqlx::query_as!(
-                TodoResponse,
-                r#"
-                SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!"
-                FROM todos
-                ORDER BY created_at DESC
-                "#
-            )
-            .fetch_all(&self.pool)
-            .await?
+    async fn create_or_existing(
+        &self,
+        id: Uuid,
+        payload: CreateTodo,
+        dry_run: bool,
+    ) -> Result<(TodoResponse, bool), AppError> {
+        if !self.unique_open_titles {
+            // Without the index there is nothing to conflict with: reuse an
+            // open todo with the title if there is one
+            if let Some(existing) = self.find_open_by_title(&payload.title).await? {
+                return Ok((existing, false));
+            }
+            return Ok((self.create(id, payload, dry_run).await?, true));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        // The conflict target names the open-title index, so other violations
+        // (metadata size, a taken id) still fail. Not checked at build time:
+        // the index only exists when UNIQUE_OPEN_TITLES is on.
+        let inserted = sqlx::query_as::<_, TodoResponse>(
+            r#"
+            INSERT INTO todos (id, title, description, tags, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, created_at, updated_at, draft, start_date)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, jsonb_merge_patch('{}', $11), $12, $12, $13, $14)
+            ON CONFLICT (lower(title)) WHERE deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft DO NOTHING
+            RETURNING id, title, description, completed, created_at, updated_at, tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            "#,
+        )
+        .bind(id)
+        .bind(&payload.title)
+        .bind(&payload.description)
+        .bind(&payload.tags)
+        .bind(payload.due_date)
+        .bind(payload.latitude)
+        .bind(payload.longitude)
+        .bind(payload.radius_m)
+        .bind(payload.estimate_minutes)
+        .bind(Value::Object(payload.custom_fields.clone()))
+        .bind(Value::Object(payload.metadata.clone()))
+        .bind(self.clock.now())
+        .bind(payload.draft)
+        .bind(payload.start_date)
+        .fetch_optional(&mut *tx)
+        .await;
+
+        let inserted = match inserted {
+            Ok(inserted) => inserted,
+            Err(SqlxError::Database(e)) if e.constraint() == Some(SCHEDULE_CHECK) => {
+                return Err(start_after_due())
+            }
+            Err(SqlxError::Database(e)) if e.constraint() == Some(METADATA_CHECK) => {
+                return Err(metadata_too_large())
+            }
+            Err(SqlxError::Database(e)) if e.constraint() == Some(PRIMARY_KEY) => {
+                return Err(AppError::Conflict(format!(
+                    "Todo with id {} already exists",
+                    id
+                )))
+            }
+            Err(e) => return Err(e.into()),
         };
+        if let Some(todo) = inserted {
+            finish(tx, dry_run).await?;
+            return Ok((todo, true));
+        }
+
+        let existing = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            FROM todos
+            WHERE lower(title) = lower($1) AND deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft
+            "#,
+            payload.title
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        tx.rollback().await?;
+
+        // The conflicting todo was completed or deleted in the meantime
+        existing.map(|todo| (todo, false)).ok_or_else(|| {
+            AppError::Conflict(format!(
+                "The open todo titled \"{}\" changed concurrently, retry",
+                payload.title
+            ))
+        })
+    }
+
+    async fn list(
+        &self,
+        query: &TodoQuery,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<TodoResponse>, AppError> {
+        let todos = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            FROM todos
+            WHERE deleted_at IS NULL
+              AND ($1::boolean IS NULL OR completed = $1)
+              AND ($2::text IS NULL OR tags @> ARRAY[$2])
+              AND ($3::jsonb IS NULL OR custom_fields @> $3)
+              AND draft = $6
+              AND (NOT $7 OR start_date IS NULL OR start_date <= $8)
+            ORDER BY created_at DESC
+            LIMIT $4 OFFSET $5
+            "#,
+            query.completed,
+            query.tag,
+            query.custom_fields,
+            limit,
+            offset,
+            query.draft,
+            query.active_only,
+            self.clock.now()
+        )
+        .fetch_all(&self.pool)
+        .await?;
 
         Ok(todos)
     }
 
+    async fn count(&self, query: &TodoQuery) -> Result<i64, AppError> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!"
+            FROM todos
+            WHERE deleted_at IS NULL
+              AND ($1::boolean IS NULL OR completed = $1)
+              AND ($2::text IS NULL OR tags @> ARRAY[$2])
+              AND ($3::jsonb IS NULL OR custom_fields @> $3)
+              AND draft = $4
+              AND (NOT $5 OR start_date IS NULL OR start_date <= $6)
+            "#,
+            query.completed,
+            query.tag,
+            query.custom_fields,
+            query.draft,
+            query.active_only,
+            self.clock.now()
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn estimate_count(&self, query: &TodoQuery) -> Result<i64, AppError> {
+        // Same filter as `count`; EXPLAIN plans it without running it
+        let plan: Json<Value> = sqlx::query_scalar(
+            r#"
+            EXPLAIN (FORMAT JSON)
+            SELECT 1
+            FROM todos
+            WHERE deleted_at IS NULL
+              AND ($1::boolean IS NULL OR completed = $1)
+              AND ($2::text IS NULL OR tags @> ARRAY[$2])
+              AND ($3::jsonb IS NULL OR custom_fields @> $3)
+              AND draft = $4
+              AND (NOT $5 OR start_date IS NULL OR start_date <= $6)
+            "#,
+        )
+        .bind(query.completed)
+        .bind(&query.tag)
+        .bind(&query.custom_fields)
+        .bind(query.draft)
+        .bind(query.active_only)
+        .bind(self.clock.now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        plan.0[0]["Plan"]["Plan Rows"]
+            .as_f64()
+            .map(|rows| rows.round() as i64)
+            .ok_or_else(|| AppError::Internal("EXPLAIN returned no row estimate".to_string()))
+    }
+
     async fn get(&self, id: Uuid) -> Result<TodoResponse, AppError> {
         let todo = sqlx::query_as!(
             TodoResponse,
             r#"
-            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
             FROM todos
-            WHERE id = $1
+            WHERE id = $1 AND deleted_at IS NULL
             "#,
             id
         )
@@ -92,144 +782,1537 @@ impl TodoRepository for PostgresTodoRepository {
         Ok(todo)
     }
 
-    async fn update(&self, id: Uuid, payload: UpdateTodo) -> Result<TodoResponse, AppError> {
+    async fn update(
+        &self,
+        id: Uuid,
+        payload: UpdateTodo,
+        holder: Option<&str>,
+        dry_run: bool,
+    ) -> Result<TodoResponse, AppError> {
         // Check if todo exists first
-        let _existing = sqlx::query!(r#"SELECT id FROM todos WHERE id = $1"#, id)
-            .fetch_optional(&self.pool)
-            .await?
-            .ok_or_else(|| AppError::NotFound(format!("Todo with id {} not found", id)))?;
-
-        // Build update query dynamically based on provided fields
-        let mut query_str = "UPDATE todos SET updated_at = NOW()".to_string();
-        let mut param_count = 1;
-        let mut title_param: Option<String> = None;
-        let mut description_param: Option<String> = None;
-        let mut completed_param: Option<bool> = None;
-
-        if payload.title.is_some() {
-            query_str.push_str(&format!(", title = ${}", param_count));
-            param_count += 1;
-            title_param = payload.title;
-        }
+        let existing = self.get(id).await?;
 
-        if payload.description.is_some() {
-            query_str.push_str(&format!(", description = ${}", param_count));
-            param_count += 1;
-            description_param = payload.description;
+        if payload.is_empty() {
+            // No updates, just return existing
+            return Ok(existing);
         }
 
-        if payload.completed.is_some() {
-            query_str.push_str(&format!(", completed = ${}", param_count));
-            param_count += 1;
-            completed_param = payload.completed;
-        }
+        // Build update query dynamically based on provided fields
+        let title = payload.title.clone().unwrap_or(existing.title);
+        let now = self.clock.now();
+        let mut builder = QueryBuilder::<Postgres>::new("UPDATE todos SET updated_at = ");
+        builder.push_bind(now);
+        push_changes(&mut builder, payload, now);
+        builder
+            .push(" WHERE id = ")
+            .push_bind(id)
+            .push(" AND deleted_at IS NULL");
+        push_unlocked(&mut builder, now, holder);
+        builder.push(" RETURNING ").push(TODO_COLUMNS);
 
-        query_str.push_str(&format!(
-            " WHERE id = ${} RETURNING id, title, description, completed, created_at, updated_at",
-            param_count
-        ));
+        let mut tx = self.pool.begin().await?;
+        let todo = builder
+            .build_query_as::<TodoResponse>()
+            .fetch_one(&mut *tx)
+            .await;
 
-        let todo = match (title_param, description_param, completed_param) {
-            (Some(title), Some(description), Some(completed)) => {
-                sqlx::query_as::<_, TodoResponse>(&query_str)
-                    .bind(title)
-                    .bind(description)
-                    .bind(completed)
-                    .bind(id)
-                    .fetch_one(&self.pool)
-                    .await?
-            }
-            (Some(title), Some(description), None) => {
-                sqlx::query_as::<_, TodoResponse>(&query_str)
-                    .bind(title)
-                    .bind(description)
-                    .bind(id)
-                    .fetch_one(&self.pool)
-                    .await?
-            }
-            (Some(title), None, Some(completed)) => {
-                sqlx::query_as::<_, TodoResponse>(&query_str)
-                    .bind(title)
-                    .bind(completed)
-                    .bind(id)
-                    .fetch_one(&self.pool)
-                    .await?
-            }
-            (Some(title), None, None) => {
-                sqlx::query_as::<_, TodoResponse>(&query_str)
-                    .bind(title)
-                    .bind(id)
-                    .fetch_one(&self.pool)
-                    .await?
-            }
-            (None, Some(description), Some(completed)) => {
-                sqlx::query_as::<_, TodoResponse>(&query_str)
-                    .bind(description)
-                    .bind(completed)
-                    .bind(id)
-                    .fetch_one(&self.pool)
-                    .await?
-            }
-            (None, Some(description), None) => {
-                sqlx::query_as::<_, TodoResponse>(&query_str)
-                    .bind(description)
-                    .bind(id)
-                    .fetch_one(&self.pool)
-                    .await?
-            }
-            (None, None, Some(completed)) => {
-                sqlx::query_as::<_, TodoResponse>(&query_str)
-                    .bind(completed)
-                    .bind(id)
-                    .fetch_one(&self.pool)
-                    .await?
-            }
-            (None, None, None) => {
-                // No updates, just return existing
-                sqlx::query_as!(
-                    TodoResponse,
-                    r#"
-                    SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!"
-                    FROM todos
-                    WHERE id = $1
-                    "#,
-                    id
+        match todo {
+            Ok(todo) => {
+                finish(tx, dry_run).await?;
+                Ok(todo)
+            }
+            Err(SqlxError::RowNotFound) => Err(self
+                .locked_or(
+                    id,
+                    holder,
+                    AppError::NotFound(format!("Todo with id {} not found", id)),
                 )
-                .fetch_one(&self.pool)
-                .await?
+                .await),
+            Err(SqlxError::Database(e)) if e.constraint() == Some(LOCATION_CHECK) => Err(
+                AppError::BadRequest("radius_m needs the todo to have a location".to_string()),
+            ),
+            Err(SqlxError::Database(e)) if e.constraint() == Some(SCHEDULE_CHECK) => {
+                Err(start_after_due())
             }
-        };
-
-        Ok(todo)
+            Err(SqlxError::Database(e)) if e.constraint() == Some(METADATA_CHECK) => {
+                Err(metadata_too_large())
+            }
+            Err(e) => Err(self.duplicate_error(e, Some(id), &title).await),
+        }
     }
 
-    async fn delete(&self, id: Uuid) -> Result<(), AppError> {
-        let result = sqlx::query!(r#"DELETE FROM todos WHERE id = $1"#, id)
-            .execute(&self.pool)
-            .await?;
+    async fn delete(&self, id: Uuid, holder: Option<&str>) -> Result<(), AppError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE todos SET deleted_at = $2
+            WHERE id = $1 AND deleted_at IS NULL
+              AND NOT EXISTS (
+                  SELECT 1 FROM todo_locks
+                  WHERE todo_id = todos.id AND expires_at > $2 AND holder IS DISTINCT FROM $3
+              )
+            "#,
+            id,
+            self.clock.now(),
+            holder
+        )
+        .execute(&self.pool)
+        .await?;
 
         if result.rows_affected() == 0 {
-            return Err(AppError::NotFound(format!("Todo with id {} not found", id)));
+            let missing = AppError::NotFound(format!("Todo with id {} not found", id));
+            return Err(self.locked_or(id, holder, missing).await);
         }
 
         Ok(())
     }
 
-    async fn mark_completed(&self, id: Uuid) -> Result<TodoResponse, AppError> {
+    async fn mark_completed(
+        &self,
+        id: Uuid,
+        holder: Option<&str>,
+    ) -> Result<(TodoResponse, bool), AppError> {
+        // Only an open todo is written, so concurrent completes can't both
+        // report the transition
         let todo = sqlx::query_as!(
             TodoResponse,
             r#"
             UPDATE todos
-            SET completed = true, updated_at = NOW()
-            WHERE id = $1
-            RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!"
+            SET completed = true, completed_at = COALESCE(completed_at, $2), updated_at = $2
+            WHERE id = $1 AND deleted_at IS NULL AND completed IS NOT TRUE
+              AND NOT EXISTS (
+                  SELECT 1 FROM todo_locks
+                  WHERE todo_id = todos.id AND expires_at > $2 AND holder IS DISTINCT FROM $3
+              )
+            RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
             "#,
+            id,
+            self.clock.now(),
+            holder
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(todo) = todo {
+            return Ok((todo, true));
+        }
+
+        // Already completed, locked, missing or reopened in the meantime
+        let todo = self.get(id).await?;
+        if todo.completed {
+            return Ok((todo, false));
+        }
+        let reopened = AppError::Conflict(format!("Todo {} changed concurrently, retry", id));
+        Err(self.locked_or(id, holder, reopened).await)
+    }
+
+    async fn restore(&self, id: Uuid) -> Result<TodoResponse, AppError> {
+        let deleted = sqlx::query!(
+            r#"SELECT title FROM todos WHERE id = $1 AND deleted_at IS NOT NULL"#,
             id
         )
         .fetch_optional(&self.pool)
         .await?
-        .ok_or_else(|| AppError::NotFound(format!("Todo with id {} not found", id)))?;
+        .ok_or_else(|| AppError::NotFound(format!("Deleted todo with id {} not found", id)))?;
+
+        let todo = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            UPDATE todos
+            SET deleted_at = NULL, merged_into = NULL, updated_at = $2
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            "#,
+            id,
+            self.clock.now()
+        )
+        .fetch_optional(&self.pool)
+        .await;
+
+        match todo {
+            Ok(Some(todo)) => Ok(todo),
+            Ok(None) => Err(AppError::NotFound(format!(
+                "Deleted todo with id {} not found",
+                id
+            ))),
+            Err(e) => Err(self.duplicate_error(e, Some(id), &deleted.title).await),
+        }
+    }
+
+    async fn publish(&self, id: Uuid, holder: Option<&str>) -> Result<TodoResponse, AppError> {
+        let todo = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            UPDATE todos
+            SET draft = FALSE, updated_at = $2
+            WHERE id = $1 AND deleted_at IS NULL AND draft
+              AND NOT EXISTS (
+                  SELECT 1 FROM todo_locks
+                  WHERE todo_id = todos.id AND expires_at > $2 AND holder IS DISTINCT FROM $3
+              )
+            RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            "#,
+            id,
+            self.clock.now(),
+            holder
+        )
+        .fetch_optional(&self.pool)
+        .await;
+
+        match todo {
+            Ok(Some(todo)) => Ok(todo),
+            Ok(None) => {
+                let missing = AppError::NotFound(format!("Draft todo with id {} not found", id));
+                Err(self.locked_or(id, holder, missing).await)
+            }
+            // Drafts don't count for the open-title index until published
+            Err(e) if Self::is_duplicate(&e) => {
+                let title = self.get(id).await?.title;
+                Err(self.duplicate_error(e, Some(id), &title).await)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn merge(
+        &self,
+        id: Uuid,
+        other_id: Uuid,
+        holder: Option<&str>,
+    ) -> Result<TodoResponse, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Lock both rows so concurrent edits can't slip in between read and write
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, description, created_at as "created_at!", tags, due_date
+            FROM todos
+            WHERE id = ANY($1) AND deleted_at IS NULL
+            FOR UPDATE
+            "#,
+            &[id, other_id][..]
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let survivor = rows.iter().find(|row| row.id == id);
+        let merged = rows.iter().find(|row| row.id == other_id);
+        let (survivor, merged) = match (survivor, merged) {
+            (Some(survivor), Some(merged)) => (survivor, merged),
+            (None, _) => return Err(AppError::NotFound(format!("Todo with id {} not found", id))),
+            (_, None) => {
+                return Err(AppError::NotFound(format!(
+                    "Todo with id {} not found",
+                    other_id
+                )))
+            }
+        };
+
+        let now = self.clock.now();
+        let lock = sqlx::query!(
+            r#"
+            SELECT holder, expires_at FROM todo_locks
+            WHERE todo_id = ANY($1) AND expires_at > $2 AND holder IS DISTINCT FROM $3
+            LIMIT 1
+            "#,
+            &[id, other_id][..],
+            now,
+            holder
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(lock) = lock {
+            return Err(AppError::Locked {
+                holder: lock.holder,
+                expires_at: lock.expires_at,
+            });
+        }
+
+        let description = match (&survivor.description, &merged.description) {
+            (Some(a), Some(b)) => Some(format!("{}\n\n{}", a, b)),
+            (a, b) => a.clone().or_else(|| b.clone()),
+        };
+        let created_at = survivor.created_at.min(merged.created_at);
+        let due_date = survivor.due_date.or(merged.due_date);
+        let mut tags = survivor.tags.clone();
+        for tag in &merged.tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+
+        sqlx::query!(
+            r#"UPDATE todos SET deleted_at = $3, merged_into = $2 WHERE id = $1"#,
+            other_id,
+            id,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let todo = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            UPDATE todos
+            SET description = $2, created_at = $3, tags = $4, due_date = $5, updated_at = $6
+            WHERE id = $1
+            RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            "#,
+            id,
+            description,
+            created_at,
+            &tags,
+            due_date,
+            now
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
 
         Ok(todo)
     }
+
+    async fn bulk_tag(
+        &self,
+        query: &TodoQuery,
+        action: TagAction,
+        tag: &str,
+        holder: Option<&str>,
+        dry_run: bool,
+    ) -> Result<(Vec<TodoResponse>, u64, Vec<Uuid>), AppError> {
+        // Only rows that actually change are touched (and reported)
+        let now = self.clock.now();
+        let max_tags = MAX_TAGS as i32;
+        let mut tx = self.pool.begin().await?;
+        let locked = count_locked(&mut tx, query, now, holder, Some((action, tag))).await?;
+        let mut full = Vec::new();
+        let todos = match action {
+            TagAction::Add => {
+                full = sqlx::query_scalar!(
+                    r#"
+                    SELECT id FROM todos
+                    WHERE deleted_at IS NULL
+                      AND ($1::boolean IS NULL OR completed = $1)
+                      AND ($2::text IS NULL OR $2 = ANY(tags))
+                      AND ($4::jsonb IS NULL OR custom_fields @> $4)
+                      AND draft = $6
+                      AND (NOT $7 OR start_date IS NULL OR start_date <= $5)
+                      AND NOT ($3 = ANY(tags))
+                      AND cardinality(tags) >= $9
+                      AND NOT EXISTS (
+                          SELECT 1 FROM todo_locks
+                          WHERE todo_id = todos.id AND expires_at > $5 AND holder IS DISTINCT FROM $8
+                      )
+                    ORDER BY created_at, id
+                    "#,
+                    query.completed,
+                    query.tag,
+                    tag,
+                    query.custom_fields,
+                    now,
+                    query.draft,
+                    query.active_only,
+                    holder,
+                    max_tags
+                )
+                .fetch_all(&mut *tx)
+                .await?;
+
+                sqlx::query_as!(
+                    TodoResponse,
+                    r#"
+                    UPDATE todos
+                    SET tags = array_append(tags, $3), updated_at = $5
+                    WHERE deleted_at IS NULL
+                      AND ($1::boolean IS NULL OR completed = $1)
+                      AND ($2::text IS NULL OR $2 = ANY(tags))
+                      AND ($4::jsonb IS NULL OR custom_fields @> $4)
+                      AND draft = $6
+                      AND (NOT $7 OR start_date IS NULL OR start_date <= $5)
+                      AND NOT ($3 = ANY(tags))
+                      AND cardinality(tags) < $9
+                      AND NOT EXISTS (
+                          SELECT 1 FROM todo_locks
+                          WHERE todo_id = todos.id AND expires_at > $5 AND holder IS DISTINCT FROM $8
+                      )
+                    RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+                    "#,
+                    query.completed,
+                    query.tag,
+                    tag,
+                    query.custom_fields,
+                    now,
+                    query.draft,
+                    query.active_only,
+                    holder,
+                    max_tags
+                )
+                .fetch_all(&mut *tx)
+                .await?
+            }
+            TagAction::Remove => {
+                sqlx::query_as!(
+                    TodoResponse,
+                    r#"
+                    UPDATE todos
+                    SET tags = array_remove(tags, $3), updated_at = $5
+                    WHERE deleted_at IS NULL
+                      AND ($1::boolean IS NULL OR completed = $1)
+                      AND ($2::text IS NULL OR $2 = ANY(tags))
+                      AND ($4::jsonb IS NULL OR custom_fields @> $4)
+                      AND draft = $6
+                      AND (NOT $7 OR start_date IS NULL OR start_date <= $5)
+                      AND $3 = ANY(tags)
+                      AND NOT EXISTS (
+                          SELECT 1 FROM todo_locks
+                          WHERE todo_id = todos.id AND expires_at > $5 AND holder IS DISTINCT FROM $8
+                      )
+                    RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+                    "#,
+                    query.completed,
+                    query.tag,
+                    tag,
+                    query.custom_fields,
+                    now,
+                    query.draft,
+                    query.active_only,
+                    holder
+                )
+                .fetch_all(&mut *tx)
+                .await?
+            }
+        };
+        finish(tx, dry_run).await?;
+
+        Ok((todos, locked, full))
+    }
+
+    async fn bulk_update(
+        &self,
+        query: &TodoQuery,
+        payload: UpdateTodo,
+        holder: Option<&str>,
+        dry_run: bool,
+    ) -> Result<(Vec<TodoResponse>, u64), AppError> {
+        let now = self.clock.now();
+        let mut builder = QueryBuilder::<Postgres>::new("UPDATE todos SET updated_at = ");
+        builder.push_bind(now);
+        push_changes(&mut builder, payload, now);
+        builder
+            .push(" WHERE deleted_at IS NULL AND (")
+            .push_bind(query.completed)
+            .push("::boolean IS NULL OR completed = ")
+            .push_bind(query.completed)
+            .push(") AND (")
+            .push_bind(query.tag.clone())
+            .push("::text IS NULL OR tags @> ARRAY[")
+            .push_bind(query.tag.clone())
+            .push("]) AND (")
+            .push_bind(query.custom_fields.clone())
+            .push("::jsonb IS NULL OR custom_fields @> ")
+            .push_bind(query.custom_fields.clone())
+            .push(") AND draft = ")
+            .push_bind(query.draft)
+            .push(" AND (NOT ")
+            .push_bind(query.active_only)
+            .push(" OR start_date IS NULL OR start_date <= ")
+            .push_bind(now)
+            .push(")");
+        push_unlocked(&mut builder, now, holder);
+        builder.push(" RETURNING ").push(TODO_COLUMNS);
+
+        let mut tx = self.pool.begin().await?;
+        let locked = count_locked(&mut tx, query, now, holder, None).await?;
+        let todos = builder
+            .build_query_as::<TodoResponse>()
+            .fetch_all(&mut *tx)
+            .await;
+
+        match todos {
+            Ok(todos) => {
+                finish(tx, dry_run).await?;
+                Ok((todos, locked))
+            }
+            Err(SqlxError::Database(e)) if e.constraint() == Some(LOCATION_CHECK) => {
+                Err(AppError::BadRequest(
+                    "radius_m needs every matching todo to have a location".to_string(),
+                ))
+            }
+            Err(SqlxError::Database(e)) if e.constraint() == Some(SCHEDULE_CHECK) => {
+                Err(start_after_due())
+            }
+            Err(SqlxError::Database(e)) if e.constraint() == Some(METADATA_CHECK) => {
+                Err(metadata_too_large())
+            }
+            Err(e) if Self::is_duplicate(&e) => Err(AppError::Conflict(
+                "Reopening these todos would duplicate the title of an open todo".to_string(),
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn tag_usage(&self, prefix: &str, limit: i64) -> Result<Vec<TagUsage>, AppError> {
+        let tags = sqlx::query_as!(
+            TagUsage,
+            r#"
+            SELECT usage.tag as "tag!", usage.count as "count!", styles.icon as "icon?", styles.color as "color?"
+            FROM (
+                SELECT tag, COUNT(*) AS count
+                FROM todos, unnest(tags) AS tag
+                WHERE deleted_at IS NULL AND tags <> '{}' AND starts_with(tag, $1)
+                GROUP BY tag
+                ORDER BY COUNT(*) DESC, tag
+                LIMIT $2
+            ) usage
+            LEFT JOIN tag_styles styles ON styles.tag = usage.tag
+            ORDER BY usage.count DESC, usage.tag
+            "#,
+            prefix,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tags)
+    }
+
+    async fn set_tag_style(
+        &self,
+        tag: &str,
+        icon: Option<&str>,
+        color: Option<&str>,
+    ) -> Result<TagStyle, AppError> {
+        let style = sqlx::query_as!(
+            TagStyle,
+            r#"
+            INSERT INTO tag_styles (tag, icon, color, updated_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (tag) DO UPDATE
+            SET icon = EXCLUDED.icon, color = EXCLUDED.color, updated_at = EXCLUDED.updated_at
+            RETURNING tag, icon, color, updated_at
+            "#,
+            tag,
+            icon,
+            color,
+            self.clock.now()
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(style)
+    }
+
+    async fn aggregate(
+        &self,
+        query: &TodoQuery,
+        group_by: GroupBy,
+        metric: Metric,
+    ) -> Result<Vec<AggregateBucket>, AppError> {
+        // Only these fixed fragments are spliced in; filters are bound
+        let (key, from) = match group_by {
+            GroupBy::Tag => ("tag", "todos, unnest(tags) AS tag"),
+            GroupBy::Status => (
+                "CASE WHEN completed THEN 'completed' ELSE 'open' END",
+                "todos",
+            ),
+            GroupBy::Day => (
+                "to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD')",
+                "todos",
+            ),
+        };
+        let value = match metric {
+            Metric::Count => "COUNT(*)::float8",
+            Metric::AvgCompletionTime => {
+                "AVG(EXTRACT(EPOCH FROM completed_at - created_at))::float8"
+            }
+        };
+
+        let mut builder = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {} AS key, {} AS value FROM {} WHERE deleted_at IS NULL",
+            key, value, from
+        ));
+        builder
+            .push(" AND (")
+            .push_bind(query.completed)
+            .push("::boolean IS NULL OR completed = ")
+            .push_bind(query.completed)
+            .push(") AND (")
+            .push_bind(query.tag.as_deref())
+            .push("::text IS NULL OR ")
+            .push_bind(query.tag.as_deref())
+            .push(" = ANY(tags)) AND (")
+            .push_bind(query.custom_fields.clone())
+            .push("::jsonb IS NULL OR custom_fields @> ")
+            .push_bind(query.custom_fields.clone())
+            .push(") AND draft = ")
+            .push_bind(query.draft)
+            .push(" AND (NOT ")
+            .push_bind(query.active_only)
+            .push(" OR start_date IS NULL OR start_date <= ")
+            .push_bind(self.clock.now())
+            .push(") GROUP BY 1 ORDER BY 1");
+
+        let buckets = builder
+            .build_query_as::<AggregateBucket>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(buckets)
+    }
+
+    async fn recently_completed(
+        &self,
+        tag: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<TodoResponse>, AppError> {
+        let todos = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            FROM todos
+            WHERE completed AND deleted_at IS NULL AND NOT draft
+              AND ($1::text IS NULL OR $1 = ANY(tags))
+            ORDER BY completed_at DESC NULLS LAST
+            LIMIT $2
+            "#,
+            tag,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    async fn nearby(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        radius_m: i32,
+        limit: i64,
+    ) -> Result<Vec<NearbyTodo>, AppError> {
+        // earth_box is a cheap indexed prefilter; the distance check is exact
+        let sql = format!(
+            r#"
+            SELECT * FROM (
+                SELECT {}, earth_distance(ll_to_earth(latitude, longitude), ll_to_earth($1, $2)) AS distance_m
+                FROM todos
+                WHERE latitude IS NOT NULL AND deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft
+                  AND earth_box(ll_to_earth($1, $2), $3 + $4) @> ll_to_earth(latitude, longitude)
+            ) nearby
+            WHERE distance_m <= $3 + COALESCE(radius_m, 0)
+            ORDER BY distance_m
+            LIMIT $5
+            "#,
+            TODO_COLUMNS
+        );
+        let todos = sqlx::query_as::<_, NearbyTodo>(&sql)
+            .bind(latitude)
+            .bind(longitude)
+            .bind(f64::from(radius_m))
+            .bind(f64::from(MAX_GEOFENCE_RADIUS_M))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(todos)
+    }
+
+    async fn workload(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<WorkloadDay>, AppError> {
+        let days = sqlx::query!(
+            r#"
+            SELECT (due_date AT TIME ZONE 'UTC')::date as "day!",
+                   COALESCE(SUM(estimate_minutes), 0)::bigint as "estimate_minutes!",
+                   COUNT(*) as "todos!",
+                   COUNT(*) FILTER (WHERE estimate_minutes IS NULL) as "unestimated!"
+            FROM todos
+            WHERE due_date IS NOT NULL AND deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft
+              AND (due_date AT TIME ZONE 'UTC')::date BETWEEN $1 AND $2
+            GROUP BY 1
+            ORDER BY 1
+            "#,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| WorkloadDay {
+            date: row.day,
+            estimate_minutes: row.estimate_minutes,
+            todos: row.todos,
+            unestimated: row.unestimated,
+            over_capacity: false,
+        })
+        .collect();
+
+        Ok(days)
+    }
+
+    async fn due_between(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<TodoResponse>, AppError> {
+        let todos = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            FROM todos
+            WHERE due_date IS NOT NULL AND deleted_at IS NULL AND NOT draft
+              AND (due_date AT TIME ZONE 'UTC')::date BETWEEN $1 AND $2
+            ORDER BY due_date, created_at
+            "#,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    async fn completion_days(&self) -> Result<Vec<(NaiveDate, i64)>, AppError> {
+        let days = sqlx::query!(
+            r#"
+            SELECT (completed_at AT TIME ZONE 'UTC')::date as "day!", COUNT(*) as "count!"
+            FROM todos
+            WHERE completed AND completed_at IS NOT NULL AND deleted_at IS NULL AND NOT draft
+            GROUP BY 1
+            ORDER BY 1
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.day, row.count))
+        .collect();
+
+        Ok(days)
+    }
+
+    async fn started_between(
+        &self,
+        after: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<TodoResponse>, AppError> {
+        let todos = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            FROM todos
+            WHERE start_date > $1 AND start_date <= $2
+              AND deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft
+            ORDER BY start_date
+            "#,
+            after,
+            until
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    async fn completed_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<TodoResponse>, AppError> {
+        let todos = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            FROM todos
+            WHERE completed AND completed_at >= $1 AND completed_at < $2
+              AND deleted_at IS NULL AND NOT draft
+            ORDER BY completed_at
+            LIMIT $3
+            "#,
+            from,
+            to,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    async fn carried_over(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<TodoResponse>, AppError> {
+        let todos = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            FROM todos
+            WHERE created_at < $1
+              AND (completed IS NOT TRUE OR completed_at >= $2)
+              AND deleted_at IS NULL AND NOT draft
+            ORDER BY created_at
+            LIMIT $3
+            "#,
+            from,
+            to,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    async fn count_created_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<i64, AppError> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!"
+            FROM todos
+            WHERE created_at >= $1 AND created_at < $2
+              AND deleted_at IS NULL AND NOT draft
+            "#,
+            from,
+            to
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn stale(
+        &self,
+        before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<TodoResponse>, AppError> {
+        let todos = sqlx::query_as!(
+            TodoResponse,
+            r#"
+            SELECT id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+            FROM todos
+            WHERE updated_at < $1
+              AND deleted_at IS NULL AND completed IS NOT TRUE AND NOT draft
+            ORDER BY updated_at
+            LIMIT $2
+            "#,
+            before,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    async fn last_modified(&self) -> Result<Option<DateTime<Utc>>, AppError> {
+        let last_modified =
+            sqlx::query_scalar!(r#"SELECT MAX(GREATEST(updated_at, deleted_at)) FROM todos"#)
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(last_modified)
+    }
+    async fn import(
+        &self,
+        todos: Vec<(Uuid, ImportedTodo)>,
+        dry_run: bool,
+    ) -> Result<Vec<Result<TodoResponse, AppError>>, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let mut results = Vec::with_capacity(todos.len());
+        let now = self.clock.now();
+
+        for (id, todo) in todos {
+            // One savepoint per todo so a duplicate only rolls back that row
+            let mut savepoint = tx.begin().await?;
+            let inserted = sqlx::query_as!(
+                TodoResponse,
+                r#"
+                INSERT INTO todos (id, title, description, tags, due_date, completed, completed_at, created_at, updated_at)
+                VALUES ($9, $1, $2, $3, $4, $5, CASE WHEN $5 THEN COALESCE($6, $8::timestamptz) END, COALESCE($7, $8), $8)
+                RETURNING id, title, description, completed as "completed!", created_at as "created_at!", updated_at as "updated_at!", tags, completed_at, due_date, latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata, draft, start_date
+                "#,
+                todo.title,
+                todo.description,
+                &todo.tags,
+                todo.due_date,
+                todo.completed,
+                todo.completed_at,
+                todo.created_at,
+                now,
+                id
+            )
+            .fetch_one(&mut *savepoint)
+            .await;
+
+            match inserted {
+                Ok(inserted) => {
+                    savepoint.commit().await?;
+                    results.push(Ok(inserted));
+                }
+                Err(e) if Self::is_duplicate(&e) => {
+                    savepoint.rollback().await?;
+                    results.push(Err(AppError::Conflict(format!(
+                        "An open todo titled \"{}\" already exists",
+                        todo.title
+                    ))));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        finish(tx, dry_run).await?;
+
+        Ok(results)
+    }
+
+    async fn dump(&self) -> Result<Vec<BackupTodo>, AppError> {
+        let todos = sqlx::query_as!(
+            BackupTodo,
+            r#"
+            SELECT id, title, description, completed as "completed!", completed_at,
+                   created_at as "created_at!", updated_at as "updated_at!", tags, due_date,
+                   latitude, longitude, radius_m, estimate_minutes, custom_fields, metadata,
+                   deleted_at, merged_into, draft, start_date
+            FROM todos
+            ORDER BY created_at, id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    async fn load_dump(&self, todos: &[BackupTodo]) -> Result<u64, AppError> {
+        let rows = serde_json::to_string(todos).map_err(|e| AppError::Internal(e.to_string()))?;
+        let mut tx = self.pool.begin().await?;
+
+        // Keep writers out until the restore is committed
+        sqlx::query!("LOCK TABLE todos IN EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await?;
+        let existing = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!" FROM todos"#)
+            .fetch_one(&mut *tx)
+            .await?;
+        if existing > 0 {
+            return Err(AppError::Conflict(format!(
+                "Backups can only be restored into an empty database ({} todos present)",
+                existing
+            )));
+        }
+
+        // A single statement, so merged_into may point at any row of the backup
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO todos (id, title, description, completed, completed_at, created_at,
+                               updated_at, tags, due_date, latitude, longitude, radius_m,
+                               estimate_minutes, custom_fields, metadata, deleted_at, merged_into,
+                               draft, start_date)
+            SELECT id, title, description, completed, completed_at, created_at,
+                   updated_at, tags, due_date, latitude, longitude, radius_m,
+                   estimate_minutes, COALESCE(custom_fields, '{}'), COALESCE(metadata, '{}'),
+                   deleted_at, merged_into, COALESCE(draft, FALSE), start_date
+            FROM jsonb_to_recordset($1::text::jsonb) AS t(
+                id UUID, title TEXT, description TEXT, completed BOOLEAN,
+                completed_at TIMESTAMPTZ, created_at TIMESTAMPTZ, updated_at TIMESTAMPTZ,
+                tags TEXT[], due_date TIMESTAMPTZ, latitude DOUBLE PRECISION,
+                longitude DOUBLE PRECISION, radius_m INTEGER, estimate_minutes INTEGER,
+                custom_fields JSONB, metadata JSONB, deleted_at TIMESTAMPTZ, merged_into UUID,
+                draft BOOLEAN, start_date TIMESTAMPTZ
+            )
+            "#,
+            rows
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn create_hook(
+        &self,
+        id: Uuid,
+        event: Trigger,
+        target_url: &str,
+    ) -> Result<HookSubscription, AppError> {
+        let created_at = sqlx::query_scalar!(
+            r#"
+            INSERT INTO hook_subscriptions (id, event, target_url, created_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING created_at
+            "#,
+            id,
+            event.name(),
+            target_url,
+            self.clock.now()
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(HookSubscription {
+            id,
+            event,
+            target_url: target_url.to_string(),
+            created_at,
+        })
+    }
+
+    async fn delete_hook(&self, id: Uuid) -> Result<(), AppError> {
+        let result = sqlx::query!("DELETE FROM hook_subscriptions WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Hook subscription with id {} not found",
+                id
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn hooks(&self, event: Trigger) -> Result<Vec<HookSubscription>, AppError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, target_url, created_at
+            FROM hook_subscriptions
+            WHERE event = $1
+            ORDER BY created_at
+            "#,
+            event.name()
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| HookSubscription {
+                id: row.id,
+                event,
+                target_url: row.target_url,
+                created_at: row.created_at,
+            })
+            .collect())
+    }
+
+    async fn create_rule(&self, id: Uuid, rule: &CreateRule) -> Result<Rule, AppError> {
+        let row = sqlx::query_as!(
+            RuleRow,
+            r#"
+            INSERT INTO rules (id, name, trigger, conditions, actions, enabled, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $7)
+            RETURNING id, name, trigger, conditions as "conditions: Json<Vec<Condition>>",
+                      actions as "actions: Json<Vec<Action>>", enabled, created_at, updated_at
+            "#,
+            id,
+            rule.name,
+            rule.trigger.name(),
+            Json(&rule.conditions) as _,
+            Json(&rule.actions) as _,
+            rule.enabled,
+            self.clock.now()
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_into()
+    }
+
+    async fn list_rules(&self) -> Result<Vec<Rule>, AppError> {
+        sqlx::query_as!(
+            RuleRow,
+            r#"
+            SELECT id, name, trigger, conditions as "conditions: Json<Vec<Condition>>",
+                   actions as "actions: Json<Vec<Action>>", enabled, created_at, updated_at
+            FROM rules
+            ORDER BY created_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(Rule::try_from)
+        .collect()
+    }
+
+    async fn get_rule(&self, id: Uuid) -> Result<Rule, AppError> {
+        sqlx::query_as!(
+            RuleRow,
+            r#"
+            SELECT id, name, trigger, conditions as "conditions: Json<Vec<Condition>>",
+                   actions as "actions: Json<Vec<Action>>", enabled, created_at, updated_at
+            FROM rules
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Rule with id {} not found", id)))?
+        .try_into()
+    }
+
+    async fn update_rule(&self, rule: &Rule) -> Result<Rule, AppError> {
+        sqlx::query_as!(
+            RuleRow,
+            r#"
+            UPDATE rules
+            SET name = $2, trigger = $3, conditions = $4, actions = $5, enabled = $6,
+                updated_at = $7
+            WHERE id = $1
+            RETURNING id, name, trigger, conditions as "conditions: Json<Vec<Condition>>",
+                      actions as "actions: Json<Vec<Action>>", enabled, created_at, updated_at
+            "#,
+            rule.id,
+            rule.name,
+            rule.trigger.name(),
+            Json(&rule.conditions) as _,
+            Json(&rule.actions) as _,
+            rule.enabled,
+            self.clock.now()
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Rule with id {} not found", rule.id)))?
+        .try_into()
+    }
+
+    async fn delete_rule(&self, id: Uuid) -> Result<(), AppError> {
+        let result = sqlx::query!("DELETE FROM rules WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("Rule with id {} not found", id)));
+        }
+
+        Ok(())
+    }
+
+    async fn enabled_rules(&self, trigger: RuleTrigger) -> Result<Vec<Rule>, AppError> {
+        sqlx::query_as!(
+            RuleRow,
+            r#"
+            SELECT id, name, trigger, conditions as "conditions: Json<Vec<Condition>>",
+                   actions as "actions: Json<Vec<Action>>", enabled, created_at, updated_at
+            FROM rules
+            WHERE enabled AND trigger = $1
+            ORDER BY created_at
+            "#,
+            trigger.name()
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(Rule::try_from)
+        .collect()
+    }
+
+    async fn insert_rule_run(
+        &self,
+        id: Uuid,
+        rule_id: Uuid,
+        todo_id: Uuid,
+        error: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO rule_runs (id, rule_id, todo_id, succeeded, error, ran_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            id,
+            rule_id,
+            todo_id,
+            error.is_none(),
+            error,
+            self.clock.now()
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn rule_runs(&self, rule_id: Uuid, limit: i64) -> Result<Vec<RuleRun>, AppError> {
+        let runs = sqlx::query_as!(
+            RuleRun,
+            r#"
+            SELECT id, rule_id, todo_id, succeeded, error, ran_at
+            FROM rule_runs
+            WHERE rule_id = $1
+            ORDER BY ran_at DESC
+            LIMIT $2
+            "#,
+            rule_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(runs)
+    }
+
+    async fn create_custom_field(
+        &self,
+        id: Uuid,
+        field: &CreateCustomField,
+    ) -> Result<CustomField, AppError> {
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO custom_fields (id, name, field_type, options, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, created_at
+            "#,
+            id,
+            field.name,
+            field.field_type.name(),
+            &field.options,
+            self.clock.now()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| match e {
+            SqlxError::Database(db) if db.is_unique_violation() => AppError::Conflict(format!(
+                "A custom field named '{}' already exists",
+                field.name
+            )),
+            e => e.into(),
+        })?;
+
+        Ok(CustomField {
+            id: row.id,
+            name: field.name.clone(),
+            field_type: field.field_type,
+            options: field.options.clone(),
+            created_at: row.created_at,
+        })
+    }
+
+    async fn custom_fields(&self) -> Result<Vec<CustomField>, AppError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, name, field_type, options, created_at
+            FROM custom_fields
+            ORDER BY name
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let field_type = FieldType::from_name(&row.field_type).ok_or_else(|| {
+                    AppError::Internal(format!(
+                        "Custom field {} has unknown type '{}'",
+                        row.id, row.field_type
+                    ))
+                })?;
+                Ok(CustomField {
+                    id: row.id,
+                    name: row.name,
+                    field_type,
+                    options: row.options,
+                    created_at: row.created_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_custom_field(&self, id: Uuid) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let name = sqlx::query_scalar!(
+            r#"DELETE FROM custom_fields WHERE id = $1 RETURNING name"#,
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Custom field with id {} not found", id)))?;
+
+        // Soft-deleted todos too, so a restored todo doesn't bring it back
+        sqlx::query!(
+            r#"
+            UPDATE todos SET custom_fields = custom_fields - $1::text, updated_at = $2
+            WHERE custom_fields ? $1::text
+            "#,
+            name,
+            self.clock.now()
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn create_relation(
+        &self,
+        id: Uuid,
+        todo_id: Uuid,
+        relation: &CreateRelation,
+    ) -> Result<Relation, AppError> {
+        // Both todos' details list the link, so both count as updated
+        let row = sqlx::query!(
+            r#"
+            WITH touched AS (
+                UPDATE todos SET updated_at = $4 WHERE id IN ($1, $2)
+            )
+            INSERT INTO todo_relations (id, todo_id, related_id, kind, created_at)
+            VALUES ($5, $1, $2, $3, $4)
+            RETURNING id, created_at
+            "#,
+            todo_id,
+            relation.related_id,
+            relation.kind.name(),
+            self.clock.now(),
+            id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| match e {
+            SqlxError::Database(db) if db.is_unique_violation() => AppError::Conflict(format!(
+                "Todo {} is already linked to {} as {}",
+                todo_id,
+                relation.related_id,
+                relation.kind.name()
+            )),
+            e => e.into(),
+        })?;
+
+        Ok(Relation {
+            id: row.id,
+            todo_id,
+            related_id: relation.related_id,
+            kind: relation.kind,
+            created_at: row.created_at,
+        })
+    }
+
+    async fn delete_relations(
+        &self,
+        todo_id: Uuid,
+        related_id: Uuid,
+        kind: Option<RelationKind>,
+    ) -> Result<u64, AppError> {
+        let removed = sqlx::query_scalar!(
+            r#"
+            WITH removed AS (
+                DELETE FROM todo_relations
+                WHERE ((todo_id = $1 AND related_id = $2) OR (todo_id = $2 AND related_id = $1))
+                  AND ($3::text IS NULL OR kind = $3)
+                RETURNING id
+            ), touched AS (
+                UPDATE todos SET updated_at = $4
+                WHERE id IN ($1, $2) AND EXISTS (SELECT 1 FROM removed)
+            )
+            SELECT COUNT(*) as "count!" FROM removed
+            "#,
+            todo_id,
+            related_id,
+            kind.map(|kind| kind.name()),
+            self.clock.now()
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(removed as u64)
+    }
+
+    async fn related(&self, todo_id: Uuid) -> Result<Vec<RelatedTodo>, AppError> {
+        // relates_to is undirected, so it always reads as outgoing
+        let rows = sqlx::query!(
+            r#"
+            SELECT t.id as "id!", t.title as "title!", t.completed as "completed!",
+                   r.kind as "kind!", (r.todo_id = $1 OR r.kind = 'relates_to') as "outgoing!"
+            FROM todo_relations r
+            JOIN todos t ON t.id = CASE WHEN r.todo_id = $1 THEN r.related_id ELSE r.todo_id END
+            WHERE (r.todo_id = $1 OR r.related_id = $1) AND t.deleted_at IS NULL
+            ORDER BY r.created_at, t.id
+            "#,
+            todo_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let kind = RelationKind::from_name(&row.kind).ok_or_else(|| {
+                    AppError::Internal(format!("Unknown relation kind '{}'", row.kind))
+                })?;
+                Ok(RelatedTodo {
+                    id: row.id,
+                    title: row.title,
+                    completed: row.completed,
+                    kind,
+                    direction: if row.outgoing {
+                        RelationDirection::Outgoing
+                    } else {
+                        RelationDirection::Incoming
+                    },
+                })
+            })
+            .collect()
+    }
+
+    async fn lock(
+        &self,
+        todo_id: Uuid,
+        holder: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<TodoLock, AppError> {
+        // Only a free, expired or own lock is (over)written
+        let lock = sqlx::query_as!(
+            TodoLock,
+            r#"
+            INSERT INTO todo_locks (todo_id, holder, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (todo_id) DO UPDATE
+            SET holder = EXCLUDED.holder, expires_at = EXCLUDED.expires_at
+            WHERE todo_locks.holder = EXCLUDED.holder OR todo_locks.expires_at <= $4
+            RETURNING todo_id, holder, expires_at
+            "#,
+            todo_id,
+            holder,
+            expires_at,
+            self.clock.now()
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match lock {
+            Some(lock) => Ok(lock),
+            None => match self.active_lock(todo_id).await? {
+                Some(held) => Err(AppError::Locked {
+                    holder: held.holder,
+                    expires_at: held.expires_at,
+                }),
+                // Released or expired in between
+                None => self.lock(todo_id, holder, expires_at).await,
+            },
+        }
+    }
+
+    async fn unlock(&self, todo_id: Uuid, holder: &str) -> Result<(), AppError> {
+        let now = self.clock.now();
+        sqlx::query!(
+            r#"
+            DELETE FROM todo_locks
+            WHERE todo_id = $1 AND (holder = $2 OR expires_at <= $3)
+            "#,
+            todo_id,
+            holder,
+            now
+        )
+        .execute(&self.pool)
+        .await?;
+
+        match self.active_lock(todo_id).await? {
+            Some(held) => Err(AppError::Locked {
+                holder: held.holder,
+                expires_at: held.expires_at,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    async fn active_lock(&self, todo_id: Uuid) -> Result<Option<TodoLock>, AppError> {
+        let lock = sqlx::query_as!(
+            TodoLock,
+            r#"
+            SELECT todo_id, holder, expires_at FROM todo_locks
+            WHERE todo_id = $1 AND expires_at > $2
+            "#,
+            todo_id,
+            self.clock.now()
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(lock)
+    }
+
+    async fn purge_deleted_todos(
+        &self,
+        before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<u64, AppError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM todos
+            WHERE id IN (
+                SELECT t.id FROM todos t
+                WHERE t.deleted_at < $1
+                  AND NOT EXISTS (SELECT 1 FROM todos m WHERE m.merged_into = t.id)
+                LIMIT $2
+            )
+            "#,
+            before,
+            limit
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn purge_rule_runs(&self, before: DateTime<Utc>, limit: i64) -> Result<u64, AppError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM rule_runs
+            WHERE id IN (SELECT id FROM rule_runs WHERE ran_at < $1 LIMIT $2)
+            "#,
+            before,
+            limit
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create(repo: &PostgresTodoRepository, title: &str, tags: Vec<String>) -> TodoResponse {
+        let payload = CreateTodo {
+            title: title.to_string(),
+            tags,
+            ..CreateTodo::default()
+        };
+        repo.create(Uuid::new_v4(), payload, false).await.unwrap()
+    }
+
+    #[sqlx::test]
+    async fn bulk_tag_skips_todos_at_the_tag_limit(pool: DbPool) {
+        let repo = PostgresTodoRepository::new(pool);
+        let tags: Vec<String> = (0..MAX_TAGS).map(|i| format!("tag-{}", i)).collect();
+        let full = create(&repo, "Full", tags).await;
+        let roomy = create(&repo, "Roomy", vec!["tag-0".to_string()]).await;
+
+        let (todos, locked, at_tag_limit) = repo
+            .bulk_tag(&TodoQuery::default(), TagAction::Add, "q4", None, false)
+            .await
+            .unwrap();
+
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].id, roomy.id);
+        assert_eq!(todos[0].tags, ["tag-0", "q4"]);
+        assert_eq!(locked, 0);
+        assert_eq!(at_tag_limit, [full.id]);
+        assert_eq!(repo.get(full.id).await.unwrap().tags.len(), MAX_TAGS);
+    }
 }
@@ -0,0 +1,136 @@
+use crate::error::AppError;
+use crate::service::TodoService;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ts_rs::TS;
+
+/// Data that is purged once it is older than its retention period
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    /// Soft-deleted todos, by deletion time
+    DeletedTodos,
+    /// The automation rule execution log
+    RuleRuns,
+}
+
+/// Outcome of the latest run of a retention policy
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct RetentionStatus {
+    pub policy: Policy,
+    pub retention_days: u32,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Rows deleted by the last run
+    #[ts(type = "number")]
+    pub purged: u64,
+    /// Why the last run stopped early, if it did
+    pub error: Option<String>,
+}
+
+/// Retention settings and the status of each policy's latest run
+pub struct Retention {
+    /// Rows deleted per statement, so no purge holds locks for long
+    batch_size: u32,
+    statuses: Mutex<Vec<RetentionStatus>>,
+}
+
+impl Retention {
+    /// Policies with a retention of 0 days are disabled and not listed
+    pub fn new(deleted_todos_days: u32, rule_runs_days: u32, batch_size: u32) -> Self {
+        let statuses = [
+            (Policy::DeletedTodos, deleted_todos_days),
+            (Policy::RuleRuns, rule_runs_days),
+        ]
+        .into_iter()
+        .filter(|(_, days)| *days > 0)
+        .map(|(policy, retention_days)| RetentionStatus {
+            policy,
+            retention_days,
+            last_run_at: None,
+            purged: 0,
+            error: None,
+        })
+        .collect();
+
+        Self {
+            batch_size: batch_size.max(1),
+            statuses: Mutex::new(statuses),
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<RetentionStatus> {
+        self.statuses.lock().unwrap().clone()
+    }
+
+    fn enabled(&self) -> Vec<(Policy, u32)> {
+        self.statuses
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|status| (status.policy, status.retention_days))
+            .collect()
+    }
+
+    /// Run every enabled policy once
+    pub async fn run(&self, service: &TodoService) {
+        for (policy, days) in self.enabled() {
+            let before = service.clock().now() - ChronoDuration::days(i64::from(days));
+            let mut purged = 0;
+            let error = loop {
+                match self.purge_batch(service, policy, before).await {
+                    Ok(deleted) => {
+                        purged += deleted;
+                        if deleted < u64::from(self.batch_size) {
+                            break None;
+                        }
+                    }
+                    Err(e) => break Some(e.to_string()),
+                }
+                // Let other queries in between batches
+                tokio::task::yield_now().await;
+            };
+
+            match &error {
+                Some(e) => tracing::error!("Retention purge of {:?} failed: {}", policy, e),
+                None if purged > 0 => tracing::info!("Retention purged {} {:?}", purged, policy),
+                None => {}
+            }
+            if let Some(status) = self
+                .statuses
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .find(|status| status.policy == policy)
+            {
+                status.last_run_at = Some(service.clock().now());
+                status.purged = purged;
+                status.error = error;
+            }
+        }
+    }
+
+    async fn purge_batch(
+        &self,
+        service: &TodoService,
+        policy: Policy,
+        before: DateTime<Utc>,
+    ) -> Result<u64, AppError> {
+        match policy {
+            Policy::DeletedTodos => service.purge_deleted_todos(before, self.batch_size).await,
+            Policy::RuleRuns => service.purge_rule_runs(before, self.batch_size).await,
+        }
+    }
+}
+
+/// Run the retention policies every `interval`, starting right away
+pub fn spawn_retention(retention: Arc<Retention>, service: Arc<TodoService>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            retention.run(&service).await;
+        }
+    });
+}
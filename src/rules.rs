@@ -0,0 +1,435 @@
+use crate::events::TodoEvent;
+use crate::models::{TodoResponse, UpdateTodo};
+use crate::outbound;
+use crate::service::TodoService;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// How long a webhook action's target gets to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events a rule can run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleTrigger {
+    Created,
+    Completed,
+    Updated,
+}
+
+impl RuleTrigger {
+    /// Name stored in the database (matches the serialized form)
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuleTrigger::Created => "created",
+            RuleTrigger::Completed => "completed",
+            RuleTrigger::Updated => "updated",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            RuleTrigger::Created,
+            RuleTrigger::Completed,
+            RuleTrigger::Updated,
+        ]
+        .into_iter()
+        .find(|trigger| trigger.name() == name)
+    }
+
+    /// Trigger fired by a domain event, with the todo it is about
+    pub fn for_event(event: &TodoEvent) -> Option<(RuleTrigger, &TodoResponse)> {
+        match event {
+            TodoEvent::Created(todo) => Some((RuleTrigger::Created, todo)),
+            TodoEvent::Completed(todo) => Some((RuleTrigger::Completed, todo)),
+            TodoEvent::Updated(todo) => Some((RuleTrigger::Updated, todo)),
+            _ => None,
+        }
+    }
+}
+
+/// A test on the todo a rule runs for; a rule runs when all of them pass
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    HasTag {
+        tag: String,
+    },
+    /// Case-insensitive substring match on the title
+    TitleContains {
+        text: String,
+    },
+}
+
+impl Condition {
+    pub fn matches(&self, todo: &TodoResponse) -> bool {
+        match self {
+            Condition::HasTag { tag } => todo.tags.contains(tag),
+            Condition::TitleContains { text } => {
+                todo.title.to_lowercase().contains(&text.to_lowercase())
+            }
+        }
+    }
+}
+
+/// A step a rule performs, in order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    AddTag {
+        tag: String,
+    },
+    RemoveTag {
+        tag: String,
+    },
+    SetCompleted {
+        completed: bool,
+    },
+    /// POST `{"rule_id", "trigger", "todo"}` to `url`
+    Webhook {
+        url: String,
+    },
+}
+
+/// An automation rule: when `trigger` fires for a todo matching every
+/// condition, the actions run in order
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct Rule {
+    pub id: Uuid,
+    pub name: String,
+    pub trigger: RuleTrigger,
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request DTO for creating a rule
+#[derive(Debug, Serialize, Deserialize, TS)]
+pub struct CreateRule {
+    pub name: String,
+    pub trigger: RuleTrigger,
+    #[serde(default)]
+    #[ts(as = "Option<Vec<Condition>>", optional)]
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+    #[serde(default = "enabled_by_default")]
+    #[ts(as = "Option<bool>", optional)]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Request DTO for changing a rule; omitted fields are kept
+#[derive(Debug, Default, Serialize, Deserialize, TS)]
+pub struct UpdateRule {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub trigger: Option<RuleTrigger>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub conditions: Option<Vec<Condition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub actions: Option<Vec<Action>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub enabled: Option<bool>,
+}
+
+/// One execution of a rule whose conditions matched
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct RuleRun {
+    pub id: Uuid,
+    pub rule_id: Uuid,
+    pub todo_id: Uuid,
+    pub succeeded: bool,
+    /// Why the run stopped; actions before the failing one were applied
+    pub error: Option<String>,
+    pub ran_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    rule_id: Uuid,
+    trigger: RuleTrigger,
+    todo: &'a TodoResponse,
+}
+
+/// A webhook action to send, with the todo as it was at that point of the
+/// rule's actions
+struct PendingWebhook {
+    url: String,
+    todo: TodoResponse,
+}
+
+/// Run the enabled rules for every event, logging each run. Changes made by
+/// rule actions don't trigger rules themselves, so rules can't loop.
+pub fn spawn_rule_engine(service: Arc<TodoService>) {
+    let mut events = service.events().subscribe();
+    let http = outbound::client(WEBHOOK_TIMEOUT);
+
+    tokio::spawn(async move {
+        // Versions of todos written by actions, with how many of their
+        // events are still to be ignored
+        let mut own_writes: HashMap<(Uuid, DateTime<Utc>), usize> = HashMap::new();
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "Rule engine fell behind, events were dropped");
+                    // The events of some of these writes may have been
+                    // dropped and would never clear their entries
+                    own_writes.clear();
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let Some((trigger, todo)) = RuleTrigger::for_event(&event) else {
+                continue;
+            };
+            let version = (todo.id, todo.updated_at);
+            if let Some(pending) = own_writes.get_mut(&version) {
+                *pending -= 1;
+                if *pending == 0 {
+                    own_writes.remove(&version);
+                }
+                continue;
+            }
+
+            let rules = match service.rules_for(trigger).await {
+                Ok(rules) => rules,
+                Err(e) => {
+                    tracing::error!("Failed to load rules: {}", e);
+                    continue;
+                }
+            };
+            let mut todo = todo.clone();
+            for rule in rules {
+                if !rule
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.matches(&todo))
+                {
+                    continue;
+                }
+                let (webhooks, result) =
+                    run_actions(&service, &rule, &mut todo, &mut own_writes).await;
+                if webhooks.is_empty() {
+                    record_run(&service, &rule, todo.id, result.err()).await;
+                } else {
+                    // Slow targets must not hold up the events queued behind
+                    // this one, as hook deliveries don't either
+                    tokio::spawn(send_webhooks(
+                        service.clone(),
+                        http.clone(),
+                        rule,
+                        todo.id,
+                        webhooks,
+                        result.err(),
+                    ));
+                }
+            }
+        }
+    });
+}
+
+/// Apply a rule's tag and completion actions to `todo`, keeping it up to
+/// date for later rules, and collect its webhooks to be sent afterwards.
+/// Actions that wouldn't change anything are skipped; the first failing one
+/// stops the run.
+async fn run_actions(
+    service: &TodoService,
+    rule: &Rule,
+    todo: &mut TodoResponse,
+    own_writes: &mut HashMap<(Uuid, DateTime<Utc>), usize>,
+) -> (Vec<PendingWebhook>, Result<(), String>) {
+    let mut webhooks = Vec::new();
+    for action in &rule.actions {
+        let update = match action {
+            Action::AddTag { tag } if !todo.tags.contains(tag) => {
+                let mut tags = todo.tags.clone();
+                tags.push(tag.clone());
+                UpdateTodo {
+                    tags: Some(tags),
+                    ..UpdateTodo::default()
+                }
+            }
+            Action::RemoveTag { tag } if todo.tags.contains(tag) => UpdateTodo {
+                tags: Some(todo.tags.iter().filter(|t| *t != tag).cloned().collect()),
+                ..UpdateTodo::default()
+            },
+            Action::SetCompleted { completed } if todo.completed != *completed => UpdateTodo {
+                completed: Some(*completed),
+                ..UpdateTodo::default()
+            },
+            Action::Webhook { url } => {
+                webhooks.push(PendingWebhook {
+                    url: url.clone(),
+                    todo: todo.clone(),
+                });
+                continue;
+            }
+            _ => continue,
+        };
+
+        match service.update(todo.id, update, None, false).await {
+            Ok(updated) => *todo = updated,
+            Err(e) => return (webhooks, Err(e.to_string())),
+        }
+        // Completing a todo is announced as an update and a completion
+        let events = if todo.just_completed() { 2 } else { 1 };
+        *own_writes.entry((todo.id, todo.updated_at)).or_default() += events;
+    }
+    (webhooks, Ok(()))
+}
+
+/// Send a rule's webhooks in order, then log the run. A failing webhook
+/// fails the run and the webhooks after it aren't sent; `error` is why the
+/// other actions stopped, if they did.
+async fn send_webhooks(
+    service: Arc<TodoService>,
+    http: reqwest::Client,
+    rule: Rule,
+    todo_id: Uuid,
+    webhooks: Vec<PendingWebhook>,
+    mut error: Option<String>,
+) {
+    for webhook in &webhooks {
+        let payload = WebhookPayload {
+            rule_id: rule.id,
+            trigger: rule.trigger,
+            todo: &webhook.todo,
+        };
+        // The client's resolver keeps hostnames on public addresses, but IP
+        // literals never reach it
+        let target = reqwest::Url::parse(&webhook.url).map_err(|e| e.to_string());
+        if let Err(e) = target.and_then(|url| outbound::check_host(&url)) {
+            error = Some(format!("Webhook to {} refused: {}", webhook.url, e));
+            break;
+        }
+        let failure = match http.post(&webhook.url).json(&payload).send().await {
+            Ok(response) if response.status().is_success() => None,
+            Ok(response) => Some(format!(
+                "Webhook to {} answered {}",
+                webhook.url,
+                response.status()
+            )),
+            Err(e) => Some(format!("Webhook to {} failed: {}", webhook.url, e)),
+        };
+        if failure.is_some() {
+            error = failure;
+            break;
+        }
+    }
+    record_run(&service, &rule, todo_id, error).await;
+}
+
+/// Log a run of `rule` for `todo_id`, failed when there is an `error`
+async fn record_run(service: &TodoService, rule: &Rule, todo_id: Uuid, error: Option<String>) {
+    if let Some(e) = &error {
+        tracing::warn!(rule_id = %rule.id, todo_id = %todo_id, "Rule failed: {}", e);
+    }
+    if let Err(e) = service.record_rule_run(rule.id, todo_id, error).await {
+        tracing::error!(rule_id = %rule.id, "Failed to log rule run: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn todo(title: &str, tags: &[&str]) -> TodoResponse {
+        let now = Utc::now();
+        TodoResponse {
+            id: Uuid::new_v4(),
+            title: title.to_string(),
+            description: None,
+            completed: false,
+            completed_at: None,
+            created_at: now,
+            updated_at: now,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            due_date: None,
+            latitude: None,
+            longitude: None,
+            radius_m: None,
+            estimate_minutes: None,
+            custom_fields: json!({}),
+            metadata: json!({}),
+            draft: false,
+            start_date: None,
+        }
+    }
+
+    #[test]
+    fn maps_events_to_triggers() {
+        let todo = todo("Pay rent", &[]);
+        for (event, trigger) in [
+            (TodoEvent::Created(todo.clone()), RuleTrigger::Created),
+            (TodoEvent::Completed(todo.clone()), RuleTrigger::Completed),
+            (TodoEvent::Updated(todo.clone()), RuleTrigger::Updated),
+        ] {
+            let (fired, about) = RuleTrigger::for_event(&event).unwrap();
+            assert_eq!(fired, trigger);
+            assert_eq!(about.id, todo.id);
+            assert_eq!(RuleTrigger::from_name(trigger.name()), Some(trigger));
+        }
+        assert!(RuleTrigger::for_event(&TodoEvent::Deleted { id: todo.id }).is_none());
+        assert_eq!(RuleTrigger::from_name("deleted"), None);
+    }
+
+    #[test]
+    fn matches_tags_exactly() {
+        let condition = Condition::HasTag {
+            tag: "urgent".to_string(),
+        };
+        assert!(condition.matches(&todo("Pay rent", &["home", "urgent"])));
+        assert!(!condition.matches(&todo("Pay rent", &["not-urgent"])));
+        assert!(!condition.matches(&todo("Pay rent", &[])));
+    }
+
+    #[test]
+    fn matches_titles_ignoring_case() {
+        let condition = Condition::TitleContains {
+            text: "Invoice".to_string(),
+        };
+        assert!(condition.matches(&todo("Send INVOICE to Acme", &[])));
+        assert!(condition.matches(&todo("invoices", &[])));
+        assert!(!condition.matches(&todo("Send bill", &[])));
+    }
+
+    #[test]
+    fn reads_conditions_and_actions_by_type() {
+        let rule: CreateRule = serde_json::from_value(json!({
+            "name": "Escalate",
+            "trigger": "created",
+            "conditions": [{"type": "title_contains", "text": "outage"}],
+            "actions": [
+                {"type": "add_tag", "tag": "urgent"},
+                {"type": "webhook", "url": "https://example.com/hook"}
+            ]
+        }))
+        .unwrap();
+        assert!(rule.enabled);
+        assert_eq!(
+            rule.actions[1],
+            Action::Webhook {
+                url: "https://example.com/hook".to_string()
+            }
+        );
+    }
+}
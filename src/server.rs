@@ -0,0 +1,220 @@
+use crate::config::Config;
+use axum::{extract::ConnectInfo, Router};
+use hyper::body::Incoming;
+use hyper::Request;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpSocket};
+use tower::ServiceExt;
+
+/// HTTP protocol settings applied to every accepted connection
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// Accept HTTP/2 (prior knowledge / h2c) next to HTTP/1.1
+    pub http2: bool,
+    pub http2_max_concurrent_streams: u32,
+    /// Interval between HTTP/2 keep-alive pings; None disables them
+    pub http2_keep_alive_interval: Option<Duration>,
+    /// How long to wait for a ping acknowledgement before closing
+    pub http2_keep_alive_timeout: Duration,
+    /// Reuse HTTP/1.1 connections for several requests
+    pub keep_alive: bool,
+    /// How long a client may take to send request headers, which also bounds
+    /// how long an idle HTTP/1.1 connection stays open
+    pub header_read_timeout: Duration,
+    pub tcp_nodelay: bool,
+    /// How long open connections may keep running after shutdown starts
+    pub shutdown_timeout: Duration,
+}
+
+impl From<&Config> for ServerOptions {
+    fn from(config: &Config) -> Self {
+        Self {
+            http2: config.http2,
+            http2_max_concurrent_streams: config.http2_max_concurrent_streams,
+            http2_keep_alive_interval: (config.http2_keep_alive_interval_secs > 0)
+                .then(|| Duration::from_secs(config.http2_keep_alive_interval_secs)),
+            http2_keep_alive_timeout: Duration::from_secs(config.http2_keep_alive_timeout_secs),
+            keep_alive: config.keep_alive,
+            header_read_timeout: Duration::from_secs(config.header_read_timeout_secs),
+            tcp_nodelay: config.tcp_nodelay,
+            shutdown_timeout: Duration::from_secs(config.shutdown_timeout_secs),
+        }
+    }
+}
+
+impl ServerOptions {
+    fn builder(&self) -> Builder<TokioExecutor> {
+        let mut builder = Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .keep_alive(self.keep_alive)
+            .header_read_timeout(self.header_read_timeout);
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .max_concurrent_streams(self.http2_max_concurrent_streams)
+            .keep_alive_interval(self.http2_keep_alive_interval)
+            .keep_alive_timeout(self.http2_keep_alive_timeout);
+        if self.http2 {
+            builder
+        } else {
+            builder.http1_only()
+        }
+    }
+}
+
+/// First file descriptor passed by systemd socket activation
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// Listening socket number `index` handed over by the service manager
+/// (systemd socket activation: `LISTEN_PID`/`LISTEN_FDS`), if there is one
+#[cfg(unix)]
+fn activated_listener(index: usize) -> io::Result<Option<std::net::TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<usize>().ok())
+        .unwrap_or(0);
+    if !for_us || index >= count {
+        return Ok(None);
+    }
+
+    // SAFETY: the service manager passes the sockets as open descriptors
+    // starting at SD_LISTEN_FDS_START, and each index is taken only once
+    let listener =
+        unsafe { std::net::TcpListener::from_raw_fd(SD_LISTEN_FDS_START + index as i32) };
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+#[cfg(not(unix))]
+fn activated_listener(_index: usize) -> io::Result<Option<std::net::TcpListener>> {
+    Ok(None)
+}
+
+/// Listening socket for `addr`: socket number `index` inherited through socket
+/// activation when present, otherwise a fresh bind. With `reuse_port` several
+/// processes can bind the same port, so a new instance can start accepting
+/// before the old one drains and exits.
+pub fn listen(addr: SocketAddr, index: usize, reuse_port: bool) -> io::Result<TcpListener> {
+    if let Some(listener) = activated_listener(index)? {
+        return TcpListener::from_std(listener);
+    }
+
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    #[cfg(unix)]
+    socket.set_reuseport(reuse_port)?;
+    #[cfg(not(unix))]
+    if reuse_port {
+        tracing::warn!("REUSE_PORT is not supported on this platform");
+    }
+    socket.bind(addr)?;
+    socket.listen(1024)
+}
+
+/// Resolves on Ctrl+C or SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Serve `app` on `listener`, making the peer address available through
+/// `ConnectInfo<SocketAddr>`. Once `shutdown` resolves the listener is closed,
+/// open connections finish their current requests and the function returns
+/// when they are done or `shutdown_timeout` has passed.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    options: ServerOptions,
+    shutdown: impl Future<Output = ()>,
+) {
+    let builder = options.builder();
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+        let (stream, addr) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Usually a transient resource limit (e.g. out of file
+                // descriptors); back off instead of spinning
+                tracing::error!("Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        if let Err(e) = stream.set_nodelay(options.tcp_nodelay) {
+            tracing::debug!("Failed to set TCP_NODELAY: {}", e);
+        }
+
+        let service = app
+            .clone()
+            .map_request(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(addr));
+                request
+            });
+        let builder = builder.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let connection =
+                builder.serve_connection(TokioIo::new(stream), TowerToHyperService::new(service));
+            if let Err(e) = watcher.watch(connection).await {
+                tracing::debug!("Connection from {} ended with an error: {}", addr, e);
+            }
+        });
+    }
+
+    // Stop accepting right away so a replacement instance gets new clients
+    drop(listener);
+    tracing::info!("Shutting down, draining open connections");
+    if tokio::time::timeout(options.shutdown_timeout, graceful.shutdown())
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "Connections still open after {:?}, closing them",
+            options.shutdown_timeout
+        );
+    }
+}
@@ -0,0 +1,1879 @@
+use crate::backup::{Backup, RestoreReport, BACKUP_FORMAT, BACKUP_VERSION};
+use crate::clock::{Clock, SystemClock};
+use crate::custom_fields::{CreateCustomField, CustomField, FieldType};
+use crate::error::{AppError, ErrorMessage};
+use crate::events::{EventBus, TodoEvent};
+use crate::ids::{IdGenerator, UuidV4};
+use crate::import::ImportItem;
+use crate::input::Sanitizer;
+use crate::integrations::{
+    newest_first, HookSubscription, SubscribeHook, Trigger, TriggerItem, TRIGGER_ITEMS,
+};
+use crate::models::{
+    Aggregate, BulkResult, BulkTagRequest, Calendar, CalendarDay, CountMode, CreateRelation,
+    CreateTodo, GroupBy, ImportIssue, ImportReport, ImportedTodo, LockTodo, Metric, NearbyTodo,
+    Page, PageMeta, Relation, RelationKind, SetTagStyle, TagStyle, TagUsage, TodoDetail, TodoLock,
+    TodoQuery, TodoResponse, UnlockTodo, UpdateTodo, WeeklyReview, Workload, WorkloadDay,
+};
+use crate::outbound;
+use crate::repository::TodoRepository;
+use crate::rules::{Action, Condition, CreateRule, Rule, RuleRun, RuleTrigger, UpdateRule};
+use crate::stats::{self, Streaks};
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDate, Utc, Weekday};
+use serde_json::{Map, Value};
+use std::sync::{Arc, RwLock};
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+/// Maximum number of characters allowed in a todo title
+pub const MAX_TITLE_LENGTH: usize = 255;
+
+/// Maximum number of characters allowed in a todo description
+pub const MAX_DESCRIPTION_LENGTH: usize = 10_000;
+
+/// Maximum number of characters allowed in a tag
+pub const MAX_TAG_LENGTH: usize = 50;
+
+/// Maximum number of tags on a single todo
+pub const MAX_TAGS: usize = 20;
+
+/// Default and maximum number of suggestions returned by tag autocomplete
+pub const DEFAULT_TAG_SUGGESTIONS: u32 = 10;
+pub const MAX_TAG_SUGGESTIONS: u32 = 50;
+
+/// Maximum number of entries accepted in a single import
+pub const MAX_IMPORT_ITEMS: usize = 10_000;
+
+/// Number of entries in the recently completed feed
+pub const FEED_ENTRIES: i64 = 50;
+
+/// Most conditions, and most actions, a rule may have
+pub const MAX_RULE_STEPS: usize = 20;
+
+/// Number of executions returned by a rule's log
+pub const RULE_RUNS: i64 = 100;
+
+/// Largest geofence radius accepted on a todo, in meters (nearby searches
+/// widen their bounding box by it)
+pub const MAX_GEOFENCE_RADIUS_M: i32 = 50_000;
+
+/// Largest radius of a nearby search, in meters
+pub const MAX_NEARBY_RADIUS_M: i32 = 100_000;
+
+/// Number of todos returned by a nearby search
+pub const NEARBY_TODOS: i64 = 100;
+
+/// Longest estimate accepted on a todo (one week of minutes)
+pub const MAX_ESTIMATE_MINUTES: i32 = 7 * 24 * 60;
+
+/// Days covered by the workload report unless a range is given, and the
+/// longest range it accepts
+pub const DEFAULT_WORKLOAD_DAYS: u64 = 14;
+pub const MAX_WORKLOAD_DAYS: i64 = 92;
+
+/// Most todos listed per section of the weekly review
+pub const REVIEW_LIMIT: i64 = 100;
+
+/// Longest range the calendar view covers, in days
+pub const MAX_CALENDAR_DAYS: i64 = 92;
+
+/// Number of stale todos the weekly review suggests closing
+pub const REVIEW_STALE_SUGGESTIONS: i64 = 20;
+
+/// Days without an update after which an open todo is stale unless another
+/// span is asked for, and the longest span accepted
+pub const DEFAULT_STALE_DAYS: u32 = 30;
+pub const MAX_STALE_DAYS: u32 = 3650;
+
+/// Default and maximum number of todos listed as stale
+pub const DEFAULT_STALE_LIMIT: u32 = 50;
+pub const MAX_STALE_LIMIT: u32 = 200;
+
+/// Largest serialized size of a todo's metadata
+pub const MAX_METADATA_BYTES: usize = 16 * 1024;
+
+/// Todos a bulk update may change before the client has to confirm the count
+pub const DEFAULT_BULK_CONFIRM_THRESHOLD: u64 = 100;
+
+/// Lifetime of an edit lock unless the client asks for another one, and the
+/// longest it may ask for
+pub const DEFAULT_LOCK_SECS: u32 = 300;
+pub const MAX_LOCK_SECS: u32 = 3600;
+
+/// Maximum number of characters in the name of a lock holder
+pub const MAX_LOCK_HOLDER_LENGTH: usize = 100;
+
+/// Server-side bounds for paginated list endpoints
+#[derive(Debug, Clone, Copy)]
+pub struct PageLimits {
+    /// Page size used when the client doesn't ask for one
+    pub default_per_page: u32,
+    /// Largest page size a client may request
+    pub max_per_page: u32,
+}
+
+impl Default for PageLimits {
+    fn default() -> Self {
+        Self {
+            default_per_page: 20,
+            max_per_page: 100,
+        }
+    }
+}
+
+/// Business logic for todos.
+///
+/// Handlers only extract requests and map responses, the repository only
+/// talks SQL; every rule about what a valid todo is lives here, and every
+/// successful mutation is published as a TodoEvent.
+pub struct TodoService {
+    repo: Arc<dyn TodoRepository>,
+    events: Arc<dyn EventBus>,
+    sanitizer: Sanitizer,
+    page_limits: PageLimits,
+    /// Minutes of estimated work a day can hold before the workload report
+    /// flags it
+    workload_capacity: u32,
+    /// Last computed streaks, refreshed by `stats::spawn_streaks_refresh`
+    streaks: RwLock<Option<Arc<Streaks>>>,
+    clock: Arc<dyn Clock>,
+    ids: Arc<dyn IdGenerator>,
+    /// Largest number of todos a bulk update changes without the client
+    /// confirming the count
+    bulk_confirm_threshold: u64,
+}
+
+impl TodoService {
+    pub fn new(repo: Arc<dyn TodoRepository>, events: Arc<dyn EventBus>) -> Self {
+        Self {
+            repo,
+            events,
+            sanitizer: Sanitizer::default(),
+            page_limits: PageLimits::default(),
+            workload_capacity: 480,
+            streaks: RwLock::new(None),
+            clock: Arc::new(SystemClock),
+            ids: Arc::new(UuidV4),
+            bulk_confirm_threshold: DEFAULT_BULK_CONFIRM_THRESHOLD,
+        }
+    }
+
+    /// Replace the default input sanitizer
+    pub fn with_sanitizer(mut self, sanitizer: Sanitizer) -> Self {
+        self.sanitizer = sanitizer;
+        self
+    }
+
+    /// Replace the default pagination bounds
+    pub fn with_page_limits(mut self, page_limits: PageLimits) -> Self {
+        self.page_limits = page_limits;
+        self
+    }
+
+    /// Replace the default daily capacity (8 hours) of the workload report
+    pub fn with_workload_capacity(mut self, minutes: u32) -> Self {
+        self.workload_capacity = minutes;
+        self
+    }
+
+    /// Replace the system clock (give the repository the same one)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Replace the random (v4) id generator
+    pub fn with_id_generator(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// Replace the default number of todos (100) a bulk update may change
+    /// without confirmation
+    pub fn with_bulk_confirm_threshold(mut self, threshold: u64) -> Self {
+        self.bulk_confirm_threshold = threshold;
+        self
+    }
+
+    /// Create a new todo after sanitizing and validating the payload. With
+    /// `dry_run` the todo that would be created is returned but not kept.
+    pub async fn create(
+        &self,
+        mut payload: CreateTodo,
+        dry_run: bool,
+    ) -> Result<TodoResponse, AppError> {
+        let id = payload.id.take();
+        self.insert(id, payload, dry_run).await
+    }
+
+    /// Create a todo under an id chosen by the client (CalDAV names
+    /// resources itself); Conflict if the id is taken, even by a deleted todo
+    pub async fn create_with_id(
+        &self,
+        id: Uuid,
+        payload: CreateTodo,
+    ) -> Result<TodoResponse, AppError> {
+        self.insert(Some(id), payload, false).await
+    }
+
+    /// Create a todo unless an open todo already has its title, in which case
+    /// that one is returned instead of a DuplicateTodo error. The flag tells
+    /// whether the todo was created.
+    pub async fn create_or_existing(
+        &self,
+        mut payload: CreateTodo,
+        dry_run: bool,
+    ) -> Result<(TodoResponse, bool), AppError> {
+        let id = self.new_id(payload.id.take())?;
+        self.prepare_create(&mut payload).await?;
+
+        let (todo, created) = self.repo.create_or_existing(id, payload, dry_run).await?;
+        if created && !dry_run && !todo.draft {
+            self.events.publish(TodoEvent::Created(todo.clone()));
+        }
+        Ok((todo, created))
+    }
+
+    async fn insert(
+        &self,
+        id: Option<Uuid>,
+        mut payload: CreateTodo,
+        dry_run: bool,
+    ) -> Result<TodoResponse, AppError> {
+        let id = self.new_id(id)?;
+        self.prepare_create(&mut payload).await?;
+
+        let todo = self.repo.create(id, payload, dry_run).await?;
+        // Drafts are announced when published
+        if !dry_run && !todo.draft {
+            self.events.publish(TodoEvent::Created(todo.clone()));
+        }
+        Ok(todo)
+    }
+
+    /// The id picked by the client, or a generated one
+    fn new_id(&self, requested: Option<Uuid>) -> Result<Uuid, AppError> {
+        match requested {
+            Some(id) if id.is_nil() => Err(validation_error("id must not be the nil UUID")),
+            Some(id) => Ok(id),
+            None => Ok(self.ids.generate()),
+        }
+    }
+
+    /// Sanitize and validate a new todo
+    async fn prepare_create(&self, payload: &mut CreateTodo) -> Result<(), AppError> {
+        payload.title = self.sanitizer.title(&payload.title);
+        payload.description = payload
+            .description
+            .take()
+            .map(|description| self.sanitizer.description(&description))
+            .filter(|description| !description.is_empty());
+        payload.tags = self.normalize_tags(&payload.tags)?;
+
+        if payload.draft {
+            validate_title_length(&payload.title)?;
+        } else {
+            validate_title(&payload.title)?;
+        }
+        validate_description(payload.description.as_deref())?;
+        validate_location(payload.latitude, payload.longitude, payload.radius_m)?;
+        validate_estimate(payload.estimate_minutes)?;
+        self.check_custom_fields(&mut payload.custom_fields, false)
+            .await?;
+        validate_metadata(&payload.metadata)?;
+        if payload.radius_m.is_some() && payload.latitude.is_none() {
+            return Err(validation_error("radius_m needs latitude and longitude"));
+        }
+        Ok(())
+    }
+
+    /// List every todo matching the filter, without paging
+    pub async fn list_unpaged(&self, mut query: TodoQuery) -> Result<Vec<TodoResponse>, AppError> {
+        self.prepare_query(&mut query).await?;
+        self.repo.list(&query, i64::MAX, 0).await
+    }
+
+    /// List a page of todos, optionally filtered by completion status or tag
+    pub async fn list(
+        &self,
+        mut query: TodoQuery,
+        page: Option<u32>,
+        per_page: Option<u32>,
+        count: CountMode,
+    ) -> Result<Page<TodoResponse>, AppError> {
+        let page = page.unwrap_or(1);
+        if page == 0 {
+            return Err(AppError::BadRequest(
+                "page must be greater than or equal to 1".to_string(),
+            ));
+        }
+        let per_page = per_page.unwrap_or(self.page_limits.default_per_page);
+        if per_page == 0 || per_page > self.page_limits.max_per_page {
+            return Err(AppError::BadRequest(format!(
+                "per_page must be between 1 and {} (got {})",
+                self.page_limits.max_per_page, per_page
+            )));
+        }
+
+        self.prepare_query(&mut query).await?;
+
+        let limit = i64::from(per_page);
+        let offset = i64::from(page - 1) * limit;
+        // One extra row tells whether another page follows
+        let mut data = self.repo.list(&query, limit + 1, offset).await?;
+        let has_more = data.len() as i64 > limit;
+        data.truncate(per_page as usize);
+
+        // The last page (unless it is past the end) pins the total down exactly
+        let seen = offset + data.len() as i64;
+        let (total, total_estimated) = match count {
+            _ if !has_more && (!data.is_empty() || page == 1) => (Some(seen), false),
+            CountMode::Exact => (Some(self.repo.count(&query).await?), false),
+            CountMode::Estimated => {
+                // Never report fewer todos than this page proves exist
+                let at_least = if data.is_empty() {
+                    0
+                } else {
+                    seen + i64::from(has_more)
+                };
+                let estimate = self.repo.estimate_count(&query).await?;
+                (Some(estimate.max(at_least)), true)
+            }
+            CountMode::None => (None, false),
+        };
+
+        Ok(Page {
+            data,
+            meta: PageMeta {
+                page,
+                per_page,
+                total,
+                total_pages: total.map(|total| (total + limit - 1) / limit),
+                total_estimated,
+                has_more,
+                quota_warnings: Vec::new(),
+            },
+        })
+    }
+
+    /// Every open and completed todo, newest first (for sync clients that
+    /// need the whole collection)
+    pub async fn list_all(&self) -> Result<Vec<TodoResponse>, AppError> {
+        self.repo.list(&TodoQuery::default(), i64::MAX, 0).await
+    }
+
+    /// Open todos to be reminded of around a point: those whose location,
+    /// widened by its geofence, lies within `radius_m`
+    pub async fn nearby(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        radius_m: i32,
+    ) -> Result<Vec<NearbyTodo>, AppError> {
+        validate_location(Some(latitude), Some(longitude), None)?;
+        if !(1..=MAX_NEARBY_RADIUS_M).contains(&radius_m) {
+            return Err(AppError::BadRequest(format!(
+                "radius must be between 1 and {} meters",
+                MAX_NEARBY_RADIUS_M
+            )));
+        }
+        self.repo
+            .nearby(latitude, longitude, radius_m, NEARBY_TODOS)
+            .await
+    }
+
+    /// A todo with the live todos linked to it
+    pub async fn detail(&self, id: Uuid) -> Result<TodoDetail, AppError> {
+        let todo = self.repo.get(id).await?;
+        let related = self.repo.related(id).await?;
+        Ok(TodoDetail { todo, related })
+    }
+
+    /// Link a todo to another live todo
+    pub async fn link(&self, id: Uuid, relation: CreateRelation) -> Result<Relation, AppError> {
+        if id == relation.related_id {
+            return Err(AppError::BadRequest(
+                "A todo cannot be linked to itself".to_string(),
+            ));
+        }
+        self.repo.get(id).await?;
+        self.repo.get(relation.related_id).await?;
+        self.repo
+            .create_relation(self.ids.generate(), id, &relation)
+            .await
+    }
+
+    /// Remove the links between two todos (only those of `kind` if given)
+    pub async fn unlink(
+        &self,
+        id: Uuid,
+        related_id: Uuid,
+        kind: Option<RelationKind>,
+    ) -> Result<(), AppError> {
+        let removed = self.repo.delete_relations(id, related_id, kind).await?;
+        if removed == 0 {
+            return Err(AppError::NotFound(format!(
+                "Todo {} is not linked to {}",
+                id, related_id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Take an edit lock on a todo, or extend one `holder` already has;
+    /// Locked while someone else's lock hasn't expired
+    pub async fn lock(&self, id: Uuid, request: LockTodo) -> Result<TodoLock, AppError> {
+        let holder = validate_lock_holder(&request.holder)?;
+        let ttl = request.ttl_secs.unwrap_or(DEFAULT_LOCK_SECS);
+        if !(1..=MAX_LOCK_SECS).contains(&ttl) {
+            return Err(validation_error(&format!(
+                "ttl_secs must be between 1 and {}",
+                MAX_LOCK_SECS
+            )));
+        }
+
+        self.repo.get(id).await?;
+        let expires_at = self.clock.now() + Duration::seconds(i64::from(ttl));
+        self.repo.lock(id, &holder, expires_at).await
+    }
+
+    /// Release `holder`'s edit lock; releasing a lock that is already gone
+    /// succeeds
+    pub async fn unlock(&self, id: Uuid, request: UnlockTodo) -> Result<(), AppError> {
+        let holder = validate_lock_holder(&request.holder)?;
+        self.repo.unlock(id, &holder).await
+    }
+
+    /// Get a single todo
+    pub async fn get(&self, id: Uuid) -> Result<TodoResponse, AppError> {
+        self.repo.get(id).await
+    }
+
+    /// Apply a partial update after sanitizing and validating the provided
+    /// fields; with `dry_run` the updated todo is returned but not saved.
+    /// Writes to a todo fail with Locked while someone other than `holder`
+    /// has an unexpired edit lock on it.
+    pub async fn update(
+        &self,
+        id: Uuid,
+        mut payload: UpdateTodo,
+        holder: Option<&str>,
+        dry_run: bool,
+    ) -> Result<TodoResponse, AppError> {
+        // Only drafts may have their title cleared
+        let draft = payload
+            .title
+            .as_deref()
+            .is_some_and(|title| title.trim().is_empty())
+            && self.repo.get(id).await?.draft;
+        self.prepare_update(&mut payload, draft).await?;
+
+        let todo = self.repo.update(id, payload, holder, dry_run).await?;
+        if !dry_run {
+            self.publish_updated(todo.clone());
+        }
+        Ok(todo)
+    }
+
+    /// Announce a written todo as updated, and as completed too when this
+    /// write completed it; drafts are announced once published
+    fn publish_updated(&self, todo: TodoResponse) {
+        if todo.draft {
+            return;
+        }
+        if todo.just_completed() {
+            self.events.publish(TodoEvent::Updated(todo.clone()));
+            self.events.publish(TodoEvent::Completed(todo));
+        } else {
+            self.events.publish(TodoEvent::Updated(todo));
+        }
+    }
+
+    /// Soft-delete a todo; it can be brought back with `restore`
+    pub async fn delete(&self, id: Uuid, holder: Option<&str>) -> Result<(), AppError> {
+        self.repo.delete(id, holder).await?;
+        self.events.publish(TodoEvent::Deleted { id });
+        Ok(())
+    }
+
+    /// Restore a soft-deleted todo; fails with DuplicateTodo when an open
+    /// todo with the same title was created in the meantime
+    pub async fn restore(&self, id: Uuid) -> Result<TodoResponse, AppError> {
+        let todo = self.repo.restore(id).await?;
+        self.events.publish(TodoEvent::Restored(todo.clone()));
+        Ok(todo)
+    }
+
+    /// Merge the duplicate `other_id` into `id`: descriptions are concatenated,
+    /// the earliest created_at is kept and `other_id` is tombstoned
+    pub async fn merge(
+        &self,
+        id: Uuid,
+        other_id: Uuid,
+        holder: Option<&str>,
+    ) -> Result<TodoResponse, AppError> {
+        if id == other_id {
+            return Err(AppError::BadRequest(
+                "A todo cannot be merged into itself".to_string(),
+            ));
+        }
+
+        let (survivor, merged) = (self.repo.get(id).await?, self.repo.get(other_id).await?);
+        // Descriptions are joined with a blank line ("\n\n")
+        let combined_length = match (&survivor.description, &merged.description) {
+            (Some(a), Some(b)) => a.chars().count() + 2 + b.chars().count(),
+            (a, b) => a.as_ref().or(b.as_ref()).map_or(0, |d| d.chars().count()),
+        };
+        if combined_length > MAX_DESCRIPTION_LENGTH {
+            return Err(validation_error(&format!(
+                "merged description must not be more than {} characters",
+                MAX_DESCRIPTION_LENGTH
+            )));
+        }
+
+        let todo = self.repo.merge(id, other_id, holder).await?;
+        self.events.publish(TodoEvent::Merged {
+            todo: todo.clone(),
+            merged_id: other_id,
+        });
+        Ok(todo)
+    }
+
+    /// Mark a todo as completed; an already completed todo is returned
+    /// unchanged and announced only once
+    pub async fn mark_completed(
+        &self,
+        id: Uuid,
+        holder: Option<&str>,
+    ) -> Result<TodoResponse, AppError> {
+        let (todo, completed) = self.repo.mark_completed(id, holder).await?;
+        if completed && !todo.draft {
+            self.events.publish(TodoEvent::Completed(todo.clone()));
+        }
+        Ok(todo)
+    }
+
+    /// Publish an Activated event for every open todo whose start date fell
+    /// in `(after, until]`, returning how many there were
+    pub async fn activate_started(
+        &self,
+        after: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<usize, AppError> {
+        let todos = self.repo.started_between(after, until).await?;
+        let activated = todos.len();
+        for todo in todos {
+            self.events.publish(TodoEvent::Activated(todo));
+        }
+        Ok(activated)
+    }
+
+    /// Publish a draft once it has everything a todo needs (a title no other
+    /// open todo has); it then shows up in lists and is announced as created
+    pub async fn publish(&self, id: Uuid, holder: Option<&str>) -> Result<TodoResponse, AppError> {
+        let draft = self.repo.get(id).await?;
+        if !draft.draft {
+            return Err(AppError::Conflict(format!(
+                "Todo {} is already published",
+                id
+            )));
+        }
+        validate_title(&draft.title)?;
+
+        let todo = self.repo.publish(id, holder).await?;
+        self.events.publish(TodoEvent::Created(todo.clone()));
+        Ok(todo)
+    }
+
+    /// Add or remove a tag on every todo matching the filter in one statement;
+    /// with `dry_run` only the number of todos that would change is reported.
+    /// Todos someone other than `holder` has locked are skipped and counted;
+    /// todos with `MAX_TAGS` tags get no more and are listed.
+    pub async fn bulk_tag(
+        &self,
+        request: BulkTagRequest,
+        holder: Option<&str>,
+        dry_run: bool,
+    ) -> Result<BulkResult, AppError> {
+        let tag = self.sanitizer.tag(&request.tag);
+        validate_tag(&tag)?;
+        let mut filter = request.filter;
+        self.prepare_query(&mut filter).await?;
+
+        let (todos, locked, at_tag_limit) = self
+            .repo
+            .bulk_tag(&filter, request.action, &tag, holder, dry_run)
+            .await?;
+        let affected = todos.len() as u64;
+        if !dry_run {
+            for todo in todos {
+                self.events.publish(TodoEvent::Updated(todo));
+            }
+        }
+
+        Ok(BulkResult {
+            affected,
+            locked,
+            at_tag_limit,
+            dry_run,
+        })
+    }
+
+    /// Apply a partial update to every todo matching the filter in one
+    /// statement. Titles can't be set in bulk (open titles are unique). When
+    /// more todos match than the confirmation threshold, `confirm` has to
+    /// equal the number of matches; dry runs need no confirmation.
+    pub async fn bulk_update(
+        &self,
+        mut filter: TodoQuery,
+        mut payload: UpdateTodo,
+        confirm: Option<u64>,
+        holder: Option<&str>,
+        dry_run: bool,
+    ) -> Result<BulkResult, AppError> {
+        if payload.title.is_some() {
+            return Err(AppError::BadRequest(
+                "title can't be set in bulk: open todos must have unique titles".to_string(),
+            ));
+        }
+        if payload.is_empty() {
+            return Err(AppError::BadRequest(
+                "A bulk update has to set at least one field".to_string(),
+            ));
+        }
+        self.prepare_update(&mut payload, false).await?;
+        self.prepare_query(&mut filter).await?;
+
+        if !dry_run {
+            let matched = self.repo.count(&filter).await?.max(0) as u64;
+            if matched > self.bulk_confirm_threshold && confirm != Some(matched) {
+                return Err(AppError::ConfirmationRequired { matched });
+            }
+        }
+
+        let (todos, locked) = self
+            .repo
+            .bulk_update(&filter, payload, holder, dry_run)
+            .await?;
+        let affected = todos.len() as u64;
+        if !dry_run {
+            for todo in todos {
+                self.publish_updated(todo);
+            }
+        }
+
+        Ok(BulkResult {
+            affected,
+            locked,
+            at_tag_limit: Vec::new(),
+            dry_run,
+        })
+    }
+
+    /// Set how clients render `tag`: an emoji icon and a hex color. The tag
+    /// doesn't need to be in use yet.
+    pub async fn set_tag_style(
+        &self,
+        tag: &str,
+        request: SetTagStyle,
+    ) -> Result<TagStyle, AppError> {
+        let tag = self.sanitizer.tag(tag);
+        validate_tag(&tag)?;
+        let icon = request
+            .icon
+            .filter(|icon| !icon.trim().is_empty())
+            .map(|icon| validate_icon(&icon))
+            .transpose()?;
+        let color = request
+            .color
+            .filter(|color| !color.trim().is_empty())
+            .map(|color| normalize_color(&color))
+            .transpose()?;
+
+        self.repo
+            .set_tag_style(&tag, icon.as_deref(), color.as_deref())
+            .await
+    }
+
+    /// Tags starting with `prefix`, most used first, for typeahead
+    pub async fn tag_suggestions(
+        &self,
+        prefix: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<Vec<TagUsage>, AppError> {
+        let limit = limit.unwrap_or(DEFAULT_TAG_SUGGESTIONS);
+        if limit == 0 || limit > MAX_TAG_SUGGESTIONS {
+            return Err(AppError::BadRequest(format!(
+                "limit must be between 1 and {} (got {})",
+                MAX_TAG_SUGGESTIONS, limit
+            )));
+        }
+        let prefix = prefix
+            .map(|prefix| self.sanitizer.tag(&prefix))
+            .unwrap_or_default();
+
+        self.repo.tag_usage(&prefix, i64::from(limit)).await
+    }
+
+    /// Group the todos matching `query` and compute `metric` per group
+    pub async fn aggregate(
+        &self,
+        mut query: TodoQuery,
+        group_by: GroupBy,
+        metric: Metric,
+    ) -> Result<Aggregate, AppError> {
+        self.prepare_query(&mut query).await?;
+        let buckets = self.repo.aggregate(&query, group_by, metric).await?;
+        Ok(Aggregate {
+            group_by,
+            metric,
+            buckets,
+        })
+    }
+
+    /// Estimated minutes of open todos per due day, from `from` (default
+    /// today, UTC) to `to` (default two weeks later), flagging days whose
+    /// total exceeds the daily capacity
+    pub async fn workload(
+        &self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Workload, AppError> {
+        let from = from.unwrap_or_else(|| self.clock.now().date_naive());
+        let to = to.unwrap_or(from + Days::new(DEFAULT_WORKLOAD_DAYS - 1));
+        let span = (to - from).num_days() + 1;
+        if !(1..=MAX_WORKLOAD_DAYS).contains(&span) {
+            return Err(AppError::BadRequest(format!(
+                "to must be on or after from, at most {} days later",
+                MAX_WORKLOAD_DAYS - 1
+            )));
+        }
+
+        let mut due = self.repo.workload(from, to).await?.into_iter().peekable();
+        let mut days = Vec::new();
+        let mut warnings = Vec::new();
+        for date in from.iter_days().take_while(|date| *date <= to) {
+            let mut day = match due.next_if(|day| day.date == date) {
+                Some(day) => day,
+                None => WorkloadDay {
+                    date,
+                    estimate_minutes: 0,
+                    todos: 0,
+                    unestimated: 0,
+                    over_capacity: false,
+                },
+            };
+            day.over_capacity = day.estimate_minutes > i64::from(self.workload_capacity);
+            if day.over_capacity {
+                warnings.push(format!(
+                    "{}: {} minutes estimated, capacity is {}",
+                    date, day.estimate_minutes, self.workload_capacity
+                ));
+            }
+            days.push(day);
+        }
+
+        Ok(Workload {
+            capacity_minutes: self.workload_capacity,
+            days,
+            warnings,
+        })
+    }
+
+    /// Todos due on each day from `from` to `to` (UTC, inclusive), the
+    /// current month by default; every day of the range is listed
+    pub async fn calendar(
+        &self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Calendar, AppError> {
+        let today = self.clock.now().date_naive();
+        let from = from.unwrap_or_else(|| today.with_day(1).unwrap_or(today));
+        let to = to.unwrap_or_else(|| {
+            (from + Months::new(1))
+                .with_day(1)
+                .map_or(from, |next| next - Days::new(1))
+        });
+        let span = (to - from).num_days() + 1;
+        if !(1..=MAX_CALENDAR_DAYS).contains(&span) {
+            return Err(AppError::BadRequest(format!(
+                "to must be on or after from, at most {} days later",
+                MAX_CALENDAR_DAYS - 1
+            )));
+        }
+
+        let mut due = self
+            .repo
+            .due_between(from, to)
+            .await?
+            .into_iter()
+            .peekable();
+        let mut days = Vec::new();
+        for date in from.iter_days().take_while(|date| *date <= to) {
+            let mut todos = Vec::new();
+            while let Some(todo) =
+                due.next_if(|todo| todo.due_date.map(|due| due.date_naive()) == Some(date))
+            {
+                todos.push(todo);
+            }
+            days.push(CalendarDay { date, todos });
+        }
+
+        Ok(Calendar { from, to, days })
+    }
+
+    /// When any todo last changed, which bounds when every todo response did
+    pub async fn last_modified(&self) -> Result<Option<DateTime<Utc>>, AppError> {
+        self.repo.last_modified().await
+    }
+
+    /// Open todos not updated for `inactive_for` (e.g. `60d`, 30 days by
+    /// default), least recently touched first
+    pub async fn stale(
+        &self,
+        inactive_for: Option<&str>,
+        limit: Option<u32>,
+    ) -> Result<Vec<TodoResponse>, AppError> {
+        let days = match inactive_for {
+            Some(span) => parse_days(span)?,
+            None => DEFAULT_STALE_DAYS,
+        };
+        if days == 0 || days > MAX_STALE_DAYS {
+            return Err(AppError::BadRequest(format!(
+                "inactive_for must be between 1d and {}d (got {}d)",
+                MAX_STALE_DAYS, days
+            )));
+        }
+        let limit = limit.unwrap_or(DEFAULT_STALE_LIMIT);
+        if limit == 0 || limit > MAX_STALE_LIMIT {
+            return Err(AppError::BadRequest(format!(
+                "limit must be between 1 and {} (got {})",
+                MAX_STALE_LIMIT, limit
+            )));
+        }
+
+        let before = self.clock.now() - Duration::days(i64::from(days));
+        self.repo.stale(before, i64::from(limit)).await
+    }
+
+    /// Review of an ISO week such as `2024-W23` (the current week by
+    /// default): what got done, what was carried over, how much came in,
+    /// and which todos have gone stale as of the week's end
+    pub async fn weekly_review(&self, week: Option<&str>) -> Result<WeeklyReview, AppError> {
+        let monday = match week {
+            Some(week) => parse_iso_week(week)?,
+            None => {
+                let today = self.clock.now().date_naive();
+                today - Days::new(u64::from(today.weekday().num_days_from_monday()))
+            }
+        };
+        let sunday = monday + Days::new(6);
+        let from = monday.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+        let to = from + Duration::days(7);
+        let stale_before = to.min(self.clock.now()) - Duration::days(i64::from(DEFAULT_STALE_DAYS));
+
+        let completed = self.repo.completed_between(from, to, REVIEW_LIMIT).await?;
+        let carried_over = self.repo.carried_over(from, to, REVIEW_LIMIT).await?;
+        let created = self.repo.count_created_between(from, to).await?;
+        let stale = self
+            .repo
+            .stale(stale_before, REVIEW_STALE_SUGGESTIONS)
+            .await?;
+
+        let iso = monday.iso_week();
+        Ok(WeeklyReview {
+            week: format!("{}-W{:02}", iso.year(), iso.week()),
+            from: monday,
+            to: sunday,
+            completed,
+            carried_over,
+            created,
+            stale,
+        })
+    }
+
+    /// Completion streaks, from the cache unless it is missing or from an
+    /// earlier day
+    pub async fn streaks(&self) -> Result<Arc<Streaks>, AppError> {
+        if !self.streaks_outdated() {
+            if let Some(streaks) = self.streaks.read().unwrap().clone() {
+                return Ok(streaks);
+            }
+        }
+        self.refresh_streaks().await
+    }
+
+    /// Recompute the streaks and replace the cached ones
+    pub async fn refresh_streaks(&self) -> Result<Arc<Streaks>, AppError> {
+        let days = self.repo.completion_days().await?;
+        let streaks = Arc::new(stats::compute_streaks(&days, self.clock.now()));
+        *self.streaks.write().unwrap() = Some(streaks.clone());
+        Ok(streaks)
+    }
+
+    /// Whether the cached streaks are missing or were computed on another day
+    pub fn streaks_outdated(&self) -> bool {
+        self.streaks
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_none_or(|streaks| streaks.computed_at.date_naive() != self.clock.now().date_naive())
+    }
+
+    /// Most recently completed todos, optionally limited to one tag
+    pub async fn recently_completed(
+        &self,
+        tag: Option<String>,
+    ) -> Result<Vec<TodoResponse>, AppError> {
+        let tag = tag.map(|tag| self.sanitizer.tag(&tag));
+        self.repo
+            .recently_completed(tag.as_deref(), FEED_ENTRIES)
+            .await
+    }
+
+    /// Import todos parsed from another service's export. Entries that are
+    /// invalid or collide with an open todo are skipped and reported; with
+    /// `dry_run` the whole import is rolled back after being checked.
+    pub async fn import(
+        &self,
+        provider: &str,
+        items: Vec<ImportItem>,
+        dry_run: bool,
+    ) -> Result<ImportReport, AppError> {
+        if items.len() > MAX_IMPORT_ITEMS {
+            return Err(AppError::BadRequest(format!(
+                "An import may contain at most {} todos (got {})",
+                MAX_IMPORT_ITEMS,
+                items.len()
+            )));
+        }
+
+        let total = items.len();
+        let mut issues = Vec::new();
+        let mut positions = Vec::new();
+        let mut todos = Vec::new();
+        for (index, item) in items.into_iter().enumerate() {
+            let result = item
+                .map_err(|skipped| (Some(skipped.title), skipped.reason))
+                .and_then(|todo| {
+                    let title = todo.title.clone();
+                    self.prepare_import(todo)
+                        .map_err(|e| (Some(title), issue_reason(e)))
+                });
+            match result {
+                Ok(todo) => {
+                    positions.push((index + 1, todo.title.clone()));
+                    todos.push((self.ids.generate(), todo));
+                }
+                Err((title, reason)) => issues.push(ImportIssue {
+                    item: index + 1,
+                    title: title.filter(|title| !title.trim().is_empty()),
+                    reason,
+                }),
+            }
+        }
+
+        let mut imported = 0;
+        for ((item, title), result) in positions
+            .into_iter()
+            .zip(self.repo.import(todos, dry_run).await?)
+        {
+            match result {
+                Ok(todo) => {
+                    imported += 1;
+                    if !dry_run {
+                        self.events.publish(TodoEvent::Created(todo));
+                    }
+                }
+                Err(e) => issues.push(ImportIssue {
+                    item,
+                    title: Some(title),
+                    reason: issue_reason(e),
+                }),
+            }
+        }
+        issues.sort_by_key(|issue| issue.item);
+
+        Ok(ImportReport {
+            provider: provider.to_string(),
+            dry_run,
+            total,
+            imported,
+            skipped: total - imported,
+            issues,
+        })
+    }
+
+    /// Apply the same sanitizing and validation as `create` to an imported todo
+    fn prepare_import(&self, mut todo: ImportedTodo) -> Result<ImportedTodo, AppError> {
+        todo.title = self.sanitizer.title(&todo.title);
+        todo.description = todo
+            .description
+            .map(|description| self.sanitizer.description(&description))
+            .filter(|description| !description.is_empty());
+        todo.tags = self.normalize_tags(&todo.tags)?;
+
+        validate_title(&todo.title)?;
+        validate_description(todo.description.as_deref())?;
+        Ok(todo)
+    }
+
+    /// Dump every todo (including deleted and merged ones) into a versioned archive
+    pub async fn create_backup(&self) -> Result<Backup, AppError> {
+        let todos = self.repo.dump().await?;
+        Ok(Backup {
+            format: BACKUP_FORMAT.to_string(),
+            version: BACKUP_VERSION,
+            created_at: self.clock.now(),
+            todos,
+        })
+    }
+
+    /// Load an archive produced by `create_backup` into an empty database.
+    /// Rows are restored verbatim, so no events are published.
+    pub async fn restore_backup(&self, backup: Backup) -> Result<RestoreReport, AppError> {
+        if backup.format != BACKUP_FORMAT {
+            return Err(AppError::BadRequest(format!(
+                "Not a backup archive (format '{}')",
+                backup.format
+            )));
+        }
+        if backup.version == 0 || backup.version > BACKUP_VERSION {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported backup version {} (this build reads versions 1 to {})",
+                backup.version, BACKUP_VERSION
+            )));
+        }
+
+        let todos = self.repo.load_dump(&backup.todos).await?;
+        Ok(RestoreReport {
+            version: backup.version,
+            todos,
+        })
+    }
+
+    /// Latest occurrences of a polling trigger, newest first, optionally
+    /// limited to one tag
+    pub async fn trigger_items(
+        &self,
+        trigger: Trigger,
+        tag: Option<String>,
+    ) -> Result<Vec<TriggerItem>, AppError> {
+        let tag = tag.map(|tag| self.sanitizer.tag(&tag));
+        let todos = match trigger {
+            Trigger::NewTodo => {
+                let query = TodoQuery {
+                    tag,
+                    ..TodoQuery::default()
+                };
+                self.repo.list(&query, TRIGGER_ITEMS, 0).await?
+            }
+            Trigger::CompletedTodo => {
+                self.repo
+                    .recently_completed(tag.as_deref(), TRIGGER_ITEMS)
+                    .await?
+            }
+        };
+
+        let mut items: Vec<TriggerItem> = todos
+            .into_iter()
+            .map(|todo| TriggerItem::new(trigger, todo))
+            .collect();
+        newest_first(&mut items);
+        Ok(items)
+    }
+
+    /// Register a REST hook; only http(s) targets on public addresses are
+    /// accepted
+    pub async fn subscribe_hook(
+        &self,
+        payload: SubscribeHook,
+    ) -> Result<HookSubscription, AppError> {
+        let target_url = validate_url("target_url", &payload.target_url)?;
+        check_resolved("target_url", &target_url).await?;
+        self.repo
+            .create_hook(self.ids.generate(), payload.event, &target_url)
+            .await
+    }
+
+    /// Remove a REST hook
+    pub async fn unsubscribe_hook(&self, id: Uuid) -> Result<(), AppError> {
+        self.repo.delete_hook(id).await
+    }
+
+    /// REST hooks subscribed to `trigger`
+    pub async fn hooks(&self, trigger: Trigger) -> Result<Vec<HookSubscription>, AppError> {
+        self.repo.hooks(trigger).await
+    }
+
+    /// Create an automation rule after validating it
+    pub async fn create_rule(&self, mut payload: CreateRule) -> Result<Rule, AppError> {
+        payload.name = self.sanitizer.title(&payload.name);
+        payload.conditions = self.normalize_conditions(payload.conditions)?;
+        payload.actions = self.normalize_actions(payload.actions)?;
+        validate_rule_name(&payload.name)?;
+        check_webhooks(&payload.actions).await?;
+
+        self.repo.create_rule(self.ids.generate(), &payload).await
+    }
+
+    /// All rules, oldest first
+    pub async fn list_rules(&self) -> Result<Vec<Rule>, AppError> {
+        self.repo.list_rules().await
+    }
+
+    pub async fn get_rule(&self, id: Uuid) -> Result<Rule, AppError> {
+        self.repo.get_rule(id).await
+    }
+
+    /// Change the provided fields of a rule
+    pub async fn update_rule(&self, id: Uuid, payload: UpdateRule) -> Result<Rule, AppError> {
+        let mut rule = self.repo.get_rule(id).await?;
+        if let Some(name) = payload.name {
+            rule.name = self.sanitizer.title(&name);
+            validate_rule_name(&rule.name)?;
+        }
+        if let Some(trigger) = payload.trigger {
+            rule.trigger = trigger;
+        }
+        if let Some(conditions) = payload.conditions {
+            rule.conditions = self.normalize_conditions(conditions)?;
+        }
+        if let Some(actions) = payload.actions {
+            rule.actions = self.normalize_actions(actions)?;
+            check_webhooks(&rule.actions).await?;
+        }
+        if let Some(enabled) = payload.enabled {
+            rule.enabled = enabled;
+        }
+
+        self.repo.update_rule(&rule).await
+    }
+
+    /// Delete a rule along with its execution log
+    pub async fn delete_rule(&self, id: Uuid) -> Result<(), AppError> {
+        self.repo.delete_rule(id).await
+    }
+
+    /// Latest executions of a rule, newest first
+    pub async fn rule_runs(&self, id: Uuid) -> Result<Vec<RuleRun>, AppError> {
+        self.repo.get_rule(id).await?;
+        self.repo.rule_runs(id, RULE_RUNS).await
+    }
+
+    /// Enabled rules run for `trigger`, in order
+    pub async fn rules_for(&self, trigger: RuleTrigger) -> Result<Vec<Rule>, AppError> {
+        self.repo.enabled_rules(trigger).await
+    }
+
+    /// Log the outcome of a rule run; `error` is None when it succeeded
+    pub async fn record_rule_run(
+        &self,
+        rule_id: Uuid,
+        todo_id: Uuid,
+        error: Option<String>,
+    ) -> Result<(), AppError> {
+        self.repo
+            .insert_rule_run(self.ids.generate(), rule_id, todo_id, error.as_deref())
+            .await
+    }
+
+    fn normalize_conditions(&self, conditions: Vec<Condition>) -> Result<Vec<Condition>, AppError> {
+        if conditions.len() > MAX_RULE_STEPS {
+            return Err(validation_error(&format!(
+                "a rule must not have more than {} conditions",
+                MAX_RULE_STEPS
+            )));
+        }
+        conditions
+            .into_iter()
+            .map(|condition| match condition {
+                Condition::HasTag { tag } => {
+                    let tag = self.sanitizer.tag(&tag);
+                    validate_tag(&tag)?;
+                    Ok(Condition::HasTag { tag })
+                }
+                Condition::TitleContains { text } => {
+                    let text = self.sanitizer.title(&text);
+                    if text.is_empty() {
+                        return Err(validation_error("title_contains text must not be empty"));
+                    }
+                    Ok(Condition::TitleContains { text })
+                }
+            })
+            .collect()
+    }
+
+    fn normalize_actions(&self, actions: Vec<Action>) -> Result<Vec<Action>, AppError> {
+        if actions.is_empty() || actions.len() > MAX_RULE_STEPS {
+            return Err(validation_error(&format!(
+                "a rule must have between 1 and {} actions",
+                MAX_RULE_STEPS
+            )));
+        }
+        actions
+            .into_iter()
+            .map(|action| match action {
+                Action::AddTag { tag } => {
+                    let tag = self.sanitizer.tag(&tag);
+                    validate_tag(&tag)?;
+                    Ok(Action::AddTag { tag })
+                }
+                Action::RemoveTag { tag } => Ok(Action::RemoveTag {
+                    tag: self.sanitizer.tag(&tag),
+                }),
+                Action::Webhook { url } => Ok(Action::Webhook {
+                    url: validate_url("url", &url)?,
+                }),
+                action => Ok(action),
+            })
+            .collect()
+    }
+
+    /// Sanitize, deduplicate and validate a list of tags
+    fn normalize_tags(&self, tags: &[String]) -> Result<Vec<String>, AppError> {
+        let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let tag = self.sanitizer.tag(tag);
+            validate_tag(&tag)?;
+            if !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+
+        if normalized.len() > MAX_TAGS {
+            return Err(validation_error(&format!(
+                "a todo must not have more than {} tags",
+                MAX_TAGS
+            )));
+        }
+        Ok(normalized)
+    }
+
+    /// Define a custom field todos can then carry
+    pub async fn create_custom_field(
+        &self,
+        mut payload: CreateCustomField,
+    ) -> Result<CustomField, AppError> {
+        payload.name = payload.name.trim().to_lowercase();
+        validate_field_name(&payload.name)?;
+        payload.options = payload
+            .options
+            .iter()
+            .map(|option| self.sanitizer.title(option))
+            .collect();
+        match payload.field_type {
+            FieldType::Select if payload.options.is_empty() => {
+                return Err(validation_error("a select field needs options"))
+            }
+            FieldType::Select => {
+                for option in &payload.options {
+                    if option.is_empty() || option.chars().count() > MAX_TAG_LENGTH {
+                        return Err(validation_error(&format!(
+                            "options must be between 1 and {} characters",
+                            MAX_TAG_LENGTH
+                        )));
+                    }
+                }
+            }
+            _ if !payload.options.is_empty() => {
+                return Err(validation_error("only select fields have options"))
+            }
+            _ => {}
+        }
+        self.repo
+            .create_custom_field(self.ids.generate(), &payload)
+            .await
+    }
+
+    pub async fn custom_fields(&self) -> Result<Vec<CustomField>, AppError> {
+        self.repo.custom_fields().await
+    }
+
+    /// Delete a custom field definition and its value on every todo
+    pub async fn delete_custom_field(&self, id: Uuid) -> Result<(), AppError> {
+        self.repo.delete_custom_field(id).await
+    }
+
+    /// Check custom field values against their definitions, dropping nulls
+    /// unless `keep_nulls` (a null in an update removes the field)
+    async fn check_custom_fields(
+        &self,
+        values: &mut Map<String, Value>,
+        keep_nulls: bool,
+    ) -> Result<(), AppError> {
+        if !keep_nulls {
+            values.retain(|_, value| !value.is_null());
+        }
+        if values.is_empty() {
+            return Ok(());
+        }
+        let fields = self.repo.custom_fields().await?;
+        for (name, value) in values.iter() {
+            let field = fields
+                .iter()
+                .find(|field| field.name == *name)
+                .ok_or_else(|| validation_error(&format!("unknown custom field '{}'", name)))?;
+            if !value.is_null() {
+                field.check(value).map_err(|e| validation_error(&e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sanitize and validate the fields an update sets; `draft` allows an
+    /// empty title
+    async fn prepare_update(&self, payload: &mut UpdateTodo, draft: bool) -> Result<(), AppError> {
+        payload.title = payload
+            .title
+            .take()
+            .map(|title| self.sanitizer.title(&title));
+        payload.description = payload
+            .description
+            .take()
+            .map(|description| self.sanitizer.description(&description));
+        payload.tags = payload
+            .tags
+            .take()
+            .map(|tags| self.normalize_tags(&tags))
+            .transpose()?;
+
+        match &payload.title {
+            Some(title) if draft => validate_title_length(title)?,
+            Some(title) => validate_title(title)?,
+            None => {}
+        }
+        validate_description(payload.description.as_deref())?;
+        validate_location(payload.latitude, payload.longitude, payload.radius_m)?;
+        validate_estimate(payload.estimate_minutes)?;
+        if let Some(custom_fields) = payload.custom_fields.as_mut() {
+            self.check_custom_fields(custom_fields, true).await?;
+        }
+        if let Some(metadata) = &payload.metadata {
+            validate_metadata(metadata)?;
+        }
+        Ok(())
+    }
+
+    /// Normalize a filter: sanitize the tag and resolve the `name:value`
+    /// custom field filter against its definition
+    async fn prepare_query(&self, query: &mut TodoQuery) -> Result<(), AppError> {
+        query.tag = query.tag.take().map(|tag| self.sanitizer.tag(&tag));
+        query.custom_fields = None;
+        let Some(filter) = query.field.as_deref() else {
+            return Ok(());
+        };
+
+        let (name, raw) = filter
+            .split_once(':')
+            .ok_or_else(|| AppError::BadRequest("field must look like name:value".to_string()))?;
+        let name = name.trim().to_lowercase();
+        let field = self
+            .repo
+            .custom_fields()
+            .await?
+            .into_iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| AppError::BadRequest(format!("Unknown custom field '{}'", name)))?;
+        let value = field.parse(raw).map_err(AppError::BadRequest)?;
+        query.custom_fields = Some(Value::Object(Map::from_iter([(name, value)])));
+        Ok(())
+    }
+
+    /// Permanently delete up to `batch` todos soft-deleted before `before`,
+    /// returning how many were removed
+    pub async fn purge_deleted_todos(
+        &self,
+        before: DateTime<Utc>,
+        batch: u32,
+    ) -> Result<u64, AppError> {
+        self.repo
+            .purge_deleted_todos(before, i64::from(batch))
+            .await
+    }
+
+    /// Delete up to `batch` rule runs older than `before`
+    pub async fn purge_rule_runs(
+        &self,
+        before: DateTime<Utc>,
+        batch: u32,
+    ) -> Result<u64, AppError> {
+        self.repo.purge_rule_runs(before, i64::from(batch)).await
+    }
+
+    /// Subscribe to the events published by this service
+    pub fn events(&self) -> &Arc<dyn EventBus> {
+        &self.events
+    }
+
+    /// The clock every time-based decision of this service is made with
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+}
+
+fn validate_title(title: &str) -> Result<(), AppError> {
+    if title.trim().is_empty() {
+        return Err(validation_error("title must not be empty"));
+    }
+    validate_title_length(title)
+}
+
+/// The length limit alone, for drafts (whose title may still be empty)
+fn validate_title_length(title: &str) -> Result<(), AppError> {
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(validation_error(&format!(
+            "title must not be more than {} characters",
+            MAX_TITLE_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+fn validate_description(description: Option<&str>) -> Result<(), AppError> {
+    if let Some(description) = description {
+        if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+            return Err(validation_error(&format!(
+                "description must not be more than {} characters",
+                MAX_DESCRIPTION_LENGTH
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn validate_tag(tag: &str) -> Result<(), AppError> {
+    if tag.is_empty() {
+        return Err(validation_error("tags must not be empty"));
+    }
+    if tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(validation_error(&format!(
+            "tags must not be more than {} characters",
+            MAX_TAG_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/// The trimmed icon, which must be one emoji (a single grapheme, so flags,
+/// keycaps and ZWJ sequences count as one)
+fn validate_icon(icon: &str) -> Result<String, AppError> {
+    let icon = icon.trim();
+    let mut graphemes = icon.graphemes(true);
+    let single = graphemes.next().is_some() && graphemes.next().is_none();
+    if !single || !icon.chars().any(is_emoji) {
+        return Err(validation_error("icon must be a single emoji"));
+    }
+    Ok(icon.to_string())
+}
+
+/// Characters shown as emoji, alone or as the base of a sequence
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF
+            | 0x2600..=0x27BF
+            | 0x2300..=0x23FF
+            | 0x2B00..=0x2BFF
+            | 0x2190..=0x21FF
+            | 0x25A0..=0x25FF
+            | 0x2900..=0x297F
+            | 0x20E3
+            | 0x00A9
+            | 0x00AE
+            | 0x203C
+            | 0x2049
+            | 0x2122
+            | 0x2139
+            | 0x3030
+            | 0x303D
+            | 0x3297
+            | 0x3299
+    )
+}
+
+/// A `#rgb` or `#rrggbb` color as lowercase `#rrggbb`
+fn normalize_color(color: &str) -> Result<String, AppError> {
+    let hex = color
+        .trim()
+        .strip_prefix('#')
+        .filter(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| validation_error("color must be a hex color such as #1e90ff"))?
+        .to_ascii_lowercase();
+    let hex: String = if hex.len() == 3 {
+        hex.chars().flat_map(|c| [c, c]).collect()
+    } else {
+        hex
+    };
+    Ok(format!("#{}", hex))
+}
+
+/// Check coordinates and geofence radius; latitude and longitude must be
+/// given together
+fn validate_location(
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    radius_m: Option<i32>,
+) -> Result<(), AppError> {
+    match (latitude, longitude) {
+        (Some(latitude), Some(longitude)) => {
+            if !(-90.0..=90.0).contains(&latitude) {
+                return Err(validation_error("latitude must be between -90 and 90"));
+            }
+            if !(-180.0..=180.0).contains(&longitude) {
+                return Err(validation_error("longitude must be between -180 and 180"));
+            }
+        }
+        (None, None) => {}
+        _ => {
+            return Err(validation_error(
+                "latitude and longitude must be set together",
+            ))
+        }
+    }
+    if let Some(radius_m) = radius_m {
+        if !(1..=MAX_GEOFENCE_RADIUS_M).contains(&radius_m) {
+            return Err(validation_error(&format!(
+                "radius_m must be between 1 and {}",
+                MAX_GEOFENCE_RADIUS_M
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Field names are lowercase letters, digits and underscores, so they can
+/// be used in `name:value` filters
+fn validate_field_name(name: &str) -> Result<(), AppError> {
+    if name.is_empty() || name.chars().count() > MAX_TAG_LENGTH {
+        return Err(validation_error(&format!(
+            "field name must be between 1 and {} characters",
+            MAX_TAG_LENGTH
+        )));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        return Err(validation_error(
+            "field name may only contain letters, digits and underscores",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_metadata(metadata: &Map<String, Value>) -> Result<(), AppError> {
+    let size = serde_json::to_string(metadata)
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .len();
+    if size > MAX_METADATA_BYTES {
+        return Err(validation_error(&format!(
+            "metadata must not be more than {} bytes",
+            MAX_METADATA_BYTES
+        )));
+    }
+    Ok(())
+}
+
+fn validate_estimate(estimate_minutes: Option<i32>) -> Result<(), AppError> {
+    if let Some(estimate_minutes) = estimate_minutes {
+        if !(1..=MAX_ESTIMATE_MINUTES).contains(&estimate_minutes) {
+            return Err(validation_error(&format!(
+                "estimate_minutes must be between 1 and {}",
+                MAX_ESTIMATE_MINUTES
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Human-readable reason an import entry was skipped
+fn issue_reason(error: AppError) -> String {
+    match error {
+        AppError::BadRequest(msg) | AppError::Conflict(msg) => msg,
+        other => other.to_string(),
+    }
+}
+
+/// The trimmed holder name of an edit lock
+fn validate_lock_holder(holder: &str) -> Result<String, AppError> {
+    let holder = holder.trim();
+    if holder.is_empty() {
+        return Err(validation_error("holder must not be empty"));
+    }
+    if holder.chars().count() > MAX_LOCK_HOLDER_LENGTH {
+        return Err(validation_error(&format!(
+            "holder must not be longer than {} characters",
+            MAX_LOCK_HOLDER_LENGTH
+        )));
+    }
+    Ok(holder.to_string())
+}
+
+fn validate_rule_name(name: &str) -> Result<(), AppError> {
+    if name.is_empty() {
+        return Err(validation_error("rule name must not be empty"));
+    }
+    if name.chars().count() > MAX_TITLE_LENGTH {
+        return Err(validation_error(&format!(
+            "rule name must not be longer than {} characters",
+            MAX_TITLE_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/// A number of days written as `<n>d`, e.g. `60d`
+fn parse_days(span: &str) -> Result<u32, AppError> {
+    span.strip_suffix('d')
+        .and_then(|days| days.parse().ok())
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "inactive_for must be a number of days like 60d, got '{}'",
+                span
+            ))
+        })
+}
+
+/// The Monday starting an ISO week written as `YYYY-Www`
+fn parse_iso_week(week: &str) -> Result<NaiveDate, AppError> {
+    let invalid = || {
+        AppError::BadRequest(format!(
+            "week must be an ISO week like 2024-W23, got '{}'",
+            week
+        ))
+    };
+    let (year, number) = week.split_once("-W").ok_or_else(invalid)?;
+    let year = year.parse().map_err(|_| invalid())?;
+    let number = number.parse().map_err(|_| invalid())?;
+    NaiveDate::from_isoywd_opt(year, number, Weekday::Mon).ok_or_else(invalid)
+}
+
+/// Parse an http(s) URL the server will call, returning it normalized; hosts
+/// that are internal IP addresses or `localhost` are refused
+fn validate_url(field: &str, url: &str) -> Result<String, AppError> {
+    let url = reqwest::Url::parse(url.trim())
+        .map_err(|e| validation_error(&format!("{} is not a valid URL: {}", field, e)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(validation_error(&format!(
+            "{} must be an http or https URL",
+            field
+        )));
+    }
+    outbound::check_host(&url).map_err(|e| validation_error(&format!("{}: {}", field, e)))?;
+    Ok(url.into())
+}
+
+/// Refuse a URL validated by `validate_url` whose host resolves to an
+/// internal address
+async fn check_resolved(field: &str, url: &str) -> Result<(), AppError> {
+    let url = reqwest::Url::parse(url)
+        .map_err(|e| validation_error(&format!("{} is not a valid URL: {}", field, e)))?;
+    outbound::check_resolved(&url)
+        .await
+        .map_err(|e| validation_error(&format!("{}: {}", field, e)))
+}
+
+async fn check_webhooks(actions: &[Action]) -> Result<(), AppError> {
+    for action in actions {
+        if let Action::Webhook { url } = action {
+            check_resolved("url", url).await?;
+        }
+    }
+    Ok(())
+}
+
+fn validation_error(detail: &str) -> AppError {
+    AppError::BadRequest(format!("{}: {}", ErrorMessage::TodoValidationError, detail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::db::DbPool;
+    use crate::events::BroadcastEventBus;
+    use crate::ids::SequentialIds;
+    use crate::repository::PostgresTodoRepository;
+    use chrono::TimeZone;
+
+    /// A service and repository sharing a clock stopped at 2024-05-06 09:00
+    fn service_with_clock(pool: DbPool) -> (TodoService, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::new(
+            Utc.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap(),
+        ));
+        let repo = PostgresTodoRepository::new(pool).with_clock(clock.clone());
+        let service = TodoService::new(Arc::new(repo), Arc::new(BroadcastEventBus::default()))
+            .with_clock(clock.clone());
+        (service, clock)
+    }
+
+    fn todo(title: &str) -> CreateTodo {
+        CreateTodo {
+            title: title.to_string(),
+            ..CreateTodo::default()
+        }
+    }
+
+    #[sqlx::test]
+    async fn every_record_gets_an_id_from_the_generator(pool: DbPool) {
+        let (service, _) = service_with_clock(pool);
+        let service = service.with_id_generator(Arc::new(SequentialIds::default()));
+        let id = |n: u128| Uuid::from_u128(n);
+
+        let release = service.create(todo("Ship release"), false).await.unwrap();
+        assert_eq!(release.id, id(1));
+        let rule = service
+            .create_rule(CreateRule {
+                name: "Tag releases".to_string(),
+                trigger: RuleTrigger::Created,
+                conditions: Vec::new(),
+                actions: vec![Action::AddTag {
+                    tag: "release".to_string(),
+                }],
+                enabled: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(rule.id, id(2));
+        service
+            .record_rule_run(rule.id, release.id, None)
+            .await
+            .unwrap();
+        assert_eq!(service.rule_runs(rule.id).await.unwrap()[0].id, id(3));
+        let field = service
+            .create_custom_field(CreateCustomField {
+                name: "client".to_string(),
+                field_type: FieldType::Text,
+                options: Vec::new(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(field.id, id(4));
+        let other = service
+            .create(todo("Write changelog"), false)
+            .await
+            .unwrap();
+        let relation = service
+            .link(
+                release.id,
+                CreateRelation {
+                    related_id: other.id,
+                    kind: RelationKind::RelatesTo,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!((other.id, relation.id), (id(5), id(6)));
+    }
+
+    #[sqlx::test]
+    async fn streaks_end_after_a_day_without_completions(pool: DbPool) {
+        let (service, clock) = service_with_clock(pool);
+        let todo = service.create(todo("Stretch"), false).await.unwrap();
+        service.mark_completed(todo.id, None).await.unwrap();
+        let today = clock.now().date_naive();
+        assert_eq!(service.streaks().await.unwrap().current, 1);
+
+        // Cached until the day changes; yesterday's completion still counts
+        clock.advance(Duration::days(1));
+        assert!(service.streaks_outdated());
+        assert_eq!(service.streaks().await.unwrap().current, 1);
+
+        clock.advance(Duration::days(1));
+        let streaks = service.streaks().await.unwrap();
+        assert_eq!(streaks.current, 0);
+        assert_eq!(streaks.longest, 1);
+        assert_eq!(streaks.last_completed_on, Some(today));
+    }
+
+    #[sqlx::test]
+    async fn workload_starts_on_the_clock_day(pool: DbPool) {
+        let (service, clock) = service_with_clock(pool);
+        let due = clock.now() + Duration::days(1);
+        let payload = CreateTodo {
+            due_date: Some(due),
+            estimate_minutes: Some(30),
+            ..todo("Write report")
+        };
+        service.create(payload, false).await.unwrap();
+
+        let workload = service.workload(None, None).await.unwrap();
+        assert_eq!(workload.days[0].date, clock.now().date_naive());
+        assert_eq!(workload.days[1].estimate_minutes, 30);
+
+        clock.set(due);
+        let workload = service.workload(None, None).await.unwrap();
+        assert_eq!(workload.days[0].date, due.date_naive());
+        assert_eq!(workload.days[0].estimate_minutes, 30);
+    }
+
+    #[sqlx::test]
+    async fn todos_go_stale_as_time_passes(pool: DbPool) {
+        let (service, clock) = service_with_clock(pool);
+        let todo = service.create(todo("Renew passport"), false).await.unwrap();
+        assert!(service.stale(Some("30d"), None).await.unwrap().is_empty());
+
+        clock.advance(Duration::days(31));
+        let stale = service.stale(Some("30d"), None).await.unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].id, todo.id);
+    }
+
+    #[sqlx::test]
+    async fn edit_locks_expire(pool: DbPool) {
+        let (service, clock) = service_with_clock(pool);
+        let todo = service.create(todo("Plan offsite"), false).await.unwrap();
+        let lock = |holder: &str| LockTodo {
+            holder: holder.to_string(),
+            ttl_secs: Some(60),
+        };
+        service.lock(todo.id, lock("Alice")).await.unwrap();
+
+        clock.advance(Duration::seconds(59));
+        assert!(matches!(
+            service.lock(todo.id, lock("Bob")).await,
+            Err(AppError::Locked { ref holder, .. }) if holder == "Alice"
+        ));
+        // The holder extends its own lock
+        let extended = service.lock(todo.id, lock("Alice")).await.unwrap();
+        assert_eq!(extended.expires_at, clock.now() + Duration::seconds(60));
+
+        clock.advance(Duration::seconds(60));
+        let taken = service.lock(todo.id, lock("Bob")).await.unwrap();
+        assert_eq!(taken.holder, "Bob");
+    }
+
+    #[test]
+    fn trims_and_bounds_lock_holders() {
+        assert_eq!(validate_lock_holder("  Alice ").unwrap(), "Alice");
+        assert!(validate_lock_holder(" \t").is_err());
+        let name = "é".repeat(MAX_LOCK_HOLDER_LENGTH);
+        assert_eq!(validate_lock_holder(&name).unwrap(), name);
+        assert!(validate_lock_holder(&format!("{}x", name)).is_err());
+    }
+
+    #[test]
+    fn parses_day_spans() {
+        assert_eq!(parse_days("60d").unwrap(), 60);
+        for span in ["60", "d", "-1d", "2w", " 5d"] {
+            assert!(parse_days(span).is_err(), "{}", span);
+        }
+    }
+
+    #[test]
+    fn iso_weeks_start_on_monday() {
+        assert_eq!(
+            parse_iso_week("2024-W23").unwrap(),
+            NaiveDate::from_ymd_opt(2024, 6, 3).unwrap()
+        );
+        // Week 1 of 2025 starts in 2024
+        assert_eq!(
+            parse_iso_week("2025-W01").unwrap(),
+            NaiveDate::from_ymd_opt(2024, 12, 30).unwrap()
+        );
+        for week in ["2024-23", "2024-W54", "2023-W53", "W23", "2024-Wxx"] {
+            assert!(parse_iso_week(week).is_err(), "{}", week);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::body_log::{capture, random_fraction};
+use axum::{
+    body::Bytes,
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ts_rs::TS;
+
+/// How long the shadow target gets to answer a mirrored request
+const SHADOW_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Mismatches kept for `/admin/shadow`, newest first
+const MAX_RECENT_DIFFS: usize = 50;
+
+/// Differing JSON paths listed per mismatch
+const MAX_DIFF_PATHS: usize = 20;
+
+/// Headers not copied onto mirrored requests; the client sets its own
+const SKIPPED_HEADERS: [header::HeaderName; 3] =
+    [header::HOST, header::CONTENT_LENGTH, header::CONNECTION];
+
+/// A mirrored request whose shadow response differed from the real one
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct ShadowDiff {
+    pub at: DateTime<Utc>,
+    pub method: String,
+    /// Path and query of the request
+    pub path: String,
+    pub route: String,
+    pub status: u16,
+    /// Status of the shadow response; None when it failed
+    pub shadow_status: Option<u16>,
+    /// JSON pointers (e.g. `/data/0/title`) whose values differ, when both
+    /// bodies are JSON
+    pub paths: Vec<String>,
+    /// Why the shadow request failed
+    pub error: Option<String>,
+}
+
+/// Counters and recent mismatches of traffic shadowing
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct ShadowStatus {
+    pub base_url: String,
+    pub sample_rate: f64,
+    #[ts(type = "number")]
+    pub mirrored: u64,
+    #[ts(type = "number")]
+    pub matched: u64,
+    #[ts(type = "number")]
+    pub mismatched: u64,
+    /// Shadow requests that errored or timed out
+    #[ts(type = "number")]
+    pub failed: u64,
+    pub recent: Vec<ShadowDiff>,
+}
+
+#[derive(Default)]
+struct Counters {
+    mirrored: u64,
+    matched: u64,
+    mismatched: u64,
+    failed: u64,
+    recent: VecDeque<ShadowDiff>,
+}
+
+/// Mirrors a sample of read requests to a second deployment and records
+/// where its responses differ from ours
+pub struct Shadow {
+    base_url: String,
+    sample_rate: f64,
+    http: reqwest::Client,
+    counters: Mutex<Counters>,
+}
+
+impl Shadow {
+    pub fn new(base_url: &str, sample_rate: f64) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            sample_rate,
+            http: reqwest::Client::builder()
+                .timeout(SHADOW_TIMEOUT)
+                .build()
+                .expect("Failed to build the shadow client"),
+            counters: Mutex::new(Counters::default()),
+        }
+    }
+
+    pub fn status(&self) -> ShadowStatus {
+        let counters = self.counters.lock().unwrap();
+        ShadowStatus {
+            base_url: self.base_url.clone(),
+            sample_rate: self.sample_rate,
+            mirrored: counters.mirrored,
+            matched: counters.matched,
+            mismatched: counters.mismatched,
+            failed: counters.failed,
+            recent: counters.recent.iter().cloned().collect(),
+        }
+    }
+
+    /// Send the mirrored request and compare its response with ours
+    async fn compare(&self, request: Mirrored, status: StatusCode, body: Bytes) {
+        let shadow = async {
+            let response = self
+                .http
+                .request(
+                    request.method.clone(),
+                    format!("{}{}", self.base_url, request.path),
+                )
+                .headers(request.headers.clone())
+                .send()
+                .await?;
+            let status = response.status();
+            Ok::<_, reqwest::Error>((status, response.bytes().await?))
+        }
+        .await;
+
+        let mut diff = ShadowDiff {
+            at: Utc::now(),
+            method: request.method.to_string(),
+            path: request.path,
+            route: request.route,
+            status: status.as_u16(),
+            shadow_status: None,
+            paths: Vec::new(),
+            error: None,
+        };
+        let mut counters = self.counters.lock().unwrap();
+        counters.mirrored += 1;
+        match shadow {
+            Ok((shadow_status, shadow_body)) => {
+                diff.shadow_status = Some(shadow_status.as_u16());
+                diff.paths = body_diff(&body, &shadow_body);
+                if shadow_status == status && diff.paths.is_empty() {
+                    counters.matched += 1;
+                    return;
+                }
+                counters.mismatched += 1;
+            }
+            Err(e) => {
+                diff.error = Some(e.to_string());
+                counters.failed += 1;
+            }
+        }
+        tracing::info!(
+            route = %diff.route,
+            status = diff.status,
+            shadow_status = ?diff.shadow_status,
+            paths = ?diff.paths,
+            error = ?diff.error,
+            "Shadow response differs"
+        );
+        counters.recent.push_front(diff);
+        counters.recent.truncate(MAX_RECENT_DIFFS);
+    }
+}
+
+/// What is needed to replay a request against the shadow target
+struct Mirrored {
+    method: Method,
+    path: String,
+    route: String,
+    headers: HeaderMap,
+}
+
+/// JSON pointers where two bodies differ; bodies that aren't both JSON
+/// differ at the root unless they are byte-for-byte equal
+fn body_diff(ours: &[u8], theirs: &[u8]) -> Vec<String> {
+    let mut paths = Vec::new();
+    match (
+        serde_json::from_slice::<Value>(ours),
+        serde_json::from_slice::<Value>(theirs),
+    ) {
+        (Ok(ours), Ok(theirs)) => json_diff(&ours, &theirs, String::new(), &mut paths),
+        _ if ours != theirs => paths.push(String::new()),
+        _ => {}
+    }
+    paths
+}
+
+fn json_diff(ours: &Value, theirs: &Value, path: String, paths: &mut Vec<String>) {
+    if paths.len() >= MAX_DIFF_PATHS {
+        return;
+    }
+    match (ours, theirs) {
+        (Value::Object(ours), Value::Object(theirs)) => {
+            let mut keys: Vec<&String> = ours.keys().chain(theirs.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match (ours.get(key), theirs.get(key)) {
+                    (Some(ours), Some(theirs)) => json_diff(ours, theirs, child, paths),
+                    _ if paths.len() < MAX_DIFF_PATHS => paths.push(child),
+                    _ => return,
+                }
+            }
+        }
+        (Value::Array(ours), Value::Array(theirs)) if ours.len() == theirs.len() => {
+            for (index, (ours, theirs)) in ours.iter().zip(theirs).enumerate() {
+                json_diff(ours, theirs, format!("{}/{}", path, index), paths);
+            }
+        }
+        _ if ours != theirs => paths.push(path),
+        _ => {}
+    }
+}
+
+/// Middleware mirroring a sample of GET requests to the shadow target once
+/// they have been answered; the client only ever sees our response
+pub async fn shadow_reads(
+    State(shadow): State<Arc<Shadow>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::GET || random_fraction() >= shadow.sample_rate {
+        return next.run(request).await;
+    }
+
+    let mut headers = request.headers().clone();
+    for name in &SKIPPED_HEADERS {
+        headers.remove(name);
+    }
+    let mirrored = Mirrored {
+        method: request.method().clone(),
+        path: request
+            .uri()
+            .path_and_query()
+            .map(|path| path.as_str().to_string())
+            .unwrap_or_else(|| "/".to_string()),
+        route: request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_string())
+            .unwrap_or_else(|| "unmatched".to_string()),
+        headers,
+    };
+
+    let response = next.run(request).await;
+    let status = response.status();
+    let (parts, body) = response.into_parts();
+    let (captured, body) = capture(body).await;
+    // Bodies too large to buffer are not compared
+    if let Some(captured) = captured {
+        tokio::spawn(async move { shadow.compare(mirrored, status, captured).await });
+    }
+
+    Response::from_parts(parts, body)
+}
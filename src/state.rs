@@ -0,0 +1,23 @@
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::rate_limit::RateLimiter;
+use crate::retention::Retention;
+use crate::service::TodoService;
+use crate::shadow::Shadow;
+use crate::usage::UsageTracker;
+use axum::extract::FromRef;
+use std::sync::Arc;
+
+/// Shared application state; handlers extract the parts they need via FromRef
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pub service: Arc<TodoService>,
+    pub config: Arc<Config>,
+    pub metrics: Arc<Metrics>,
+    pub usage: Arc<UsageTracker>,
+    pub retention: Arc<Retention>,
+    /// None when rate limiting is disabled
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// None when traffic shadowing is disabled
+    pub shadow: Option<Arc<Shadow>>,
+}
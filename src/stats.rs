@@ -0,0 +1,106 @@
+use crate::service::TodoService;
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use ts_rs::TS;
+
+/// Number of weeks covered by the completion heatmap
+pub const HEATMAP_WEEKS: u64 = 52;
+
+/// Completion streaks and activity, computed over UTC days
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct Streaks {
+    /// Consecutive days with a completion, ending today or yesterday (a
+    /// streak isn't broken until a whole day passes without completions)
+    pub current: u32,
+    pub longest: u32,
+    pub last_completed_on: Option<NaiveDate>,
+    #[ts(type = "number")]
+    pub total_completed: i64,
+    /// Completions per day, one entry per week (oldest first)
+    pub heatmap: Vec<HeatmapWeek>,
+    pub computed_at: DateTime<Utc>,
+}
+
+/// One week of the heatmap; `days` holds Monday to Sunday
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct HeatmapWeek {
+    pub week_start: NaiveDate,
+    #[ts(type = "Array<number>")]
+    pub days: Vec<i64>,
+}
+
+/// Compute streaks from completion counts per day, sorted by day
+pub fn compute_streaks(days: &[(NaiveDate, i64)], now: DateTime<Utc>) -> Streaks {
+    let today = now.date_naive();
+
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for &(day, _) in days {
+        let consecutive = previous.is_some_and(|previous| previous.succ_opt() == Some(day));
+        run = if consecutive { run + 1 } else { 1 };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+    let last_completed_on = previous;
+    let streak_alive =
+        last_completed_on.is_some_and(|last| last == today || last.succ_opt() == Some(today));
+    let current = if streak_alive { run } else { 0 };
+
+    let counts: HashMap<NaiveDate, i64> = days.iter().copied().collect();
+    let this_week = today - Days::new(u64::from(today.weekday().num_days_from_monday()));
+    let first_week = this_week - Days::new((HEATMAP_WEEKS - 1) * 7);
+    let heatmap = (0..HEATMAP_WEEKS)
+        .map(|week| {
+            let week_start = first_week + Days::new(week * 7);
+            let days = (0..7)
+                .map(|day| {
+                    let date = week_start + Days::new(day);
+                    counts.get(&date).copied().unwrap_or_default()
+                })
+                .collect();
+            HeatmapWeek { week_start, days }
+        })
+        .collect();
+
+    Streaks {
+        current,
+        longest,
+        last_completed_on,
+        total_completed: days.iter().map(|&(_, count)| count).sum(),
+        heatmap,
+        computed_at: now,
+    }
+}
+
+/// Keep the cached streaks fresh: recompute every `interval` when a todo
+/// changed since the last run, or when the day rolled over
+pub fn spawn_streaks_refresh(service: Arc<TodoService>, interval: Duration) {
+    let mut events = service.events().subscribe();
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut dirty = true;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if !dirty && !service.streaks_outdated() {
+                        continue;
+                    }
+                    match service.refresh_streaks().await {
+                        Ok(_) => dirty = false,
+                        Err(e) => tracing::error!("Failed to refresh streaks: {}", e),
+                    }
+                }
+                event = events.recv() => match event {
+                    Ok(_) | Err(RecvError::Lagged(_)) => dirty = true,
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+    });
+}
@@ -0,0 +1,105 @@
+use crate::error::HttpError;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Query, Request},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::Value;
+
+pub const TIMEZONE_HEADER: &str = "x-timezone";
+
+/// Timestamp fields rewritten in the requested zone, at any depth
+const TIMESTAMP_FIELDS: &[&str] = &[
+    "created_at",
+    "updated_at",
+    "due_date",
+    "completed_at",
+    "start_date",
+    "expires_at",
+];
+
+#[derive(Debug, Deserialize)]
+struct TimezoneParams {
+    tz: Option<String>,
+}
+
+/// Middleware formatting the todo timestamps of JSON responses in the zone
+/// asked for with `?tz=Europe/Berlin` (or `X-Timezone`), for clients that
+/// can't convert from UTC themselves. The values stay RFC 3339, with the
+/// zone's offset instead of `Z`, so they denote the same instants.
+pub async fn localize_timestamps(request: Request, next: Next) -> Response {
+    let requested = Query::<TimezoneParams>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|Query(params)| params.tz)
+        .or_else(|| {
+            request
+                .headers()
+                .get(TIMEZONE_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        });
+    let Some(requested) = requested else {
+        return next.run(request).await;
+    };
+    let tz: Tz = match requested.parse() {
+        Ok(tz) => tz,
+        Err(_) => {
+            return HttpError::bad_request(format!(
+                "Unknown timezone: {} (expected an IANA name such as Europe/Berlin)",
+                requested
+            ))
+            .into_response()
+        }
+    };
+
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+        return HttpError::server_error("Failed to read the response body").into_response();
+    };
+    let Ok(mut json) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    localize(&mut json, tz);
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(json.to_string()))
+}
+
+fn localize(value: &mut Value, tz: Tz) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if TIMESTAMP_FIELDS.contains(&key.as_str()) {
+                    if let Some(at) = value
+                        .as_str()
+                        .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+                    {
+                        *value = Value::String(
+                            at.with_timezone(&tz)
+                                .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                        );
+                    }
+                } else {
+                    localize(value, tz);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| localize(item, tz)),
+        _ => {}
+    }
+}
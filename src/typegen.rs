@@ -0,0 +1,93 @@
+use crate::backup::{Backup, RestoreReport};
+use crate::build_info::VersionInfo;
+use crate::custom_fields::{CreateCustomField, CustomField};
+use crate::error::ErrorResponse;
+use crate::integrations::{HookSubscription, SubscribeHook, TriggerItem};
+use crate::models::{
+    Aggregate, BulkResult, BulkTagRequest, Calendar, CalendarDay, CountMode, CreateRelation,
+    CreateTodo, ImportReport, LockTodo, NearbyTodo, OnConflict, Page, QuotaWarning, Relation,
+    RenderedTodo, SetTagStyle, TagStyle, TagUsage, Todo, TodoDetail, TodoLock, TodoQuery,
+    UnlockTodo, UpdateTodo, WeeklyReview, Workload, WorkloadDay,
+};
+use crate::retention::RetentionStatus;
+use crate::rules::{CreateRule, Rule, RuleRun, UpdateRule};
+use crate::shadow::{ShadowDiff, ShadowStatus};
+use crate::stats::Streaks;
+use crate::usage::ApiUsage;
+use std::fs;
+use std::path::Path;
+use ts_rs::{ExportError, TS};
+
+/// Directory the TypeScript definitions are written to when none is given
+pub const DEFAULT_TYPES_DIR: &str = "bindings";
+
+/// Write TypeScript definitions for the API's request/response shapes to
+/// `out_dir`, one `.ts` file per type plus an `index.ts` re-exporting them
+pub fn export_types(out_dir: &Path) -> Result<(), ExportError> {
+    Todo::export_all_to(out_dir)?;
+    CreateTodo::export_all_to(out_dir)?;
+    UpdateTodo::export_all_to(out_dir)?;
+    TodoQuery::export_all_to(out_dir)?;
+    BulkTagRequest::export_all_to(out_dir)?;
+    BulkResult::export_all_to(out_dir)?;
+    TagUsage::export_all_to(out_dir)?;
+    TagStyle::export_all_to(out_dir)?;
+    SetTagStyle::export_all_to(out_dir)?;
+    Aggregate::export_all_to(out_dir)?;
+    Streaks::export_all_to(out_dir)?;
+    RenderedTodo::export_all_to(out_dir)?;
+    TodoDetail::export_all_to(out_dir)?;
+    CreateRelation::export_all_to(out_dir)?;
+    Relation::export_all_to(out_dir)?;
+    NearbyTodo::export_all_to(out_dir)?;
+    Workload::export_all_to(out_dir)?;
+    WeeklyReview::export_all_to(out_dir)?;
+    Calendar::export_all_to(out_dir)?;
+    CalendarDay::export_all_to(out_dir)?;
+    QuotaWarning::export_all_to(out_dir)?;
+    ApiUsage::export_all_to(out_dir)?;
+    WorkloadDay::export_all_to(out_dir)?;
+    ImportReport::export_all_to(out_dir)?;
+    Backup::export_all_to(out_dir)?;
+    RestoreReport::export_all_to(out_dir)?;
+    RetentionStatus::export_all_to(out_dir)?;
+    VersionInfo::export_all_to(out_dir)?;
+    ShadowStatus::export_all_to(out_dir)?;
+    ShadowDiff::export_all_to(out_dir)?;
+    TriggerItem::export_all_to(out_dir)?;
+    SubscribeHook::export_all_to(out_dir)?;
+    HookSubscription::export_all_to(out_dir)?;
+    CustomField::export_all_to(out_dir)?;
+    CreateCustomField::export_all_to(out_dir)?;
+    Rule::export_all_to(out_dir)?;
+    CreateRule::export_all_to(out_dir)?;
+    UpdateRule::export_all_to(out_dir)?;
+    RuleRun::export_all_to(out_dir)?;
+    Page::<Todo>::export_all_to(out_dir)?;
+    CountMode::export_all_to(out_dir)?;
+    OnConflict::export_all_to(out_dir)?;
+    LockTodo::export_all_to(out_dir)?;
+    UnlockTodo::export_all_to(out_dir)?;
+    TodoLock::export_all_to(out_dir)?;
+    ErrorResponse::export_all_to(out_dir)?;
+
+    let mut names: Vec<String> = fs::read_dir(out_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let stem = name.strip_suffix(".ts")?;
+            (stem != "index").then(|| stem.to_string())
+        })
+        .collect();
+    names.sort();
+
+    let mut index = String::new();
+    for name in &names {
+        index.push_str(&format!("export type {{ {name} }} from \"./{name}\";\n"));
+    }
+    // Handlers return `TodoResponse`, which is an alias of the model
+    index.push_str("export type { Todo as TodoResponse } from \"./Todo\";\n");
+    fs::write(out_dir.join("index.ts"), index)?;
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use crate::error::HttpError;
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+/// Static files of the demo UI, compiled into the binary
+#[derive(RustEmbed)]
+#[folder = "ui/"]
+struct Assets;
+
+/// Serve the UI entry page
+pub async fn index() -> Response {
+    serve("index.html")
+}
+
+/// Serve a UI asset (script, stylesheet, ...)
+pub async fn asset(Path(path): Path<String>) -> Response {
+    serve(&path)
+}
+
+fn serve(path: &str) -> Response {
+    match Assets::get(path) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype().to_string())],
+            file.data,
+        )
+            .into_response(),
+        None => HttpError::new("Asset not found", StatusCode::NOT_FOUND).into_response(),
+    }
+}
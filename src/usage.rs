@@ -0,0 +1,159 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use ts_rs::TS;
+
+/// Length of the rolling usage window in minutes
+pub const WINDOW_MINUTES: u64 = 60;
+
+/// Number of tracked clients after which idle ones are swept
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Rate limit standing of a client, as also sent in the X-RateLimit-* headers
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct RateLimitQuota {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the current window resets
+    pub reset: u64,
+}
+
+/// Requests made by one client over the last `window_secs`
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct ApiUsage {
+    /// Client IP address, the identity rate limits are applied to
+    pub client: String,
+    pub window_secs: u64,
+    pub requests: u64,
+    /// Responses with a 4xx or 5xx status
+    pub errors: u64,
+    /// `errors / requests`, 0 when there were no requests
+    pub error_rate: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub rate_limit: Option<RateLimitQuota>,
+}
+
+/// Counts for one minute of a client's traffic
+struct Bucket {
+    minute: u64,
+    requests: u64,
+    errors: u64,
+}
+
+/// In-memory per-client request and error counts over a rolling window,
+/// kept in one-minute buckets
+#[derive(Default)]
+pub struct UsageTracker {
+    clients: Mutex<HashMap<IpAddr, VecDeque<Bucket>>>,
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, client: IpAddr, error: bool) {
+        let minute = current_minute();
+        let mut clients = self.clients.lock().unwrap();
+
+        if clients.len() >= SWEEP_THRESHOLD {
+            clients.retain(|_, buckets| {
+                buckets
+                    .back()
+                    .is_some_and(|bucket| bucket.minute + WINDOW_MINUTES > minute)
+            });
+        }
+
+        let buckets = clients.entry(client).or_default();
+        match buckets.back_mut() {
+            Some(bucket) if bucket.minute == minute => {
+                bucket.requests += 1;
+                bucket.errors += u64::from(error);
+            }
+            _ => buckets.push_back(Bucket {
+                minute,
+                requests: 1,
+                errors: u64::from(error),
+            }),
+        }
+        while buckets
+            .front()
+            .is_some_and(|bucket| bucket.minute + WINDOW_MINUTES <= minute)
+        {
+            buckets.pop_front();
+        }
+    }
+
+    /// Usage of a single client (zero when it made no requests)
+    pub fn usage(&self, client: IpAddr) -> ApiUsage {
+        let minute = current_minute();
+        let clients = self.clients.lock().unwrap();
+        summarize(client, clients.get(&client), minute)
+    }
+
+    /// Usage of every client active in the window, busiest first
+    pub fn all(&self) -> Vec<ApiUsage> {
+        let minute = current_minute();
+        let clients = self.clients.lock().unwrap();
+        let mut usage: Vec<ApiUsage> = clients
+            .iter()
+            .map(|(client, buckets)| summarize(*client, Some(buckets), minute))
+            .filter(|usage| usage.requests > 0)
+            .collect();
+        usage.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.client.cmp(&b.client)));
+        usage
+    }
+}
+
+fn summarize(client: IpAddr, buckets: Option<&VecDeque<Bucket>>, minute: u64) -> ApiUsage {
+    let (requests, errors) = buckets
+        .into_iter()
+        .flatten()
+        .filter(|bucket| bucket.minute + WINDOW_MINUTES > minute)
+        .fold((0, 0), |(requests, errors), bucket| {
+            (requests + bucket.requests, errors + bucket.errors)
+        });
+    ApiUsage {
+        client: client.to_string(),
+        window_secs: WINDOW_MINUTES * 60,
+        requests,
+        errors,
+        error_rate: if requests == 0 {
+            0.0
+        } else {
+            errors as f64 / requests as f64
+        },
+        rate_limit: None,
+    }
+}
+
+fn current_minute() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 60)
+        .unwrap_or_default()
+}
+
+/// Middleware counting each client's requests and error responses
+pub async fn track_usage(
+    State(tracker): State<Arc<UsageTracker>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    tracker.record(
+        addr.ip(),
+        status.is_client_error() || status.is_server_error(),
+    );
+    response
+}
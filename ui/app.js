@@ -0,0 +1,107 @@
+const state = { page: 1, completed: "" };
+
+const el = (id) => document.getElementById(id);
+
+async function api(method, path, body) {
+  const response = await fetch(path, {
+    method,
+    headers: body ? { "Content-Type": "application/json" } : {},
+    body: body ? JSON.stringify(body) : undefined,
+  });
+  if (!response.ok) {
+    const error = await response.json().catch(() => ({ message: response.statusText }));
+    throw new Error(error.message);
+  }
+  return response.status === 204 ? null : response.json();
+}
+
+function showError(error) {
+  el("error").textContent = error ? error.message : "";
+  el("error").hidden = !error;
+}
+
+function render(todo) {
+  const item = document.createElement("li");
+  item.className = todo.completed ? "completed" : "";
+
+  const complete = document.createElement("input");
+  complete.type = "checkbox";
+  complete.checked = todo.completed;
+  complete.disabled = todo.completed;
+  complete.title = "Mark completed";
+  complete.onchange = () => run(() => api("PATCH", `/todos/${todo.id}/complete`));
+
+  const title = document.createElement("span");
+  title.className = "title";
+  title.textContent = todo.title;
+
+  const remove = document.createElement("button");
+  remove.textContent = "Delete";
+  remove.onclick = () => run(() => api("DELETE", `/todos/${todo.id}`));
+
+  item.append(complete, title);
+  for (const tag of todo.tags) {
+    const chip = document.createElement("span");
+    chip.className = "tag";
+    chip.textContent = tag;
+    item.append(chip);
+  }
+  item.append(remove);
+  return item;
+}
+
+async function load() {
+  const params = new URLSearchParams({ page: state.page });
+  if (state.completed) params.set("completed", state.completed);
+
+  const { data, meta } = await api("GET", `/todos?${params}`);
+  el("todos").replaceChildren(...data.map(render));
+  el("page-info").textContent = `Page ${meta.page} of ${Math.max(meta.total_pages, 1)} (${meta.total} todos)`;
+  el("prev").disabled = meta.page <= 1;
+  el("next").disabled = !meta.has_more;
+}
+
+async function run(action) {
+  try {
+    await action();
+    showError(null);
+  } catch (error) {
+    showError(error);
+  }
+  try {
+    await load();
+  } catch (error) {
+    showError(error);
+  }
+}
+
+el("create").onsubmit = (event) => {
+  event.preventDefault();
+  const tags = el("tags").value.split(",").map((tag) => tag.trim()).filter(Boolean);
+  run(async () => {
+    await api("POST", "/todos", { title: el("title").value, tags });
+    el("create").reset();
+  });
+};
+
+for (const button of document.querySelectorAll("#filters button")) {
+  button.onclick = () => {
+    document.querySelector("#filters .active").classList.remove("active");
+    button.classList.add("active");
+    state.completed = button.dataset.completed;
+    state.page = 1;
+    run(() => Promise.resolve());
+  };
+}
+
+el("prev").onclick = () => {
+  state.page -= 1;
+  run(() => Promise.resolve());
+};
+
+el("next").onclick = () => {
+  state.page += 1;
+  run(() => Promise.resolve());
+};
+
+run(() => Promise.resolve());